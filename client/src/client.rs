@@ -1,11 +1,24 @@
 use std::net::TcpStream;
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 
-use crate::Session;
+use libs::{BaseModels, PacketModels};
+use uuid::Uuid;
+
+use crate::{
+    keystore::{KnownServers, SavedTokens},
+    session::SessionError,
+    tls, Event, Session,
+};
+
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 pub struct Client {
     session: Option<Session>,
+    server: Option<(String, String)>,
+    token: Option<PacketModels::SessionToken>,
+    events: Option<mpsc::Receiver<Event>>,
     tx: mpsc::Sender<ClientMessage>,
     pub channel_handler: Option<thread::JoinHandle<()>>,
 }
@@ -21,6 +34,9 @@ impl Client {
 
         let client = Arc::new(Mutex::new(Client {
             session: None,
+            server: None,
+            token: None,
+            events: None,
             tx,
             channel_handler: None,
         }));
@@ -36,15 +52,373 @@ impl Client {
     }
 
     fn connect(&mut self, addr: String, port: String) {
-        let stream = TcpStream::connect(format!("{addr}:{port}", addr = addr, port = port));
+        self.session = None;
+        self.events = None;
+        self.server = Some((addr, port));
+
+        let mut session = match self.open_session() {
+            Ok(session) => session,
+            Err(err) => return self.tx.send(ClientMessage::Err(err.message)).unwrap(),
+        };
+
+        let resumed = self.resume(&mut session);
+        self.session = Some(session);
+
+        match resumed {
+            true => {
+                self.listen();
+                self.tx.send(ClientMessage::LoginSuccess).unwrap();
+                self.get_chats();
+            }
+            false => self.tx.send(ClientMessage::ConnectedToServer).unwrap(),
+        }
+    }
 
-        match stream {
-            Ok(stream) => {
-                self.session = Some(Session::new(stream));
-                self.tx.send(ClientMessage::ConnectedToServer).unwrap();
+    /// Connects to the current server. Its static key is trusted on first
+    /// use, like its certificate, and must match on every later connection.
+    fn open_session(&self) -> Result<Session, SessionError> {
+        let (addr, port) = match &self.server {
+            Some(server) => server,
+            None => {
+                return Err(SessionError {
+                    message: String::from("Server Not Selected"),
+                })
             }
-            Err(err) => self.tx.send(ClientMessage::Err(err.to_string())).unwrap(),
+        };
+
+        let stream = match TcpStream::connect(format!("{}:{}", addr, port)) {
+            Ok(stream) => stream,
+            Err(err) => {
+                return Err(SessionError {
+                    message: err.to_string(),
+                })
+            }
+        };
+
+        let stream = tls::connect(addr, port, stream)?;
+
+        let server = format!("{}:{}", addr, port);
+        let mut known = KnownServers::open("known_keys.json")?;
+        let pin = known.get(&server);
+
+        let session = Session::new(stream, pin.as_deref())?;
+
+        if pin.is_none() {
+            known.pin(server, String::from(session.server_key()))?;
         }
+
+        Ok(session)
+    }
+
+    /// Logs `session` in with the token saved for the current server, and
+    /// forgets the token if the server no longer accepts it.
+    fn resume(&mut self, session: &mut Session) -> bool {
+        let server = match &self.server {
+            Some((addr, port)) => format!("{}:{}", addr, port),
+            None => return false,
+        };
+
+        let mut saved = match SavedTokens::open() {
+            Ok(saved) => saved,
+            Err(err) => {
+                self.tx.send(ClientMessage::Err(err.message)).unwrap();
+                return false;
+            }
+        };
+
+        let token = match self.token.take().or_else(|| saved.get(&server)) {
+            Some(token) => token,
+            None => return false,
+        };
+
+        match session.resume(token.clone()) {
+            Ok(()) => {
+                self.token = Some(token);
+                true
+            }
+            Err(_) => {
+                let _ = saved.set(server, None);
+                false
+            }
+        }
+    }
+
+    /// Replaces a dropped connection without bothering the user, as long as
+    /// the saved token still works.
+    fn reconnect(&mut self) -> bool {
+        if self.token.is_none() {
+            return false;
+        }
+
+        let mut session = match self.open_session() {
+            Ok(session) => session,
+            Err(_) => return false,
+        };
+
+        if !self.resume(&mut session) {
+            return false;
+        }
+
+        self.session = Some(session);
+        self.listen();
+        self.get_chats();
+
+        true
+    }
+
+    fn save_token(&mut self, token: Option<PacketModels::SessionToken>) {
+        self.token = token;
+
+        let server = match &self.server {
+            Some((addr, port)) => format!("{}:{}", addr, port),
+            None => return,
+        };
+
+        if let Err(err) =
+            SavedTokens::open().and_then(|mut saved| saved.set(server, self.token.clone()))
+        {
+            self.tx.send(ClientMessage::Err(err.message)).unwrap();
+        }
+    }
+
+    fn login(&mut self, user: String, pass: String) {
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => {
+                return self
+                    .tx
+                    .send(ClientMessage::Err(String::from("Session Not Created")))
+                    .unwrap()
+            }
+        };
+
+        match session.login(user, pass) {
+            Ok(_) => {
+                let token = session.token();
+                self.save_token(token);
+                self.listen();
+                self.tx.send(ClientMessage::LoginSuccess).unwrap();
+                self.get_chats();
+            }
+            Err(err) => self.tx.send(ClientMessage::Err(err.message)).unwrap(),
+        }
+    }
+
+    fn signup(&mut self, name: String, user: String, pass: String) {
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => {
+                return self
+                    .tx
+                    .send(ClientMessage::Err(String::from("Session Not Created")))
+                    .unwrap()
+            }
+        };
+
+        match session.signup(name, user, pass) {
+            Ok(_) => {
+                let token = session.token();
+                self.save_token(token);
+                self.listen();
+                self.tx.send(ClientMessage::LoginSuccess).unwrap();
+                self.get_chats();
+            }
+            Err(err) => self.tx.send(ClientMessage::Err(err.message)).unwrap(),
+        }
+    }
+
+    fn get_chats(&mut self) {
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => {
+                return self
+                    .tx
+                    .send(ClientMessage::Err(String::from("Session Not Created")))
+                    .unwrap()
+            }
+        };
+
+        match session.get_chats() {
+            Ok(chats) => self.tx.send(ClientMessage::Chats(chats)).unwrap(),
+            Err(err) => self.tx.send(ClientMessage::Err(err.message)).unwrap(),
+        }
+    }
+
+    fn create_group(&mut self, name: String) {
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => {
+                return self
+                    .tx
+                    .send(ClientMessage::Err(String::from("Session Not Created")))
+                    .unwrap()
+            }
+        };
+
+        match session.create_group(name) {
+            Ok(_) => self.get_chats(),
+            Err(err) => self.tx.send(ClientMessage::Err(err.message)).unwrap(),
+        }
+    }
+
+    fn search_users(&mut self, query: String) {
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => {
+                return self
+                    .tx
+                    .send(ClientMessage::Err(String::from("Session Not Created")))
+                    .unwrap()
+            }
+        };
+
+        match session.search_users(query) {
+            Ok(users) => self.tx.send(ClientMessage::Users(users)).unwrap(),
+            Err(err) => self.tx.send(ClientMessage::Err(err.message)).unwrap(),
+        }
+    }
+
+    fn add_member(&mut self, group: Uuid, username: String) {
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => {
+                return self
+                    .tx
+                    .send(ClientMessage::Err(String::from("Session Not Created")))
+                    .unwrap()
+            }
+        };
+
+        match session.add_member(group, username) {
+            Ok(group) => {
+                self.tx.send(ClientMessage::MemberAdded(group)).unwrap();
+                self.get_chats();
+            }
+            Err(err) => self.tx.send(ClientMessage::Err(err.message)).unwrap(),
+        }
+    }
+
+    fn leave_group(&mut self, group: Uuid) {
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => {
+                return self
+                    .tx
+                    .send(ClientMessage::Err(String::from("Session Not Created")))
+                    .unwrap()
+            }
+        };
+
+        match session.leave_group(group) {
+            Ok(_) => self.get_chats(),
+            Err(err) => self.tx.send(ClientMessage::Err(err.message)).unwrap(),
+        }
+    }
+
+    fn open_chat(&mut self, group: Uuid) {
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => {
+                return self
+                    .tx
+                    .send(ClientMessage::Err(String::from("Session Not Created")))
+                    .unwrap()
+            }
+        };
+
+        match session.open_conversation(group) {
+            Ok(messages) => self.tx.send(ClientMessage::Conversation(messages)).unwrap(),
+            Err(err) => self.tx.send(ClientMessage::Err(err.message)).unwrap(),
+        }
+    }
+
+    fn load_older(&mut self, group: Uuid) {
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => {
+                return self
+                    .tx
+                    .send(ClientMessage::Err(String::from("Session Not Created")))
+                    .unwrap()
+            }
+        };
+
+        match session.load_older(group) {
+            Ok(Some(messages)) => self.tx.send(ClientMessage::Messages(messages)).unwrap(),
+            Ok(None) => {}
+            Err(err) => self.tx.send(ClientMessage::Err(err.message)).unwrap(),
+        }
+    }
+
+    fn send_message(&mut self, group: Uuid, body: String) {
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => {
+                return self
+                    .tx
+                    .send(ClientMessage::Err(String::from("Session Not Created")))
+                    .unwrap()
+            }
+        };
+
+        match session.send_message(group, &body) {
+            Ok(message) => self.tx.send(ClientMessage::NewMessage(message)).unwrap(),
+            Err(err) => self.tx.send(ClientMessage::Err(err.message)).unwrap(),
+        }
+    }
+
+    fn listen(&mut self) {
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => return,
+        };
+
+        let (events, rx) = mpsc::channel();
+
+        match session.listen(events) {
+            Ok(()) => self.events = Some(rx),
+            Err(err) => self.tx.send(ClientMessage::Err(err.message)).unwrap(),
+        }
+    }
+
+    fn poll_events(&mut self) {
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => return,
+        };
+
+        if let Err(err) = session.poll() {
+            self.session = None;
+            self.events = None;
+
+            if self.reconnect() {
+                return;
+            }
+
+            return self.tx.send(ClientMessage::Err(err.message)).unwrap();
+        }
+
+        let events: Vec<Event> = match &self.events {
+            Some(events) => events.try_iter().collect(),
+            None => return,
+        };
+
+        if events.is_empty() {
+            return;
+        }
+
+        for event in events {
+            match event {
+                Event::Message(message) => match session.receive_message(*message) {
+                    Ok(Some(message)) => self.tx.send(ClientMessage::NewMessage(message)).unwrap(),
+                    Ok(None) => {}
+                    Err(err) => self.tx.send(ClientMessage::Err(err.message)).unwrap(),
+                },
+                event => self.tx.send(ClientMessage::Event(event)).unwrap(),
+            }
+        }
+
+        self.get_chats();
     }
 
     fn manage_channel(
@@ -53,7 +427,14 @@ impl Client {
         rx: Arc<Mutex<mpsc::Receiver<ClientMessage>>>,
     ) {
         loop {
-            let message = rx.lock().unwrap().recv().unwrap();
+            let message = match rx.lock().unwrap().recv_timeout(EVENT_POLL_INTERVAL) {
+                Ok(message) => message,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    client.lock().unwrap().poll_events();
+                    continue;
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            };
 
             match message {
                 ClientMessage::Terminate => {
@@ -63,25 +444,35 @@ impl Client {
                 ClientMessage::ConnectToServer(addr, port) => {
                     client.lock().unwrap().connect(addr, port);
                 }
-                ClientMessage::Login(user, pass) => match &mut client.lock().unwrap().session {
-                    Some(session) => match session.login(user, pass) {
-                        Ok(_) => tx.send(ClientMessage::LoginSuccess).unwrap(),
-                        Err(err) => tx.send(ClientMessage::Err(err.message)).unwrap(),
-                    },
-                    None => tx
-                        .send(ClientMessage::Err(String::from("Session Not Created")))
-                        .unwrap(),
-                },
+                ClientMessage::Login(user, pass) => {
+                    client.lock().unwrap().login(user, pass);
+                }
                 ClientMessage::Signup(name, user, pass) => {
-                    match &mut client.lock().unwrap().session {
-                        Some(session) => match session.signup(name, user, pass) {
-                            Ok(_) => tx.send(ClientMessage::LoginSuccess).unwrap(),
-                            Err(err) => tx.send(ClientMessage::Err(err.message)).unwrap(),
-                        },
-                        None => tx
-                            .send(ClientMessage::Err(String::from("Session Not Created")))
-                            .unwrap(),
-                    }
+                    client.lock().unwrap().signup(name, user, pass);
+                }
+                ClientMessage::GetChats => {
+                    client.lock().unwrap().get_chats();
+                }
+                ClientMessage::OpenChat(group) => {
+                    client.lock().unwrap().open_chat(group);
+                }
+                ClientMessage::CreateGroup(name) => {
+                    client.lock().unwrap().create_group(name);
+                }
+                ClientMessage::SearchUsers(query) => {
+                    client.lock().unwrap().search_users(query);
+                }
+                ClientMessage::AddMember(group, username) => {
+                    client.lock().unwrap().add_member(group, username);
+                }
+                ClientMessage::LeaveGroup(group) => {
+                    client.lock().unwrap().leave_group(group);
+                }
+                ClientMessage::LoadOlder(group) => {
+                    client.lock().unwrap().load_older(group);
+                }
+                ClientMessage::SendMessage(group, body) => {
+                    client.lock().unwrap().send_message(group, body);
                 }
                 _ => {}
             }
@@ -97,4 +488,19 @@ pub enum ClientMessage {
     Err(String),
     ConnectedToServer,
     LoginSuccess,
+    GetChats,
+    Chats(PacketModels::Chats),
+    CreateGroup(String),
+    SearchUsers(String),
+    Users(Vec<BaseModels::User>),
+    AddMember(Uuid, String),
+    MemberAdded(BaseModels::Group),
+    LeaveGroup(Uuid),
+    OpenChat(Uuid),
+    Conversation(PacketModels::Messages),
+    LoadOlder(Uuid),
+    Messages(PacketModels::Messages),
+    SendMessage(Uuid, String),
+    NewMessage(BaseModels::Message),
+    Event(Event),
 }
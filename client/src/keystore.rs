@@ -0,0 +1,339 @@
+use std::{
+    collections::HashMap,
+    env,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+use libs::{
+    crypto::{ratchet::Ratchet, sender_key::GroupSession, x3dh::LocalKeys},
+    PacketModels,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::session::SessionError;
+
+const ONE_TIME_PREKEYS: usize = 100;
+
+#[derive(Deserialize)]
+struct StoredKeys {
+    keys: LocalKeys,
+    #[serde(default)]
+    ratchets: HashMap<String, Ratchet>,
+    #[serde(default)]
+    groups: HashMap<Uuid, GroupSession>,
+    #[serde(default)]
+    history: HashMap<Uuid, HashMap<Uuid, String>>,
+}
+
+#[derive(Serialize)]
+struct StoredKeysRef<'a> {
+    keys: &'a LocalKeys,
+    ratchets: &'a HashMap<String, Ratchet>,
+    groups: &'a HashMap<Uuid, GroupSession>,
+    history: &'a HashMap<Uuid, HashMap<Uuid, String>>,
+}
+
+pub struct KeyStore {
+    path: PathBuf,
+    keys: LocalKeys,
+    ratchets: HashMap<String, Ratchet>,
+    groups: HashMap<Uuid, GroupSession>,
+    history: HashMap<Uuid, HashMap<Uuid, String>>,
+}
+
+impl KeyStore {
+    pub fn open(username: &str) -> Result<Self, SessionError> {
+        // the username becomes a file name, so it must not reach outside
+        // the keystore directory
+        let valid = !username.is_empty()
+            && !username.starts_with('.')
+            && username
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+
+        if !valid {
+            return Err(SessionError {
+                message: format!("Invalid Username {:?}", username),
+            });
+        }
+
+        let dir = data_dir().join("keystores");
+
+        if let Err(err) = fs::create_dir_all(&dir) {
+            return Err(SessionError {
+                message: err.to_string(),
+            });
+        }
+
+        let path = dir.join(format!("{}.json", username));
+
+        // keystores used to sit directly in the data directory, next to
+        // files like sessions.json, so only move one that parses as a keystore
+        let legacy = data_dir().join(format!("{}.json", username));
+        let is_keystore = |buf: String| serde_json::from_str::<StoredKeys>(&buf).is_ok();
+
+        if !path.exists() && fs::read_to_string(&legacy).is_ok_and(is_keystore) {
+            if let Err(err) = fs::rename(&legacy, &path) {
+                return Err(SessionError {
+                    message: err.to_string(),
+                });
+            }
+        }
+
+        let stored = match fs::read_to_string(&path) {
+            Ok(buf) => match serde_json::from_str(&buf) {
+                Ok(stored) => stored,
+                Err(err) => {
+                    return Err(SessionError {
+                        message: err.to_string(),
+                    })
+                }
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => StoredKeys {
+                keys: LocalKeys::generate(ONE_TIME_PREKEYS as u32),
+                ratchets: HashMap::new(),
+                groups: HashMap::new(),
+                history: HashMap::new(),
+            },
+            // anything else must not overwrite the identity on disk
+            Err(err) => {
+                return Err(SessionError {
+                    message: err.to_string(),
+                })
+            }
+        };
+
+        let mut store = Self {
+            path,
+            keys: stored.keys,
+            ratchets: stored.ratchets,
+            groups: stored.groups,
+            history: stored.history,
+        };
+
+        store.keys.refill(ONE_TIME_PREKEYS);
+        store.save()?;
+
+        Ok(store)
+    }
+
+    pub fn keys(&mut self) -> &mut LocalKeys {
+        &mut self.keys
+    }
+
+    pub fn ratchet(&mut self, username: &str) -> Option<&mut Ratchet> {
+        self.ratchets.get_mut(username)
+    }
+
+    pub fn set_ratchet(&mut self, username: String, ratchet: Ratchet) {
+        self.ratchets.insert(username, ratchet);
+    }
+
+    pub fn group(&mut self, group: Uuid) -> &mut GroupSession {
+        self.groups
+            .entry(group)
+            .or_insert_with(|| GroupSession::new(group, Vec::new()))
+    }
+
+    pub fn group_session(&mut self, group: Uuid) -> Option<&mut GroupSession> {
+        self.groups.get_mut(&group)
+    }
+
+    pub fn remove_group(&mut self, group: Uuid) {
+        self.groups.remove(&group);
+        self.history.remove(&group);
+    }
+
+    pub fn retain_groups(&mut self, groups: &[Uuid]) {
+        self.groups.retain(|group, _| groups.contains(group));
+        self.history.retain(|group, _| groups.contains(group));
+    }
+
+    /// Group messages can only be decrypted once, so keep what was read or sent.
+    pub fn plaintext(&self, group: Uuid, message: Uuid) -> Option<String> {
+        self.history.get(&group)?.get(&message).cloned()
+    }
+
+    pub fn remember(&mut self, group: Uuid, message: Uuid, body: String) {
+        self.history.entry(group).or_default().insert(message, body);
+    }
+
+    pub fn save(&self) -> Result<(), SessionError> {
+        let stored = StoredKeysRef {
+            keys: &self.keys,
+            ratchets: &self.ratchets,
+            groups: &self.groups,
+            history: &self.history,
+        };
+
+        let buf = match serde_json::to_string(&stored) {
+            Ok(buf) => buf,
+            Err(err) => {
+                return Err(SessionError {
+                    message: err.to_string(),
+                })
+            }
+        };
+
+        write_private(&self.path, buf.as_bytes())
+    }
+}
+
+/// Fingerprints remembered from the first connection to each server, keyed
+/// by `addr:port`. Later connections must present the same one.
+pub(crate) struct KnownServers {
+    path: PathBuf,
+    pins: HashMap<String, String>,
+}
+
+impl KnownServers {
+    pub(crate) fn open(file: &str) -> Result<Self, SessionError> {
+        let path = data_dir().join(file);
+        let pins = read_map(&path)?;
+
+        Ok(Self { path, pins })
+    }
+
+    pub(crate) fn get(&self, server: &str) -> Option<String> {
+        self.pins.get(server).cloned()
+    }
+
+    pub(crate) fn pin(&mut self, server: String, fingerprint: String) -> Result<(), SessionError> {
+        self.pins.insert(server, fingerprint);
+
+        let buf = match serde_json::to_string_pretty(&self.pins) {
+            Ok(buf) => buf,
+            Err(err) => {
+                return Err(SessionError {
+                    message: err.to_string(),
+                })
+            }
+        };
+
+        if let Err(err) = fs::create_dir_all(data_dir()) {
+            return Err(SessionError {
+                message: err.to_string(),
+            });
+        }
+
+        write_private(&self.path, buf.as_bytes())
+    }
+}
+
+/// Session tokens kept between runs, keyed by `addr:port`, so a restart or
+/// a dropped connection resumes without asking for the password again.
+pub(crate) struct SavedTokens {
+    path: PathBuf,
+    tokens: HashMap<String, PacketModels::SessionToken>,
+}
+
+impl SavedTokens {
+    pub(crate) fn open() -> Result<Self, SessionError> {
+        let path = data_dir().join("sessions.json");
+        let tokens = read_map(&path)?;
+
+        Ok(Self { path, tokens })
+    }
+
+    pub(crate) fn get(&self, server: &str) -> Option<PacketModels::SessionToken> {
+        self.tokens.get(server).cloned()
+    }
+
+    pub(crate) fn set(
+        &mut self,
+        server: String,
+        token: Option<PacketModels::SessionToken>,
+    ) -> Result<(), SessionError> {
+        match token {
+            Some(token) => self.tokens.insert(server, token),
+            None => self.tokens.remove(&server),
+        };
+
+        let buf = match serde_json::to_string(&self.tokens) {
+            Ok(buf) => buf,
+            Err(err) => {
+                return Err(SessionError {
+                    message: err.to_string(),
+                })
+            }
+        };
+
+        if let Err(err) = fs::create_dir_all(data_dir()) {
+            return Err(SessionError {
+                message: err.to_string(),
+            });
+        }
+
+        write_private(&self.path, buf.as_bytes())
+    }
+}
+
+/// Replaces `path` with `buf` in one step, readable only by its owner. The
+/// data goes to a fresh temporary file that is renamed over the old one, so
+/// a crash never leaves half a key file behind.
+pub(crate) fn write_private(path: &Path, buf: &[u8]) -> Result<(), SessionError> {
+    let tmp = path.with_extension("tmp");
+    let _ = fs::remove_file(&tmp);
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let result = options
+        .open(&tmp)
+        .and_then(|mut file| file.write_all(buf).and_then(|_| file.sync_all()))
+        .and_then(|_| fs::rename(&tmp, path));
+
+    if let Err(err) = result {
+        let _ = fs::remove_file(&tmp);
+
+        return Err(SessionError {
+            message: err.to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Reads a map saved by [`write_private`]. Only a missing file counts as
+/// empty; a file that can't be read or parsed is an error, since treating
+/// it as empty would silently drop pins.
+fn read_map<T>(path: &Path) -> Result<HashMap<String, T>, SessionError>
+where
+    T: for<'a> Deserialize<'a>,
+{
+    let buf = match fs::read_to_string(path) {
+        Ok(buf) => buf,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(err) => {
+            return Err(SessionError {
+                message: format!("{}: {}", path.display(), err),
+            })
+        }
+    };
+
+    match serde_json::from_str(&buf) {
+        Ok(map) => Ok(map),
+        Err(err) => Err(SessionError {
+            message: format!("{}: {}", path.display(), err),
+        }),
+    }
+}
+
+pub(crate) fn data_dir() -> PathBuf {
+    if let Ok(dir) = env::var("SECURE_CHAT_HOME") {
+        return PathBuf::from(dir);
+    }
+
+    match env::var("HOME") {
+        Ok(home) => PathBuf::from(home).join(".secure_chat"),
+        Err(_) => PathBuf::from(".secure_chat"),
+    }
+}
@@ -2,6 +2,10 @@ mod page;
 pub use page::{Page, PageEvent, PageMessage};
 
 mod pages;
+pub use pages::chats_page::{ChatsPage, ChatsPageEvent};
+pub use pages::conversation_page::{ConversationPage, ConversationPageEvent};
+pub use pages::create_group_dialog::{CreateGroupDialog, CreateGroupDialogEvent};
+pub use pages::invite_dialog::{InviteDialog, InviteDialogEvent};
 pub use pages::login_page::{LoginPage, LoginPageEvent};
 pub use pages::main_page::{MainPage, MainPageEvent};
 pub use pages::signup_page::{SignupPage, SignupPageEvent};
@@ -9,13 +13,18 @@ pub use pages::signup_page::{SignupPage, SignupPageEvent};
 mod client;
 pub use client::{Client, ClientMessage};
 
+mod keystore;
+
 mod session;
-pub use session::Session;
+pub use session::{Event, Session};
+
+mod tls;
 
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
 
 use cursive::views::{Dialog, TextView};
+use libs::BaseModels;
 
 pub struct Manager {
     client: Arc<Mutex<Client>>,
@@ -101,6 +110,66 @@ impl Manager {
                         }))
                         .unwrap();
                 }
+                ClientMessage::LoginSuccess => {
+                    let chats_tx = tx_page.clone();
+                    cb_sink
+                        .send(Box::new(|s| {
+                            let chats_page = Self::render_chats_page(chats_tx);
+
+                            s.pop_layer();
+                            s.add_layer(chats_page.body());
+                        }))
+                        .unwrap();
+                }
+                ClientMessage::Chats(chats) => {
+                    cb_sink
+                        .send(Box::new(move |s| ChatsPage::update(s, &chats)))
+                        .unwrap();
+                }
+                ClientMessage::Users(users) => {
+                    cb_sink
+                        .send(Box::new(move |s| InviteDialog::show_results(s, &users)))
+                        .unwrap();
+                }
+                ClientMessage::MemberAdded(_) => {
+                    cb_sink.send(Box::new(InviteDialog::close)).unwrap();
+                }
+                ClientMessage::Conversation(messages) => {
+                    let conversation_tx = tx_page.clone();
+                    cb_sink
+                        .send(Box::new(move |s| {
+                            let conversation_page = Self::render_conversation_page(
+                                conversation_tx,
+                                messages.get_group().clone(),
+                            );
+
+                            s.pop_layer();
+                            s.add_layer(conversation_page.body());
+
+                            ConversationPage::show(s, &messages.get());
+                        }))
+                        .unwrap();
+                }
+                ClientMessage::Messages(messages) => {
+                    cb_sink
+                        .send(Box::new(move |s| {
+                            if ConversationPage::is_open(s, messages.get_group().get_id()) {
+                                ConversationPage::prepend(s, &messages.get());
+                            }
+                        }))
+                        .unwrap();
+                }
+                ClientMessage::NewMessage(message) => {
+                    cb_sink
+                        .send(Box::new(move |s| {
+                            let group = message.get_member().get_group().get_id();
+
+                            if ConversationPage::is_open(s, group) {
+                                ConversationPage::append(s, &message);
+                            }
+                        }))
+                        .unwrap();
+                }
                 _ => {}
             }
         }
@@ -115,9 +184,8 @@ impl Manager {
         loop {
             let message = rx_page.lock().unwrap().recv().unwrap();
 
-            match message.downcast_ref::<PageEvent>() {
-                Some(PageEvent::Terminate) => break,
-                _ => {}
+            if let Some(PageEvent::Terminate) = message.downcast_ref::<PageEvent>() {
+                break;
             }
 
             match message.downcast_ref::<MainPageEvent>() {
@@ -184,6 +252,96 @@ impl Manager {
                 }
                 _ => {}
             }
+
+            match message.downcast_ref::<ChatsPageEvent>() {
+                Some(ChatsPageEvent::Quit) => {
+                    break;
+                }
+                Some(ChatsPageEvent::Refresh) => {
+                    tx_client.send(ClientMessage::GetChats).unwrap();
+                }
+                Some(ChatsPageEvent::OpenChat(group)) => {
+                    tx_client.send(ClientMessage::OpenChat(*group)).unwrap();
+                }
+                Some(ChatsPageEvent::NewGroup) => {
+                    let dialog_tx = tx_page.clone();
+                    cb_sink
+                        .send(Box::new(|s| {
+                            s.add_layer(CreateGroupDialog::new(dialog_tx).body());
+                        }))
+                        .unwrap();
+                }
+                _ => {}
+            }
+
+            match message.downcast_ref::<ConversationPageEvent>() {
+                Some(ConversationPageEvent::Back) => {
+                    let chats_tx = tx_page.clone();
+                    cb_sink
+                        .send(Box::new(|s| {
+                            let chats_page = Self::render_chats_page(chats_tx);
+
+                            s.pop_layer();
+                            s.add_layer(chats_page.body());
+                        }))
+                        .unwrap();
+
+                    tx_client.send(ClientMessage::GetChats).unwrap();
+                }
+                Some(ConversationPageEvent::Invite(group)) => {
+                    let dialog_tx = tx_page.clone();
+                    let group = *group;
+                    cb_sink
+                        .send(Box::new(move |s| {
+                            s.add_layer(InviteDialog::new(dialog_tx).with_group(group).body());
+                        }))
+                        .unwrap();
+                }
+                Some(ConversationPageEvent::Leave(group)) => {
+                    let chats_tx = tx_page.clone();
+                    cb_sink
+                        .send(Box::new(|s| {
+                            let chats_page = Self::render_chats_page(chats_tx);
+
+                            s.pop_layer();
+                            s.add_layer(chats_page.body());
+                        }))
+                        .unwrap();
+
+                    tx_client.send(ClientMessage::LeaveGroup(*group)).unwrap();
+                }
+                Some(ConversationPageEvent::LoadOlder(group)) => {
+                    tx_client.send(ClientMessage::LoadOlder(*group)).unwrap();
+                }
+                Some(ConversationPageEvent::Send(group, body)) => {
+                    tx_client
+                        .send(ClientMessage::SendMessage(*group, body.clone()))
+                        .unwrap();
+                }
+                _ => {}
+            }
+
+            if let Some(CreateGroupDialogEvent::Create(name)) =
+                message.downcast_ref::<CreateGroupDialogEvent>()
+            {
+                tx_client
+                    .send(ClientMessage::CreateGroup(name.clone()))
+                    .unwrap();
+            }
+
+            match message.downcast_ref::<InviteDialogEvent>() {
+                Some(InviteDialogEvent::Search(query)) => {
+                    tx_client
+                        .send(ClientMessage::SearchUsers(query.clone()))
+                        .unwrap();
+                }
+                Some(InviteDialogEvent::Invite(group, username)) => {
+                    tx_client
+                        .send(ClientMessage::AddMember(*group, username.clone()))
+                        .unwrap();
+                }
+                _ => {}
+            }
         }
     }
 
@@ -200,6 +358,17 @@ impl Manager {
         SignupPage::new(tx)
     }
 
+    fn render_chats_page(tx: mpsc::Sender<PageMessage>) -> ChatsPage {
+        ChatsPage::new(tx)
+    }
+
+    fn render_conversation_page(
+        tx: mpsc::Sender<PageMessage>,
+        group: BaseModels::Group,
+    ) -> ConversationPage {
+        ConversationPage::new(tx).with_group(group)
+    }
+
     fn render_error(message: String) -> Dialog {
         Dialog::around(TextView::new(message))
             .title("Error")
@@ -0,0 +1,90 @@
+use crate::{Page, PageMessage};
+use cursive::{
+    view::{Nameable, Resizable, Scrollable},
+    views::{Dialog, SelectView},
+    Cursive,
+};
+use libs::PacketModels;
+use uuid::Uuid;
+
+use std::sync::mpsc;
+
+pub struct ChatsPage {
+    tx: mpsc::Sender<PageMessage>,
+}
+
+impl ChatsPage {
+    pub fn update(s: &mut Cursive, chats: &PacketModels::Chats) {
+        s.call_on_name("chats", |view: &mut SelectView<Uuid>| {
+            let selected = view.selection().map(|id| *id);
+
+            view.clear();
+
+            for group in chats.get_groups() {
+                let label = match chats.get_unread(group.get_id()) {
+                    0 => group.get_name(),
+                    unread => format!("{} ({})", group.get_name(), unread),
+                };
+
+                view.add_item(label, group.get_id());
+            }
+
+            if let Some(index) =
+                selected.and_then(|selected| view.iter().position(|(_, id)| *id == selected))
+            {
+                view.set_selection(index);
+            }
+        });
+
+        s.call_on_name("chats_dialog", |view: &mut Dialog| {
+            view.set_title(match chats.is_new() {
+                true => "Chats (new messages)",
+                false => "Chats",
+            });
+        });
+    }
+}
+
+impl Page for ChatsPage {
+    fn body(&self) -> Box<dyn cursive::View> {
+        let o_tx = self.tx.clone();
+        let q_tx = self.tx.clone();
+        let r_tx = self.tx.clone();
+        let n_tx = self.tx.clone();
+
+        Box::new(
+            Dialog::around(
+                SelectView::<Uuid>::new()
+                    .on_submit(move |_, id| {
+                        o_tx.send(Box::new(ChatsPageEvent::OpenChat(*id))).unwrap();
+                    })
+                    .with_name("chats")
+                    .scrollable()
+                    .min_size((40, 10)),
+            )
+            .title("Chats")
+            .button("Quit", move |s| {
+                q_tx.send(Box::new(ChatsPageEvent::Quit)).unwrap();
+                s.quit();
+            })
+            .button("Refresh", move |_| {
+                r_tx.send(Box::new(ChatsPageEvent::Refresh)).unwrap();
+            })
+            .button("New Group", move |_| {
+                n_tx.send(Box::new(ChatsPageEvent::NewGroup)).unwrap();
+            })
+            .with_name("chats_dialog"),
+        )
+    }
+
+    fn new(tx: mpsc::Sender<PageMessage>) -> Self {
+        Self { tx }
+    }
+}
+
+pub enum ChatsPageEvent {
+    OpenChat(Uuid),
+    Refresh,
+    NewGroup,
+    Quit,
+}
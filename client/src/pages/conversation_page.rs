@@ -0,0 +1,192 @@
+use crate::{Page, PageMessage};
+use cursive::{
+    traits::With,
+    view::{Nameable, Resizable, ScrollStrategy, Scrollable},
+    views::{Dialog, LinearLayout, NamedView, ScrollView, TextArea, TextView},
+    Cursive, Vec2, View,
+};
+use libs::BaseModels;
+use uuid::Uuid;
+
+use std::sync::mpsc;
+
+type MessagesView = ScrollView<NamedView<LinearLayout>>;
+
+pub struct ConversationPage {
+    tx: mpsc::Sender<PageMessage>,
+    group: Option<BaseModels::Group>,
+}
+
+impl ConversationPage {
+    pub fn with_group(self, group: BaseModels::Group) -> Self {
+        Self {
+            group: Some(group),
+            ..self
+        }
+    }
+
+    pub fn show(s: &mut Cursive, messages: &[BaseModels::Message]) {
+        s.call_on_name("messages", |view: &mut LinearLayout| {
+            for message in messages {
+                view.add_child(Self::render_message(message));
+            }
+        });
+
+        s.call_on_name("messages_scroll", |view: &mut MessagesView| {
+            view.set_scroll_strategy(ScrollStrategy::StickToBottom);
+        });
+    }
+
+    pub fn prepend(s: &mut Cursive, messages: &[BaseModels::Message]) {
+        let width = s
+            .call_on_name("messages_scroll", |view: &mut MessagesView| {
+                view.content_viewport().width()
+            })
+            .unwrap_or(0);
+
+        let mut height = 0;
+
+        s.call_on_name("messages", |view: &mut LinearLayout| {
+            for (index, message) in messages.iter().enumerate() {
+                // long bodies wrap, so measure the rows each one really takes
+                let mut text = Self::render_message(message);
+                height += text.required_size(Vec2::new(width, usize::MAX)).y;
+
+                view.insert_child(index, text);
+            }
+        });
+
+        s.call_on_name("messages_scroll", |view: &mut MessagesView| {
+            let offset = view.content_viewport().top() + height;
+            view.set_offset((0, offset));
+        });
+    }
+
+    pub fn append(s: &mut Cursive, message: &BaseModels::Message) {
+        let at_bottom = s
+            .call_on_name("messages_scroll", |view: &mut MessagesView| {
+                view.is_at_bottom()
+            })
+            .unwrap_or(false);
+
+        s.call_on_name("messages", |view: &mut LinearLayout| {
+            view.add_child(Self::render_message(message));
+        });
+
+        if at_bottom {
+            s.call_on_name("messages_scroll", |view: &mut MessagesView| {
+                view.set_scroll_strategy(ScrollStrategy::StickToBottom);
+            });
+        }
+    }
+
+    pub fn is_open(s: &mut Cursive, group: Uuid) -> bool {
+        s.call_on_name(&Self::dialog_name(group), |_: &mut Dialog| ())
+            .is_some()
+    }
+
+    fn render_message(message: &BaseModels::Message) -> TextView {
+        let created_at = message.get_created_at();
+
+        TextView::new(format!(
+            "[{:04}-{:02}-{:02} {:02}:{:02}] {}\n{}\n",
+            created_at.year(),
+            u8::from(created_at.month()),
+            created_at.day(),
+            created_at.hour(),
+            created_at.minute(),
+            message.get_member().get_user().get_username(),
+            message.get_body(),
+        ))
+    }
+
+    fn dialog_name(group: Uuid) -> String {
+        format!("conversation:{}", group)
+    }
+}
+
+impl Page for ConversationPage {
+    fn body(&self) -> Box<dyn cursive::View> {
+        let (id, name) = match &self.group {
+            Some(group) => (group.get_id(), group.get_name()),
+            None => (Uuid::nil(), String::new()),
+        };
+
+        let l_tx = self.tx.clone();
+        let s_tx = self.tx.clone();
+        let b_tx = self.tx.clone();
+        let i_tx = self.tx.clone();
+        let x_tx = self.tx.clone();
+
+        let messages = LinearLayout::vertical()
+            .with_name("messages")
+            .scrollable()
+            .with(|view| {
+                // one request per visit to the top: a loaded page moves the
+                // view down, and an exhausted history leaves it there
+                let mut loading = false;
+
+                view.set_on_scroll_change_inner(move |view, _| {
+                    if !view.is_at_top() {
+                        loading = false;
+                    } else if !loading {
+                        loading = true;
+                        l_tx.send(Box::new(ConversationPageEvent::LoadOlder(id)))
+                            .unwrap();
+                    }
+
+                    cursive::event::EventResult::Ignored
+                })
+            })
+            .with_name("messages_scroll")
+            .min_size((60, 15));
+
+        Box::new(
+            Dialog::around(
+                LinearLayout::vertical()
+                    .child(messages)
+                    .child(TextView::new("Message"))
+                    .child(TextArea::new().with_name("composer").min_height(3)),
+            )
+            .title(name)
+            .button("Back", move |_| {
+                b_tx.send(Box::new(ConversationPageEvent::Back)).unwrap();
+            })
+            .button("Invite", move |_| {
+                i_tx.send(Box::new(ConversationPageEvent::Invite(id)))
+                    .unwrap();
+            })
+            .button("Leave", move |_| {
+                x_tx.send(Box::new(ConversationPageEvent::Leave(id)))
+                    .unwrap();
+            })
+            .button("Send", move |s| {
+                let body = s
+                    .call_on_name("composer", |view: &mut TextArea| {
+                        let body = String::from(view.get_content());
+                        view.set_content("");
+                        body
+                    })
+                    .unwrap();
+
+                if !body.trim().is_empty() {
+                    s_tx.send(Box::new(ConversationPageEvent::Send(id, body)))
+                        .unwrap();
+                }
+            })
+            .with_name(Self::dialog_name(id)),
+        )
+    }
+
+    fn new(tx: mpsc::Sender<PageMessage>) -> Self {
+        Self { tx, group: None }
+    }
+}
+
+pub enum ConversationPageEvent {
+    LoadOlder(Uuid),
+    Send(Uuid, String),
+    Invite(Uuid),
+    Leave(Uuid),
+    Back,
+}
@@ -0,0 +1,53 @@
+use crate::{Page, PageMessage};
+use cursive::{
+    view::{Nameable, Resizable},
+    views::{Dialog, EditView, LinearLayout, TextView},
+};
+
+use std::sync::mpsc;
+
+pub struct CreateGroupDialog {
+    tx: mpsc::Sender<PageMessage>,
+}
+
+impl Page for CreateGroupDialog {
+    fn body(&self) -> Box<dyn cursive::View> {
+        let c_tx = self.tx.clone();
+
+        Box::new(
+            Dialog::around(
+                LinearLayout::vertical()
+                    .child(TextView::new("Group Name"))
+                    .child(EditView::new().with_name("group_name").min_width(30)),
+            )
+            .title("New Group")
+            .button("Cancel", |s| {
+                s.pop_layer();
+            })
+            .button("Create", move |s| {
+                let name = s
+                    .call_on_name("group_name", |view: &mut EditView| view.get_content())
+                    .unwrap();
+
+                if name.trim().is_empty() {
+                    return;
+                }
+
+                c_tx.send(Box::new(CreateGroupDialogEvent::Create(String::from(
+                    name.trim(),
+                ))))
+                .unwrap();
+
+                s.pop_layer();
+            }),
+        )
+    }
+
+    fn new(tx: mpsc::Sender<PageMessage>) -> Self {
+        Self { tx }
+    }
+}
+
+pub enum CreateGroupDialogEvent {
+    Create(String),
+}
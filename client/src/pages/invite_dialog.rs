@@ -0,0 +1,114 @@
+use crate::{Page, PageMessage};
+use cursive::{
+    view::{Nameable, Resizable, Scrollable},
+    views::{Dialog, EditView, LinearLayout, SelectView, TextView},
+    Cursive,
+};
+use libs::BaseModels;
+use uuid::Uuid;
+
+use std::sync::mpsc;
+
+pub struct InviteDialog {
+    tx: mpsc::Sender<PageMessage>,
+    group: Uuid,
+}
+
+impl InviteDialog {
+    pub fn with_group(self, group: Uuid) -> Self {
+        Self { group, ..self }
+    }
+
+    pub fn show_results(s: &mut Cursive, users: &[BaseModels::User]) {
+        s.call_on_name("invite_results", |view: &mut SelectView<String>| {
+            view.clear();
+
+            for user in users {
+                let label = match user.get_name().is_empty() {
+                    true => user.get_username(),
+                    false => format!("{} ({})", user.get_username(), user.get_name()),
+                };
+
+                view.add_item(label, user.get_username());
+            }
+        });
+    }
+
+    pub fn close(s: &mut Cursive) {
+        if s.find_name::<Dialog>("invite_dialog").is_some() {
+            s.pop_layer();
+        }
+    }
+}
+
+impl Page for InviteDialog {
+    fn body(&self) -> Box<dyn cursive::View> {
+        let group = self.group;
+
+        let s_tx = self.tx.clone();
+        let b_tx = self.tx.clone();
+        let i_tx = self.tx.clone();
+
+        Box::new(
+            Dialog::around(
+                LinearLayout::vertical()
+                    .child(TextView::new("Username"))
+                    .child(
+                        EditView::new()
+                            .on_submit(move |_, query| {
+                                if !query.trim().is_empty() {
+                                    s_tx.send(Box::new(InviteDialogEvent::Search(String::from(
+                                        query.trim(),
+                                    ))))
+                                    .unwrap();
+                                }
+                            })
+                            .with_name("invite_query")
+                            .min_width(30),
+                    )
+                    .child(
+                        SelectView::<String>::new()
+                            .on_submit(move |_, username: &String| {
+                                i_tx.send(Box::new(InviteDialogEvent::Invite(
+                                    group,
+                                    username.clone(),
+                                )))
+                                .unwrap();
+                            })
+                            .with_name("invite_results")
+                            .scrollable()
+                            .min_height(5),
+                    ),
+            )
+            .title("Invite Member")
+            .button("Close", |s| {
+                s.pop_layer();
+            })
+            .button("Search", move |s| {
+                let query = s
+                    .call_on_name("invite_query", |view: &mut EditView| view.get_content())
+                    .unwrap();
+
+                if !query.trim().is_empty() {
+                    b_tx.send(Box::new(InviteDialogEvent::Search(String::from(
+                        query.trim(),
+                    ))))
+                    .unwrap();
+                }
+            })
+            .with_name("invite_dialog"),
+        )
+    }
+
+    fn new(tx: mpsc::Sender<PageMessage>) -> Self {
+        Self {
+            tx,
+            group: Uuid::nil(),
+        }
+    }
+}
+
+pub enum InviteDialogEvent {
+    Search(String),
+    Invite(Uuid, String),
+}
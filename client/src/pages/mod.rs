@@ -1,3 +1,7 @@
+pub mod chats_page;
+pub mod conversation_page;
+pub mod create_group_dialog;
+pub mod invite_dialog;
 pub mod login_page;
 pub mod main_page;
 pub mod signup_page;
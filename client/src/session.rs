@@ -1,49 +1,62 @@
 use libs::{
-    packet::{DataPacket, Packet, PacketType},
-    packet_manager, BaseModels, PacketModels,
+    crypto::{
+        ratchet::Ratchet,
+        sender_key::SenderKeyDistribution,
+        srp::{self, SrpClient},
+        transport::ClientHandshake,
+    },
+    packet::{Codec, DataPacket, Packet, PacketError, PacketType},
+    packet_manager::{self, Channel, Stream},
+    BaseModels, PacketModels,
 };
-use std::{fmt, net::TcpStream};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    env, fmt,
+    sync::mpsc::{self, TryRecvError},
+    thread,
+};
+use uuid::Uuid;
+
+use crate::keystore::KeyStore;
 
 pub struct Session {
     me: Option<BaseModels::User>,
     groups: Vec<BaseModels::Group>,
-    messages: Vec<BaseModels::Message>,
-    stream: TcpStream,
+    messages: HashMap<Uuid, History>,
+    channel: Channel,
+    server_key: String,
+    authenticated: bool,
+    keystore: Option<KeyStore>,
+    token: Option<PacketModels::SessionToken>,
+    inbound: mpsc::Receiver<Result<DataPacket, PacketError>>,
+    events: Option<mpsc::Sender<Event>>,
+    stale_members: bool,
+    next_request_id: u64,
 }
 
 impl Session {
-    pub fn new(stream: TcpStream) -> Self {
-        Self {
-            me: None,
-            groups: Vec::new(),
-            messages: Vec::new(),
-            stream,
-        }
-    }
+    pub fn new(stream: Stream, expected_server_key: Option<&str>) -> Result<Self, SessionError> {
+        let mut channel = Channel::new(stream).with_codec(codec()?);
 
-    pub fn login(&mut self, user: String, pass: String) -> Result<(), SessionError> {
-        let body = BaseModels::User::simple(user, pass);
-
-        let packet = Packet::new(PacketType::Login, body);
+        let (handshake, hello) = ClientHandshake::start();
 
-        let data_packet = match packet.to() {
+        let data_packet = match Packet::new(PacketType::PubKey, hello).encode(channel.get_codec()) {
             Ok(data) => data,
             Err(err) => {
                 return Err(SessionError {
-                    message: err.to_string(),
+                    message: err.message,
                 })
             }
         };
 
-        let res = packet_manager::send_packet(&mut self.stream, data_packet);
-
-        if let Err(err) = res {
+        if let Err(err) = channel.send(data_packet) {
             return Err(SessionError {
                 message: err.to_string(),
             });
         }
 
-        let data_packet = match packet_manager::recv_packet(&mut self.stream) {
+        let data_packet = match channel.recv() {
             Ok(packet) => packet,
             Err(err) => {
                 return Err(SessionError {
@@ -58,89 +71,1116 @@ impl Session {
             });
         }
 
-        let packet: Packet<BaseModels::User> =
+        if matches!(data_packet.get_type(), PacketType::Shutdown) {
+            return Err(Self::shutdown_error(&data_packet));
+        }
+
+        let reply: Packet<PacketModels::KeyExchange> =
             match Packet::parse(&data_packet, "Wrong Packet Received") {
                 Ok(packet) => packet,
                 Err(data_packet) => {
-                    match packet_manager::send_packet(&mut self.stream, data_packet) {
-                        Ok(_) => {
-                            return Err(SessionError {
-                                message: String::from("Wrong Packet Received"),
-                            })
-                        }
-                        Err(err) => {
-                            return Err(SessionError {
-                                message: err.to_string(),
-                            })
-                        }
-                    }
+                    return Err(SessionError {
+                        message: data_packet.get_data(),
+                    })
                 }
             };
 
-        self.me = Some(packet.get().1);
+        let reply = reply.get().1;
+        let server_key = reply.get_static_key();
 
-        Ok(())
+        match handshake.finish(reply, expected_server_key) {
+            Ok(cipher) => channel.set_cipher(cipher),
+            Err(err) => {
+                return Err(SessionError {
+                    message: err.message,
+                })
+            }
+        }
+
+        let stream = match channel.try_clone_stream() {
+            Ok(stream) => stream,
+            Err(err) => {
+                return Err(SessionError {
+                    message: err.message,
+                })
+            }
+        };
+
+        let (inbox, inbound) = mpsc::channel();
+        thread::spawn(move || Self::read_frames(stream, inbox));
+
+        Ok(Self {
+            me: None,
+            groups: Vec::new(),
+            messages: HashMap::new(),
+            channel,
+            server_key,
+            authenticated: expected_server_key.is_some(),
+            keystore: None,
+            token: None,
+            inbound,
+            events: None,
+            stale_members: false,
+            next_request_id: 1,
+        })
+    }
+
+    pub fn server_key(&self) -> &str {
+        &self.server_key
+    }
+
+    pub fn login(&mut self, user: String, pass: String) -> Result<(), SessionError> {
+        let me = match self.srp_login(&user, &pass) {
+            Ok(me) => me,
+            // the account predates SRP; offer its password once so the
+            // server can migrate it, but never to a server we can't verify
+            Err(err) if self.authenticated && err.message == "Password Migration Required" => {
+                self.migrate_password(user.clone(), pass.clone())?;
+                self.srp_login(&user, &pass)?
+            }
+            Err(err) => return Err(err),
+        };
+
+        self.logged_in(me)
     }
     pub fn signup(&mut self, name: String, user: String, pass: String) -> Result<(), SessionError> {
-        let body = BaseModels::User::full(name, user, pass);
+        self.enable_srp(
+            BaseModels::User::full(name, user.clone(), String::new()),
+            &pass,
+        )?;
 
-        let packet = Packet::new(PacketType::Register, body);
+        let me = self.srp_login(&user, &pass)?;
 
-        let data_packet = match packet.to() {
-            Ok(data) => data,
+        self.logged_in(me)
+    }
+
+    fn srp_login(&mut self, user: &str, pass: &str) -> Result<BaseModels::User, SessionError> {
+        let (client, start) = SrpClient::start(user);
+
+        let data_packet = self.request(PacketType::SrpStart, start)?;
+        let challenge: PacketModels::SrpChallenge = Self::parse(&data_packet)?;
+
+        let (proof, body) = match client.finish(pass, challenge) {
+            Ok(res) => res,
             Err(err) => {
                 return Err(SessionError {
-                    message: err.to_string(),
+                    message: err.message,
                 })
             }
         };
 
-        let res = packet_manager::send_packet(&mut self.stream, data_packet);
+        let data_packet = self.request(PacketType::SrpProof, body)?;
+        let mut verify: PacketModels::SrpVerify = Self::parse(&data_packet)?;
+
+        let key = match proof.verify(&verify.get_proof()) {
+            Ok(key) => key,
+            Err(err) => {
+                return Err(SessionError {
+                    message: err.message,
+                })
+            }
+        };
 
-        if let Err(err) = res {
+        if let Err(err) = self.channel.rekey(&key) {
             return Err(SessionError {
-                message: err.to_string(),
+                message: err.message,
             });
         }
 
-        let data_packet = match packet_manager::recv_packet(&mut self.stream) {
-            Ok(packet) => packet,
+        self.token = verify.take_token();
+
+        Ok(verify.into_user())
+    }
+
+    /// Logs back in with a saved token. Tokens only go to a server whose key
+    /// was pinned before this connection, never over a first-use exchange.
+    pub fn resume(&mut self, token: PacketModels::SessionToken) -> Result<(), SessionError> {
+        if !self.authenticated {
+            return Err(SessionError {
+                message: String::from("Server Key Not Pinned"),
+            });
+        }
+
+        let data_packet = self.request(PacketType::Resume, token.clone())?;
+
+        let me = Self::parse(&data_packet)?;
+        self.token = Some(token);
+
+        self.logged_in(me)
+    }
+
+    pub fn token(&self) -> Option<PacketModels::SessionToken> {
+        self.token.clone()
+    }
+
+    fn migrate_password(&mut self, user: String, pass: String) -> Result<(), SessionError> {
+        self.request(PacketType::Login, BaseModels::User::simple(user, pass))?;
+
+        Ok(())
+    }
+
+    fn enable_srp(&mut self, user: BaseModels::User, pass: &str) -> Result<(), SessionError> {
+        let (salt, verifier) = match srp::register(&user.get_username(), pass) {
+            Ok(res) => res,
             Err(err) => {
                 return Err(SessionError {
-                    message: err.to_string(),
+                    message: err.message,
                 })
             }
         };
 
-        if matches!(data_packet.get_type(), PacketType::Error) {
-            return Err(SessionError {
-                message: data_packet.get_data(),
-            });
+        self.request(
+            PacketType::SrpRegister,
+            PacketModels::SrpRegistration::new(user, salt, verifier),
+        )?;
+
+        Ok(())
+    }
+
+    fn logged_in(&mut self, me: BaseModels::User) -> Result<(), SessionError> {
+        self.keystore = Some(KeyStore::open(&me.get_username())?);
+        self.me = Some(me);
+
+        self.publish_keys()
+    }
+
+    fn publish_keys(&mut self) -> Result<(), SessionError> {
+        let keystore = self.keystore()?;
+
+        if !keystore.keys().has_unpublished() {
+            return Ok(());
         }
 
-        let packet: Packet<BaseModels::User> =
-            match Packet::parse(&data_packet, "Wrong Packet Received") {
-                Ok(packet) => packet,
-                Err(data_packet) => {
-                    match packet_manager::send_packet(&mut self.stream, data_packet) {
-                        Ok(_) => {
-                            return Err(SessionError {
-                                message: String::from("Wrong Packet Received"),
-                            })
-                        }
-                        Err(err) => {
-                            return Err(SessionError {
-                                message: err.to_string(),
-                            })
-                        }
+        let bundle = match keystore.keys().bundle() {
+            Ok(bundle) => bundle,
+            Err(err) => {
+                return Err(SessionError {
+                    message: err.message,
+                })
+            }
+        };
+
+        self.request(PacketType::PublishKeys, bundle)?;
+
+        let keystore = self.keystore()?;
+        keystore.keys().mark_published();
+        keystore.save()
+    }
+
+    pub fn start_e2e(&mut self, username: String, message: String) -> Result<(), SessionError> {
+        let data_packet = self.request(
+            PacketType::GetKeyBundle,
+            BaseModels::User::simple(username.clone(), String::new()),
+        )?;
+
+        let bundle: PacketModels::PreKeyBundle = Self::parse(&data_packet)?;
+
+        let recipient = BaseModels::User::simple(username.clone(), String::new());
+
+        let (ratchet, init) =
+            match self
+                .keystore()?
+                .keys()
+                .initiate(recipient, bundle, message.as_bytes())
+            {
+                Ok(res) => res,
+                Err(err) => {
+                    return Err(SessionError {
+                        message: err.message,
+                    })
+                }
+            };
+
+        self.request(PacketType::E2E, init)?;
+
+        let keystore = self.keystore()?;
+        keystore.set_ratchet(username, ratchet);
+        keystore.save()
+    }
+
+    pub fn fetch_e2e(&mut self) -> Result<Vec<(String, String)>, SessionError> {
+        let data_packet = self.request(PacketType::GetE2E, PacketModels::Empty {})?;
+
+        let inbox: PacketModels::E2EInbox = Self::parse(&data_packet)?;
+
+        let keystore = self.keystore()?;
+
+        let mut messages = Vec::new();
+        for init in inbox.get() {
+            let (ratchet, plaintext) = match keystore.keys().accept(&init) {
+                Ok(res) => res,
+                Err(_) => continue,
+            };
+
+            let sender = init.get_user().get_username();
+            keystore.set_ratchet(sender.clone(), ratchet);
+
+            messages.push((sender, String::from_utf8_lossy(&plaintext).into_owned()));
+        }
+
+        keystore.save()?;
+
+        Ok(messages)
+    }
+
+    pub fn encrypt_for(&mut self, username: &str, message: &str) -> Result<String, SessionError> {
+        let keystore = self.keystore()?;
+
+        let ratchet = match keystore.ratchet(username) {
+            Some(ratchet) => ratchet,
+            None => {
+                return Err(SessionError {
+                    message: String::from("No E2E Session"),
+                })
+            }
+        };
+
+        let body = match ratchet.encrypt(message.as_bytes()) {
+            Ok(body) => body,
+            Err(err) => {
+                return Err(SessionError {
+                    message: err.message,
+                })
+            }
+        };
+
+        keystore.save()?;
+
+        Ok(body)
+    }
+
+    pub fn decrypt_from(&mut self, username: &str, body: &str) -> Result<String, SessionError> {
+        let keystore = self.keystore()?;
+
+        let ratchet = match keystore.ratchet(username) {
+            Some(ratchet) => ratchet,
+            None => {
+                return Err(SessionError {
+                    message: String::from("No E2E Session"),
+                })
+            }
+        };
+
+        let message = match ratchet.decrypt(body) {
+            Ok(message) => message,
+            Err(err) => {
+                return Err(SessionError {
+                    message: err.message,
+                })
+            }
+        };
+
+        keystore.save()?;
+
+        Ok(String::from_utf8_lossy(&message).into_owned())
+    }
+
+    pub fn create_group(&mut self, name: String) -> Result<BaseModels::Group, SessionError> {
+        let data_packet = self.request(PacketType::CreateGroup, BaseModels::Group::new(name))?;
+
+        let group: BaseModels::Group = Self::parse(&data_packet)?;
+
+        self.set_group_members(group.get_id(), group.get_members())?;
+        self.store_group(group.clone());
+
+        Ok(group)
+    }
+
+    pub fn add_member(
+        &mut self,
+        group: Uuid,
+        username: String,
+    ) -> Result<BaseModels::Group, SessionError> {
+        let group = match self.groups.iter().find(|stored| stored.get_id() == group) {
+            Some(stored) => stored.clone(),
+            None => {
+                return Err(SessionError {
+                    message: String::from("Group Not Found"),
+                })
+            }
+        };
+
+        let member =
+            BaseModels::Member::new(group, BaseModels::User::simple(username, String::new()));
+
+        let data_packet = self.request(PacketType::AddUser, member)?;
+
+        let group: BaseModels::Group = Self::parse(&data_packet)?;
+
+        self.set_group_members(group.get_id(), group.get_members())?;
+        self.store_group(group.clone());
+
+        Ok(group)
+    }
+
+    pub fn remove_member(
+        &mut self,
+        group: Uuid,
+        username: String,
+    ) -> Result<BaseModels::Group, SessionError> {
+        let group = match self.groups.iter().find(|stored| stored.get_id() == group) {
+            Some(stored) => stored.clone(),
+            None => {
+                return Err(SessionError {
+                    message: String::from("Group Not Found"),
+                })
+            }
+        };
+
+        let member = BaseModels::Member::new(
+            group,
+            BaseModels::User::simple(username.clone(), String::new()),
+        );
+
+        let data_packet = self.request(PacketType::RemoveUser, member)?;
+
+        let group: BaseModels::Group = Self::parse(&data_packet)?;
+
+        let me = match &self.me {
+            Some(me) => me.get_username(),
+            None => String::new(),
+        };
+
+        if username == me {
+            self.forget_group(group.get_id())?;
+        } else {
+            self.set_group_members(group.get_id(), group.get_members())?;
+            self.store_group(group.clone());
+        }
+
+        Ok(group)
+    }
+
+    pub fn leave_group(&mut self, group: Uuid) -> Result<BaseModels::Group, SessionError> {
+        let me = match &self.me {
+            Some(me) => me.get_username(),
+            None => {
+                return Err(SessionError {
+                    message: String::from("Login Required"),
+                })
+            }
+        };
+
+        self.remove_member(group, me)
+    }
+
+    pub fn search_users(&mut self, query: String) -> Result<Vec<BaseModels::User>, SessionError> {
+        let data_packet = self.request(
+            PacketType::SearchUsers,
+            PacketModels::UserSearch::new(query),
+        )?;
+
+        let users: PacketModels::Users = Self::parse(&data_packet)?;
+
+        Ok(users.get())
+    }
+
+    pub fn send_message(
+        &mut self,
+        group: Uuid,
+        message: &str,
+    ) -> Result<BaseModels::Message, SessionError> {
+        let stored = match self.groups.iter().find(|stored| stored.get_id() == group) {
+            Some(stored) => stored.clone(),
+            None => {
+                return Err(SessionError {
+                    message: String::from("Group Not Found"),
+                })
+            }
+        };
+
+        let body = self.encrypt_group(group, message)?;
+
+        let member = BaseModels::Member::new(
+            stored,
+            BaseModels::User::simple(String::new(), String::new()),
+        );
+
+        let data_packet = self.request(
+            PacketType::CreateMessage,
+            BaseModels::Message::new(member, body),
+        )?;
+
+        let sent: BaseModels::Message = Self::parse(&data_packet)?;
+
+        let keystore = self.keystore()?;
+        keystore.remember(group, sent.get_id(), String::from(message));
+        keystore.save()?;
+
+        let sent = sent.with_body(String::from(message));
+
+        self.history(group).insert(sent.clone());
+
+        Ok(sent)
+    }
+
+    pub fn open_conversation(
+        &mut self,
+        group: Uuid,
+    ) -> Result<PacketModels::Messages, SessionError> {
+        if !self.history(group).loaded {
+            let page = self.get_messages(group, None, 0)?;
+            let next_cursor = page.get_next_cursor();
+            let messages = self.reveal_page(page)?;
+
+            let history = self.history(group);
+            history.loaded = true;
+            history.next_cursor = next_cursor;
+
+            for message in messages {
+                history.insert(message);
+            }
+        }
+
+        let history = self.history(group);
+
+        Ok(PacketModels::Messages::new(
+            history.group.clone(),
+            history.messages.clone(),
+            history.next_cursor,
+        ))
+    }
+
+    pub fn load_older(
+        &mut self,
+        group: Uuid,
+    ) -> Result<Option<PacketModels::Messages>, SessionError> {
+        let cursor = match self.history(group).next_cursor {
+            Some(cursor) => cursor,
+            None => return Ok(None),
+        };
+
+        let page = self.get_messages(group, Some(cursor), 0)?;
+        let next_cursor = page.get_next_cursor();
+        let messages = self.reveal_page(page)?;
+
+        let history = self.history(group);
+        history.next_cursor = next_cursor;
+
+        let older: Vec<BaseModels::Message> = messages
+            .into_iter()
+            .filter(|message| history.insert(message.clone()))
+            .collect();
+
+        Ok(Some(PacketModels::Messages::new(
+            history.group.clone(),
+            older,
+            next_cursor,
+        )))
+    }
+
+    pub fn receive_message(
+        &mut self,
+        message: BaseModels::Message,
+    ) -> Result<Option<BaseModels::Message>, SessionError> {
+        let group = message.get_member().get_group().get_id();
+
+        if self.history(group).contains(message.get_id()) {
+            return Ok(None);
+        }
+
+        self.fetch_sender_keys()?;
+
+        let message = self.reveal(message);
+        self.history(group).insert(message.clone());
+
+        Ok(Some(message))
+    }
+
+    fn reveal_page(
+        &mut self,
+        page: PacketModels::Messages,
+    ) -> Result<Vec<BaseModels::Message>, SessionError> {
+        let group = page.get_group().get_id();
+        self.history(group).group = page.get_group().clone();
+
+        self.fetch_sender_keys()?;
+
+        let mut messages = Vec::new();
+        for message in page.get() {
+            if !self.history(group).contains(message.get_id()) {
+                messages.push(self.reveal(message));
+            }
+        }
+
+        Ok(messages)
+    }
+
+    fn reveal(&mut self, message: BaseModels::Message) -> BaseModels::Message {
+        let group = message.get_member().get_group().get_id();
+        let sender = message.get_member().get_user().get_username();
+
+        let me = match &self.me {
+            Some(me) => me.get_username(),
+            None => String::new(),
+        };
+
+        let keystore = match self.keystore() {
+            Ok(keystore) => keystore,
+            Err(_) => return message.with_body(String::from("[unable to decrypt]")),
+        };
+
+        if let Some(body) = keystore.plaintext(group, message.get_id()) {
+            return message.with_body(body);
+        }
+
+        if sender == me {
+            return message.with_body(String::from("[sent from another session]"));
+        }
+
+        match self.decrypt_group(&message) {
+            Ok(body) => message.with_body(body),
+            Err(_) => message.with_body(String::from("[unable to decrypt]")),
+        }
+    }
+
+    fn history(&mut self, group: Uuid) -> &mut History {
+        let stored = self.groups.iter().find(|stored| stored.get_id() == group);
+
+        self.messages.entry(group).or_insert_with(|| History {
+            group: match stored {
+                Some(stored) => stored.clone(),
+                None => BaseModels::Group::new(String::new()),
+            },
+            messages: Vec::new(),
+            next_cursor: None,
+            loaded: false,
+        })
+    }
+
+    pub fn get_chats(&mut self) -> Result<PacketModels::Chats, SessionError> {
+        let data_packet = self.request(PacketType::GetChats, PacketModels::Empty {})?;
+
+        let chats: PacketModels::Chats = Self::parse(&data_packet)?;
+
+        self.stale_members = false;
+
+        let ids: Vec<Uuid> = chats
+            .get_groups()
+            .iter()
+            .map(|group| group.get_id())
+            .collect();
+
+        for id in self
+            .groups
+            .iter()
+            .map(|group| group.get_id())
+            .collect::<Vec<_>>()
+        {
+            if !ids.contains(&id) {
+                self.forget_group(id)?;
+            }
+        }
+        self.keystore()?.retain_groups(&ids);
+
+        for group in chats.get_groups() {
+            self.set_group_members(group.get_id(), group.get_members())?;
+            self.store_group(group.clone());
+        }
+
+        Ok(chats)
+    }
+
+    fn forget_group(&mut self, group: Uuid) -> Result<(), SessionError> {
+        self.groups.retain(|stored| stored.get_id() != group);
+        self.messages.remove(&group);
+
+        let keystore = self.keystore()?;
+        keystore.remove_group(group);
+        keystore.save()
+    }
+
+    pub fn get_messages(
+        &mut self,
+        group: Uuid,
+        cursor: Option<u64>,
+        limit: u32,
+    ) -> Result<PacketModels::Messages, SessionError> {
+        let data_packet = self.request(
+            PacketType::GetMessages,
+            PacketModels::GetMessages::new(group, cursor, limit),
+        )?;
+
+        Self::parse(&data_packet)
+    }
+
+    pub fn listen(&mut self, events: mpsc::Sender<Event>) -> Result<(), SessionError> {
+        self.events = Some(events);
+
+        self.request(PacketType::Listen, PacketModels::Empty {})?;
+
+        Ok(())
+    }
+
+    pub fn poll(&mut self) -> Result<(), SessionError> {
+        loop {
+            let data_packet = match self.inbound.try_recv() {
+                Ok(packet) => self.open(packet)?,
+                Err(TryRecvError::Empty) => return Ok(()),
+                Err(TryRecvError::Disconnected) => {
+                    return Err(SessionError {
+                        message: String::from("Connection Closed"),
+                    })
+                }
+            };
+
+            if data_packet.is_event() {
+                self.route_event(data_packet)?;
+            }
+        }
+    }
+
+    fn read_frames(mut stream: Stream, inbox: mpsc::Sender<Result<DataPacket, PacketError>>) {
+        loop {
+            let frame = packet_manager::recv_packet(&mut stream);
+            let closed = frame.is_err();
+
+            if inbox.send(frame).is_err() || closed {
+                break;
+            }
+        }
+    }
+
+    fn open(&mut self, frame: Result<DataPacket, PacketError>) -> Result<DataPacket, SessionError> {
+        let result = match frame {
+            Ok(packet) => self.channel.open(packet),
+            Err(err) => Err(err),
+        };
+
+        match result {
+            Ok(packet) => Ok(packet),
+            Err(err) => Err(SessionError {
+                message: err.to_string(),
+            }),
+        }
+    }
+
+    fn route_event(&mut self, data_packet: DataPacket) -> Result<(), SessionError> {
+        let event = match data_packet.get_type() {
+            PacketType::CreateMessage => Self::parse(&data_packet).map(Event::Message),
+            PacketType::Refresh => {
+                Self::parse::<PacketModels::Refresh>(&data_packet).map(|refresh| {
+                    if refresh.is_group() {
+                        self.stale_members = true;
+                    }
+
+                    Event::Refresh(refresh)
+                })
+            }
+            PacketType::Shutdown => return Err(Self::shutdown_error(&data_packet)),
+            _ => return Ok(()),
+        };
+
+        if let (Ok(event), Some(events)) = (event, &self.events) {
+            if events.send(event).is_err() {
+                self.events = None;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn shutdown_error(data_packet: &DataPacket) -> SessionError {
+        let message = match Self::parse::<PacketModels::Shutdown>(data_packet) {
+            Ok(notice) => format!("Server Shutting Down: {}", notice.get_reason()),
+            Err(_) => String::from("Server Shutting Down"),
+        };
+
+        SessionError { message }
+    }
+
+    fn store_group(&mut self, group: BaseModels::Group) {
+        match self
+            .groups
+            .iter_mut()
+            .find(|stored| stored.get_id() == group.get_id())
+        {
+            Some(stored) => *stored = group,
+            None => self.groups.push(group),
+        }
+    }
+
+    pub fn set_group_members(
+        &mut self,
+        group: Uuid,
+        members: Vec<String>,
+    ) -> Result<(), SessionError> {
+        let me = match &self.me {
+            Some(me) => me.get_username(),
+            None => {
+                return Err(SessionError {
+                    message: String::from("Login Required"),
+                })
+            }
+        };
+
+        let members = members.into_iter().filter(|user| *user != me).collect();
+
+        let keystore = self.keystore()?;
+        keystore.group(group).set_members(members);
+        keystore.save()?;
+
+        self.distribute_sender_key(group)
+    }
+
+    pub fn fetch_sender_keys(&mut self) -> Result<Vec<Uuid>, SessionError> {
+        if self.stale_members {
+            self.get_chats()?;
+        }
+
+        let data_packet = self.request(PacketType::GetSenderKeys, PacketModels::Empty {})?;
+
+        let inbox: PacketModels::SenderKeyInbox = Self::parse(&data_packet)?;
+
+        let keystore = self.keystore()?;
+
+        let mut groups = Vec::new();
+        let mut strangers = Vec::new();
+        for sender_key in inbox.get() {
+            let sender = sender_key.get_user().get_username();
+
+            let plaintext = match (sender_key.get_session(), sender_key.get_ciphertext()) {
+                (Some(init), _) => match keystore.keys().accept(init) {
+                    Ok((ratchet, plaintext)) => {
+                        keystore.set_ratchet(sender.clone(), ratchet);
+                        plaintext
                     }
+                    Err(_) => continue,
+                },
+                (None, Some(ciphertext)) => match keystore.ratchet(&sender) {
+                    Some(ratchet) => match ratchet.decrypt(&ciphertext) {
+                        Ok(plaintext) => plaintext,
+                        Err(_) => continue,
+                    },
+                    None => continue,
+                },
+                (None, None) => continue,
+            };
+
+            let distribution = match SenderKeyDistribution::decode(&plaintext) {
+                Ok(distribution) if distribution.get_group() == sender_key.get_group() => {
+                    distribution
+                }
+                _ => continue,
+            };
+
+            let group = sender_key.get_group();
+
+            let session = match keystore.group_session(group) {
+                Some(session) if session.members().contains(&sender) => session,
+                _ => {
+                    strangers.push((sender, distribution));
+                    continue;
                 }
             };
 
-        self.me = Some(packet.get().1);
+            if session.accept(&sender, distribution).is_ok() && !groups.contains(&group) {
+                groups.push(group);
+            }
+        }
+
+        keystore.save()?;
+
+        // a key can arrive before the refresh announcing its sender joined
+        if !strangers.is_empty() {
+            self.get_chats()?;
+
+            let keystore = self.keystore()?;
+
+            for (sender, distribution) in strangers {
+                let group = distribution.get_group();
+
+                let accepted = match keystore.group_session(group) {
+                    Some(session) => session.accept(&sender, distribution).is_ok(),
+                    None => false,
+                };
+
+                if accepted && !groups.contains(&group) {
+                    groups.push(group);
+                }
+            }
+
+            keystore.save()?;
+        }
+
+        for group in &groups {
+            self.distribute_sender_key(*group)?;
+        }
+
+        Ok(groups)
+    }
+
+    pub fn encrypt_group(&mut self, group: Uuid, message: &str) -> Result<String, SessionError> {
+        if self.stale_members {
+            self.get_chats()?;
+        }
+
+        self.distribute_sender_key(group)?;
+
+        let keystore = self.keystore()?;
+
+        let session = match keystore.group_session(group) {
+            Some(session) => session,
+            None => {
+                return Err(SessionError {
+                    message: String::from("No Group Session"),
+                })
+            }
+        };
+
+        let body = match session.encrypt(message.as_bytes()) {
+            Ok(body) => body,
+            Err(err) => {
+                return Err(SessionError {
+                    message: err.message,
+                })
+            }
+        };
+
+        keystore.save()?;
+
+        Ok(body)
+    }
+
+    pub fn decrypt_group(&mut self, message: &BaseModels::Message) -> Result<String, SessionError> {
+        let group = message.get_member().get_group().get_id();
+        let sender = message.get_member().get_user().get_username();
+
+        let keystore = self.keystore()?;
+
+        let session = match keystore.group_session(group) {
+            Some(session) => session,
+            None => {
+                return Err(SessionError {
+                    message: String::from("No Group Session"),
+                })
+            }
+        };
+
+        let body = match session.decrypt(&sender, &message.get_body()) {
+            Ok(body) => String::from_utf8_lossy(&body).into_owned(),
+            Err(err) => {
+                return Err(SessionError {
+                    message: err.message,
+                })
+            }
+        };
+
+        keystore.remember(group, message.get_id(), body.clone());
+        keystore.save()?;
+
+        Ok(body)
+    }
+
+    fn distribute_sender_key(&mut self, group: Uuid) -> Result<(), SessionError> {
+        let session = match self.keystore()?.group_session(group) {
+            Some(session) => session,
+            None => return Ok(()),
+        };
+
+        let pending = session.pending();
+
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let distribution = match session.distribution().and_then(|d| d.encode()) {
+            Ok(distribution) => distribution,
+            Err(err) => {
+                return Err(SessionError {
+                    message: err.message,
+                })
+            }
+        };
+
+        for username in pending {
+            let (sender_key, ratchet) = self.seal_sender_key(group, &username, &distribution)?;
+
+            self.request(PacketType::SenderKey, sender_key)?;
+
+            let keystore = self.keystore()?;
+
+            if let Some(ratchet) = ratchet {
+                keystore.set_ratchet(username.clone(), ratchet);
+            }
+
+            keystore.group(group).mark_distributed(&username);
+            keystore.save()?;
+        }
 
         Ok(())
     }
+
+    fn seal_sender_key(
+        &mut self,
+        group: Uuid,
+        username: &str,
+        distribution: &[u8],
+    ) -> Result<(PacketModels::SenderKey, Option<Ratchet>), SessionError> {
+        let recipient = BaseModels::User::simple(String::from(username), String::new());
+
+        if let Some(ratchet) = self.keystore()?.ratchet(username) {
+            let ciphertext = match ratchet.encrypt(distribution) {
+                Ok(ciphertext) => ciphertext,
+                Err(err) => {
+                    return Err(SessionError {
+                        message: err.message,
+                    })
+                }
+            };
+
+            return Ok((
+                PacketModels::SenderKey::new(group, recipient, None, Some(ciphertext)),
+                None,
+            ));
+        }
+
+        let data_packet = self.request(
+            PacketType::GetKeyBundle,
+            BaseModels::User::simple(String::from(username), String::new()),
+        )?;
+
+        let bundle: PacketModels::PreKeyBundle = Self::parse(&data_packet)?;
+
+        let user = BaseModels::User::simple(String::from(username), String::new());
+
+        let (ratchet, init) = match self.keystore()?.keys().initiate(user, bundle, distribution) {
+            Ok(res) => res,
+            Err(err) => {
+                return Err(SessionError {
+                    message: err.message,
+                })
+            }
+        };
+
+        Ok((
+            PacketModels::SenderKey::new(group, recipient, Some(init), None),
+            Some(ratchet),
+        ))
+    }
+
+    fn keystore(&mut self) -> Result<&mut KeyStore, SessionError> {
+        match &mut self.keystore {
+            Some(keystore) => Ok(keystore),
+            None => Err(SessionError {
+                message: String::from("Login Required"),
+            }),
+        }
+    }
+
+    fn request<T>(&mut self, p_type: PacketType, body: T) -> Result<DataPacket, SessionError>
+    where
+        T: Serialize + for<'a> Deserialize<'a>,
+    {
+        let mut data_packet = match Packet::new(p_type, body).encode(self.channel.get_codec()) {
+            Ok(data) => data,
+            Err(err) => {
+                return Err(SessionError {
+                    message: err.message,
+                })
+            }
+        };
+
+        let request_id = self.next_request_id;
+        self.next_request_id += 1;
+
+        data_packet.set_request_id(request_id);
+
+        if let Err(err) = self.channel.send(data_packet) {
+            return Err(SessionError {
+                message: err.to_string(),
+            });
+        }
+
+        let data_packet = loop {
+            let frame = match self.inbound.recv() {
+                Ok(frame) => frame,
+                Err(_) => {
+                    return Err(SessionError {
+                        message: String::from("Connection Closed"),
+                    })
+                }
+            };
+
+            let data_packet = self.open(frame)?;
+
+            if data_packet.is_event() {
+                self.route_event(data_packet)?;
+            } else if data_packet.get_request_id() == request_id {
+                break data_packet;
+            }
+        };
+
+        if matches!(data_packet.get_type(), PacketType::Error) {
+            return Err(SessionError {
+                message: data_packet.get_data(),
+            });
+        }
+
+        Ok(data_packet)
+    }
+
+    fn parse<T>(data_packet: &DataPacket) -> Result<T, SessionError>
+    where
+        T: Serialize + for<'a> Deserialize<'a>,
+    {
+        match Packet::<T>::from(data_packet) {
+            Ok(packet) => Ok(packet.get().1),
+            Err(_) => Err(SessionError {
+                message: String::from("Wrong Packet Received"),
+            }),
+        }
+    }
+}
+
+/// Packets go out as JSON unless `SECURE_CHAT_CODEC` asks for `msgpack`.
+fn codec() -> Result<Codec, SessionError> {
+    match env::var("SECURE_CHAT_CODEC") {
+        Ok(name) => match Codec::parse(&name) {
+            Ok(codec) => Ok(codec),
+            Err(message) => Err(SessionError {
+                message: format!("SECURE_CHAT_CODEC: {}", message),
+            }),
+        },
+        Err(_) => Ok(Codec::default()),
+    }
+}
+
+struct History {
+    group: BaseModels::Group,
+    messages: Vec<BaseModels::Message>,
+    next_cursor: Option<u64>,
+    loaded: bool,
+}
+
+impl History {
+    fn contains(&self, id: Uuid) -> bool {
+        self.messages.iter().any(|message| message.get_id() == id)
+    }
+
+    fn insert(&mut self, message: BaseModels::Message) -> bool {
+        if self.contains(message.get_id()) {
+            return false;
+        }
+
+        let index = self
+            .messages
+            .partition_point(|stored| stored.get_created_at() <= message.get_created_at());
+        self.messages.insert(index, message);
+
+        true
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.channel.shutdown();
+    }
+}
+
+pub enum Event {
+    Message(Box<BaseModels::Message>),
+    Refresh(PacketModels::Refresh),
 }
 
 pub struct SessionError {
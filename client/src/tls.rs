@@ -0,0 +1,66 @@
+use std::{env, net::TcpStream, path::PathBuf};
+
+use libs::{
+    crypto::tls::{self, ServerTrust},
+    packet_manager::Stream,
+};
+
+use crate::keystore::KnownServers;
+use crate::session::SessionError;
+
+/// Wraps `stream` in TLS when `SECURE_CHAT_TLS` is set. Servers are trusted
+/// on first use unless `SECURE_CHAT_TLS_CA` names a certificate authority.
+pub fn connect(addr: &str, port: &str, stream: TcpStream) -> Result<Stream, SessionError> {
+    let enabled = match env::var("SECURE_CHAT_TLS") {
+        Ok(value) => matches!(value.to_ascii_lowercase().as_str(), "1" | "true" | "yes"),
+        Err(_) => false,
+    };
+
+    if !enabled {
+        return Ok(Stream::from(stream));
+    }
+
+    if let Ok(ca) = env::var("SECURE_CHAT_TLS_CA") {
+        let trust = match ServerTrust::authority(&PathBuf::from(ca)) {
+            Ok(trust) => trust,
+            Err(err) => {
+                return Err(SessionError {
+                    message: err.message,
+                })
+            }
+        };
+
+        return match tls::connect(trust, addr, stream) {
+            Ok(stream) => Ok(Stream::from(stream)),
+            Err(err) => Err(SessionError {
+                message: err.message,
+            }),
+        };
+    }
+
+    let server = format!("{}:{}", addr, port);
+    let mut known = KnownServers::open("known_servers.json")?;
+
+    let trust = match known.get(&server) {
+        Some(pin) => ServerTrust::Pinned(pin),
+        None => ServerTrust::FirstUse,
+    };
+    let first_use = matches!(trust, ServerTrust::FirstUse);
+
+    let stream = match tls::connect(trust, addr, stream) {
+        Ok(stream) => stream,
+        Err(err) => {
+            return Err(SessionError {
+                message: err.message,
+            })
+        }
+    };
+
+    if first_use {
+        if let Some(fingerprint) = stream.peer_fingerprint() {
+            known.pin(server, fingerprint)?;
+        }
+    }
+
+    Ok(Stream::from(stream))
+}
@@ -0,0 +1,132 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use libs::{
+    crypto::transport::{ClientHandshake, ServerIdentity, TransportCipher},
+    packet::{Codec, Packet, PacketType},
+    packet_manager, BaseModels, PacketModels,
+};
+
+const CODECS: [(&str, Codec); 2] = [("json", Codec::Json), ("msgpack", Codec::MessagePack)];
+
+/// A full page of history, the largest body a client routinely receives.
+fn page() -> PacketModels::Messages {
+    let group = BaseModels::Group::new(String::from("benchmarks"));
+    let member = BaseModels::Member::new(
+        group.clone(),
+        BaseModels::User::simple(String::from("alice"), String::new()),
+    );
+
+    let messages = (0..50)
+        .map(|i| {
+            BaseModels::Message::new(
+                member.clone(),
+                format!(
+                    "message {} with \"quotes\" and a {{\"nested\": true}} body",
+                    i
+                ),
+            )
+        })
+        .collect();
+
+    PacketModels::Messages::new(group, messages, Some(50))
+}
+
+fn ciphers() -> (TransportCipher, TransportCipher) {
+    let identity = ServerIdentity::generate();
+
+    let (handshake, hello) = ClientHandshake::start();
+    let (reply, server) = identity.respond(hello).unwrap();
+    (handshake.finish(reply, None).unwrap(), server)
+}
+
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+
+    for (name, codec) in CODECS {
+        let (mut client, _) = ciphers();
+
+        let packet = Packet::new(PacketType::GetMessages, page())
+            .encode(codec)
+            .unwrap();
+        let plain = codec.encode(&packet).unwrap().len();
+        let sealed = packet_manager::encrypt_packet(packet, &mut client).unwrap();
+        let sealed = codec.encode(&sealed).unwrap().len();
+
+        println!(
+            "{}: {} bytes plain, {} bytes encrypted",
+            name, plain, sealed
+        );
+        group.throughput(Throughput::Bytes(plain as u64));
+
+        group.bench_function(BenchmarkId::new("plain", name), |b| {
+            b.iter_batched(
+                page,
+                |body| {
+                    let packet = Packet::new(PacketType::GetMessages, body)
+                        .encode(codec)
+                        .unwrap();
+                    codec.encode(&packet).unwrap()
+                },
+                BatchSize::SmallInput,
+            )
+        });
+
+        group.bench_function(BenchmarkId::new("encrypted", name), |b| {
+            b.iter_batched(
+                page,
+                |body| {
+                    let packet = Packet::new(PacketType::GetMessages, body)
+                        .encode(codec)
+                        .unwrap();
+                    let packet = packet_manager::encrypt_packet(packet, &mut client).unwrap();
+                    codec.encode(&packet).unwrap()
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+
+    for (name, codec) in CODECS {
+        let packet = Packet::new(PacketType::GetMessages, page())
+            .encode(codec)
+            .unwrap();
+        let buf = codec.encode(&packet).unwrap();
+        group.throughput(Throughput::Bytes(buf.len() as u64));
+
+        group.bench_function(BenchmarkId::new("plain", name), |b| {
+            b.iter(|| {
+                let packet = codec.decode(&buf).unwrap();
+                let page: PacketModels::Messages = Packet::from(&packet).unwrap().get().1;
+                page
+            })
+        });
+
+        // every iteration needs a fresh nonce, so seal ahead of time
+        let (mut client, mut server) = ciphers();
+        group.bench_function(BenchmarkId::new("encrypted", name), |b| {
+            b.iter_batched(
+                || {
+                    let sealed = packet_manager::encrypt_packet(packet.clone(), &mut client);
+                    codec.encode(&sealed.unwrap()).unwrap()
+                },
+                |buf| {
+                    let packet = codec.decode(&buf).unwrap();
+                    let packet = packet_manager::decrypt_packet(packet, &mut server).unwrap();
+                    let page: PacketModels::Messages = Packet::from(&packet).unwrap().get().1;
+                    page
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, encode, decode);
+criterion_main!(benches);
@@ -0,0 +1,7 @@
+pub mod ratchet;
+pub mod sender_key;
+pub mod signing;
+pub mod srp;
+pub mod tls;
+pub mod transport;
+pub mod x3dh;
@@ -0,0 +1,311 @@
+use std::collections::VecDeque;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::crypto::x3dh::{aead_open, aead_seal, decode_bytes, decode_vec, expand, SharedSecret};
+use crate::packet::{PacketError, PacketErrorKind};
+
+const ROOT_INFO: &[u8] = b"secure_chat/ratchet/v1/root";
+const MAX_SKIP: u32 = 1000;
+const MAX_SKIPPED_KEYS: usize = 2000;
+
+#[derive(Serialize, Deserialize, Clone)]
+struct SkippedKey {
+    dh: String,
+    n: u32,
+    key: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    dh: String,
+    pn: u32,
+    n: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RatchetMessage {
+    header: Header,
+    ciphertext: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Ratchet {
+    dh_self: String,
+    dh_remote: Option<String>,
+    root_key: String,
+    send_chain: Option<String>,
+    recv_chain: Option<String>,
+    send_n: u32,
+    recv_n: u32,
+    prev_send_n: u32,
+    skipped: VecDeque<SkippedKey>,
+    associated_data: String,
+}
+
+impl Ratchet {
+    pub(crate) fn initiator(secret: &SharedSecret, remote: &str) -> Result<Self, PacketError> {
+        let dh_self = StaticSecret::random_from_rng(OsRng);
+        let dh_remote = PublicKey::from(decode_bytes(remote)?);
+
+        let (root_key, send_chain) =
+            kdf_root(secret.key(), dh_self.diffie_hellman(&dh_remote).as_bytes())?;
+
+        Ok(Self {
+            dh_self: STANDARD.encode(dh_self.to_bytes()),
+            dh_remote: Some(String::from(remote)),
+            root_key: STANDARD.encode(root_key),
+            send_chain: Some(STANDARD.encode(send_chain)),
+            recv_chain: None,
+            send_n: 0,
+            recv_n: 0,
+            prev_send_n: 0,
+            skipped: VecDeque::new(),
+            associated_data: STANDARD.encode(secret.associated_data()),
+        })
+    }
+
+    pub(crate) fn responder(
+        secret: &SharedSecret,
+        dh_self: &StaticSecret,
+    ) -> Result<Self, PacketError> {
+        Ok(Self {
+            dh_self: STANDARD.encode(dh_self.to_bytes()),
+            dh_remote: None,
+            root_key: STANDARD.encode(secret.key()),
+            send_chain: None,
+            recv_chain: None,
+            send_n: 0,
+            recv_n: 0,
+            prev_send_n: 0,
+            skipped: VecDeque::new(),
+            associated_data: STANDARD.encode(secret.associated_data()),
+        })
+    }
+
+    pub fn encrypt(&mut self, plaintext: &[u8]) -> Result<String, PacketError> {
+        let chain = match &self.send_chain {
+            Some(chain) => decode_bytes(chain)?,
+            None => {
+                return Err(PacketError {
+                    kind: PacketErrorKind::Crypto,
+                    message: String::from("Ratchet has no sending chain yet"),
+                })
+            }
+        };
+
+        let (chain, message_key) = kdf_chain(&chain)?;
+
+        let header = Header {
+            dh: self.public_key()?,
+            pn: self.prev_send_n,
+            n: self.send_n,
+        };
+
+        let ciphertext = aead_seal(
+            &message_key,
+            &[0; 12],
+            plaintext,
+            &self.header_aad(&header)?,
+        )?;
+
+        self.send_chain = Some(STANDARD.encode(chain));
+        self.send_n += 1;
+
+        encode_message(RatchetMessage {
+            header,
+            ciphertext: STANDARD.encode(ciphertext),
+        })
+    }
+
+    pub fn decrypt(&mut self, message: &str) -> Result<Vec<u8>, PacketError> {
+        let message = decode_message(message)?;
+
+        let mut next = self.clone();
+        let plaintext = next.step(&message)?;
+        *self = next;
+
+        Ok(plaintext)
+    }
+
+    fn step(&mut self, message: &RatchetMessage) -> Result<Vec<u8>, PacketError> {
+        let header = &message.header;
+        let ciphertext = decode_vec(&message.ciphertext)?;
+        let aad = self.header_aad(header)?;
+
+        if let Some(index) = self
+            .skipped
+            .iter()
+            .position(|key| key.dh == header.dh && key.n == header.n)
+        {
+            let key = self.skipped.remove(index).unwrap();
+            return aead_open(&decode_bytes(&key.key)?, &[0; 12], &ciphertext, &aad);
+        }
+
+        if self.dh_remote.as_deref() != Some(header.dh.as_str()) {
+            self.skip(header.pn)?;
+            self.dh_ratchet(&header.dh)?;
+        }
+
+        self.skip(header.n)?;
+
+        let chain = match &self.recv_chain {
+            Some(chain) => decode_bytes(chain)?,
+            None => {
+                return Err(PacketError {
+                    kind: PacketErrorKind::Crypto,
+                    message: String::from("Ratchet has no receiving chain"),
+                })
+            }
+        };
+
+        let (chain, message_key) = kdf_chain(&chain)?;
+
+        self.recv_chain = Some(STANDARD.encode(chain));
+        self.recv_n += 1;
+
+        aead_open(&message_key, &[0; 12], &ciphertext, &aad)
+    }
+
+    fn skip(&mut self, until: u32) -> Result<(), PacketError> {
+        let mut chain = match &self.recv_chain {
+            Some(chain) => decode_bytes(chain)?,
+            None => return Ok(()),
+        };
+
+        if until < self.recv_n {
+            return Ok(());
+        }
+
+        if until - self.recv_n > MAX_SKIP {
+            return Err(PacketError {
+                kind: PacketErrorKind::Crypto,
+                message: String::from("Too many skipped messages"),
+            });
+        }
+
+        let dh = match &self.dh_remote {
+            Some(dh) => dh.clone(),
+            None => return Ok(()),
+        };
+
+        while self.recv_n < until {
+            let (next, message_key) = kdf_chain(&chain)?;
+
+            self.skipped.push_back(SkippedKey {
+                dh: dh.clone(),
+                n: self.recv_n,
+                key: STANDARD.encode(message_key),
+            });
+
+            if self.skipped.len() > MAX_SKIPPED_KEYS {
+                self.skipped.pop_front();
+            }
+
+            chain = next;
+            self.recv_n += 1;
+        }
+
+        self.recv_chain = Some(STANDARD.encode(chain));
+
+        Ok(())
+    }
+
+    fn dh_ratchet(&mut self, remote: &str) -> Result<(), PacketError> {
+        let dh_remote = PublicKey::from(decode_bytes(remote)?);
+
+        self.prev_send_n = self.send_n;
+        self.send_n = 0;
+        self.recv_n = 0;
+        self.dh_remote = Some(String::from(remote));
+
+        let dh_self = StaticSecret::from(decode_bytes(&self.dh_self)?);
+        let (root_key, recv_chain) = kdf_root(
+            &decode_bytes(&self.root_key)?,
+            dh_self.diffie_hellman(&dh_remote).as_bytes(),
+        )?;
+
+        let dh_self = StaticSecret::random_from_rng(OsRng);
+        let (root_key, send_chain) =
+            kdf_root(&root_key, dh_self.diffie_hellman(&dh_remote).as_bytes())?;
+
+        self.dh_self = STANDARD.encode(dh_self.to_bytes());
+        self.root_key = STANDARD.encode(root_key);
+        self.recv_chain = Some(STANDARD.encode(recv_chain));
+        self.send_chain = Some(STANDARD.encode(send_chain));
+
+        Ok(())
+    }
+
+    fn public_key(&self) -> Result<String, PacketError> {
+        let dh_self = StaticSecret::from(decode_bytes(&self.dh_self)?);
+
+        Ok(STANDARD.encode(PublicKey::from(&dh_self).as_bytes()))
+    }
+
+    fn header_aad(&self, header: &Header) -> Result<Vec<u8>, PacketError> {
+        let mut aad = decode_vec(&self.associated_data)?;
+        aad.extend_from_slice(header.dh.as_bytes());
+        aad.extend_from_slice(&header.pn.to_be_bytes());
+        aad.extend_from_slice(&header.n.to_be_bytes());
+
+        Ok(aad)
+    }
+}
+
+fn kdf_root(root_key: &[u8; 32], dh: &[u8; 32]) -> Result<([u8; 32], [u8; 32]), PacketError> {
+    let mut okm = [0; 64];
+    expand(Some(root_key), dh, ROOT_INFO, &mut okm)?;
+
+    let mut root = [0; 32];
+    let mut chain = [0; 32];
+    root.copy_from_slice(&okm[..32]);
+    chain.copy_from_slice(&okm[32..]);
+
+    Ok((root, chain))
+}
+
+pub(crate) fn kdf_chain(chain_key: &[u8; 32]) -> Result<([u8; 32], [u8; 32]), PacketError> {
+    Ok((hmac(chain_key, 0x02)?, hmac(chain_key, 0x01)?))
+}
+
+fn hmac(key: &[u8; 32], input: u8) -> Result<[u8; 32], PacketError> {
+    let mut mac = match Hmac::<Sha256>::new_from_slice(key) {
+        Ok(mac) => mac,
+        Err(err) => {
+            return Err(PacketError {
+                kind: PacketErrorKind::Crypto,
+                message: err.to_string(),
+            })
+        }
+    };
+
+    mac.update(&[input]);
+
+    Ok(mac.finalize().into_bytes().into())
+}
+
+fn encode_message(message: RatchetMessage) -> Result<String, PacketError> {
+    match serde_json::to_vec(&message) {
+        Ok(buf) => Ok(STANDARD.encode(buf)),
+        Err(err) => Err(PacketError {
+            kind: PacketErrorKind::Serialize,
+            message: err.to_string(),
+        }),
+    }
+}
+
+fn decode_message(message: &str) -> Result<RatchetMessage, PacketError> {
+    match serde_json::from_slice(&decode_vec(message)?) {
+        Ok(message) => Ok(message),
+        Err(err) => Err(PacketError {
+            kind: PacketErrorKind::Malformed,
+            message: err.to_string(),
+        }),
+    }
+}
@@ -0,0 +1,379 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::crypto::ratchet::kdf_chain;
+use crate::crypto::signing::{decode_key, encode_key};
+use crate::crypto::x3dh::{aead_open, aead_seal, decode_bytes, decode_vec};
+use crate::packet::{PacketError, PacketErrorKind};
+
+const SENDER_KEY_CONTEXT: &[u8] = b"secure_chat/sender_key/v1";
+const MAX_SKIP: u32 = 1000;
+const MAX_SKIPPED_KEYS: usize = 2000;
+const MAX_SENDER_KEYS: usize = 4;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SenderKeyDistribution {
+    group: Uuid,
+    key_id: u32,
+    iteration: u32,
+    chain_key: String,
+    signing_key: String,
+}
+
+impl SenderKeyDistribution {
+    pub fn get_group(&self) -> Uuid {
+        self.group
+    }
+
+    pub fn get_key_id(&self) -> u32 {
+        self.key_id
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, PacketError> {
+        match serde_json::to_vec(self) {
+            Ok(buf) => Ok(buf),
+            Err(err) => Err(PacketError {
+                kind: PacketErrorKind::Serialize,
+                message: err.to_string(),
+            }),
+        }
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self, PacketError> {
+        match serde_json::from_slice(buf) {
+            Ok(distribution) => Ok(distribution),
+            Err(err) => Err(PacketError {
+                kind: PacketErrorKind::Malformed,
+                message: err.to_string(),
+            }),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct SenderKey {
+    key_id: u32,
+    iteration: u32,
+    chain_key: String,
+    signing_key: String,
+}
+
+impl SenderKey {
+    fn generate(key_id: u32) -> Self {
+        let mut chain_key = [0; 32];
+        OsRng.fill_bytes(&mut chain_key);
+
+        Self {
+            key_id,
+            iteration: 0,
+            chain_key: STANDARD.encode(chain_key),
+            signing_key: STANDARD.encode(SigningKey::generate(&mut OsRng).to_bytes()),
+        }
+    }
+
+    fn signing(&self) -> Result<SigningKey, PacketError> {
+        Ok(SigningKey::from_bytes(&decode_bytes(&self.signing_key)?))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct SkippedKey {
+    n: u32,
+    key: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct SenderKeyState {
+    key_id: u32,
+    iteration: u32,
+    chain_key: String,
+    signing_key: String,
+    skipped: VecDeque<SkippedKey>,
+}
+
+impl SenderKeyState {
+    fn message_key(&mut self, n: u32) -> Result<[u8; 32], PacketError> {
+        if let Some(index) = self.skipped.iter().position(|key| key.n == n) {
+            let key = self.skipped.remove(index).unwrap();
+            return decode_bytes(&key.key);
+        }
+
+        if n < self.iteration {
+            return Err(PacketError {
+                kind: PacketErrorKind::Replay,
+                message: format!("Group message {} already received", n),
+            });
+        }
+
+        if n - self.iteration > MAX_SKIP {
+            return Err(PacketError {
+                kind: PacketErrorKind::Crypto,
+                message: String::from("Too many skipped messages"),
+            });
+        }
+
+        let mut chain = decode_bytes(&self.chain_key)?;
+
+        while self.iteration < n {
+            let (next, message_key) = kdf_chain(&chain)?;
+
+            self.skipped.push_back(SkippedKey {
+                n: self.iteration,
+                key: STANDARD.encode(message_key),
+            });
+
+            if self.skipped.len() > MAX_SKIPPED_KEYS {
+                self.skipped.pop_front();
+            }
+
+            chain = next;
+            self.iteration += 1;
+        }
+
+        let (next, message_key) = kdf_chain(&chain)?;
+
+        self.chain_key = STANDARD.encode(next);
+        self.iteration += 1;
+
+        Ok(message_key)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct GroupMessage {
+    key_id: u32,
+    n: u32,
+    ciphertext: String,
+    signature: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GroupSession {
+    group: Uuid,
+    members: BTreeSet<String>,
+    pending: BTreeSet<String>,
+    sender_key: SenderKey,
+    senders: HashMap<String, VecDeque<SenderKeyState>>,
+}
+
+impl GroupSession {
+    pub fn new(group: Uuid, members: Vec<String>) -> Self {
+        let members: BTreeSet<String> = members.into_iter().collect();
+
+        Self {
+            group,
+            pending: members.clone(),
+            members,
+            // a random first id keeps a fresh session (say, after the
+            // keystore was lost) from reusing the id of its predecessor
+            sender_key: SenderKey::generate(OsRng.next_u32()),
+            senders: HashMap::new(),
+        }
+    }
+
+    pub fn get_group(&self) -> Uuid {
+        self.group
+    }
+
+    pub fn members(&self) -> Vec<String> {
+        self.members.iter().cloned().collect()
+    }
+
+    pub fn set_members(&mut self, members: Vec<String>) -> bool {
+        let members: BTreeSet<String> = members.into_iter().collect();
+
+        if members == self.members {
+            return false;
+        }
+
+        self.senders.retain(|sender, _| members.contains(sender));
+        self.members = members;
+        self.rotate();
+
+        true
+    }
+
+    pub fn rotate(&mut self) {
+        self.sender_key = SenderKey::generate(self.sender_key.key_id.wrapping_add(1));
+        self.pending = self.members.clone();
+    }
+
+    pub fn pending(&self) -> Vec<String> {
+        self.pending.iter().cloned().collect()
+    }
+
+    pub fn mark_distributed(&mut self, username: &str) {
+        self.pending.remove(username);
+    }
+
+    pub fn distribution(&self) -> Result<SenderKeyDistribution, PacketError> {
+        Ok(SenderKeyDistribution {
+            group: self.group,
+            key_id: self.sender_key.key_id,
+            iteration: self.sender_key.iteration,
+            chain_key: self.sender_key.chain_key.clone(),
+            signing_key: encode_key(&self.sender_key.signing()?.verifying_key()),
+        })
+    }
+
+    pub fn encrypt(&mut self, plaintext: &[u8]) -> Result<String, PacketError> {
+        let chain = decode_bytes(&self.sender_key.chain_key)?;
+        let (chain, message_key) = kdf_chain(&chain)?;
+
+        let key_id = self.sender_key.key_id;
+        let n = self.sender_key.iteration;
+        let aad = self.message_aad(key_id, n);
+
+        let ciphertext = aead_seal(&message_key, &[0; 12], plaintext, &aad)?;
+
+        let mut signed = aad;
+        signed.extend_from_slice(&ciphertext);
+        let signature = self.sender_key.signing()?.sign(&signed);
+
+        self.sender_key.chain_key = STANDARD.encode(chain);
+        self.sender_key.iteration += 1;
+
+        encode_message(GroupMessage {
+            key_id,
+            n,
+            ciphertext: STANDARD.encode(ciphertext),
+            signature: STANDARD.encode(signature.to_bytes()),
+        })
+    }
+
+    pub fn accept(
+        &mut self,
+        sender: &str,
+        distribution: SenderKeyDistribution,
+    ) -> Result<(), PacketError> {
+        if distribution.group != self.group {
+            return Err(PacketError {
+                kind: PacketErrorKind::UnknownKey,
+                message: String::from("Sender key belongs to another group"),
+            });
+        }
+
+        if !self.members.contains(sender) {
+            return Err(PacketError {
+                kind: PacketErrorKind::UnknownKey,
+                message: format!("{} is not a member of the group", sender),
+            });
+        }
+
+        decode_bytes(&distribution.chain_key)?;
+        decode_key(&distribution.signing_key)?;
+
+        let states = self.senders.entry(String::from(sender)).or_default();
+
+        // a redistributed key may arrive with a later chain key, but it is the
+        // same key only if it signs with the same key; anything else under an
+        // id we already hold replaces the old state
+        if let Some(index) = states
+            .iter()
+            .position(|state| state.key_id == distribution.key_id)
+        {
+            if states[index].signing_key == distribution.signing_key {
+                return Ok(());
+            }
+
+            states.remove(index);
+        }
+
+        states.push_front(SenderKeyState {
+            key_id: distribution.key_id,
+            iteration: distribution.iteration,
+            chain_key: distribution.chain_key,
+            signing_key: distribution.signing_key,
+            skipped: VecDeque::new(),
+        });
+        states.truncate(MAX_SENDER_KEYS);
+
+        Ok(())
+    }
+
+    pub fn decrypt(&mut self, sender: &str, message: &str) -> Result<Vec<u8>, PacketError> {
+        let message = decode_message(message)?;
+        let aad = self.message_aad(message.key_id, message.n);
+
+        let state = match self.senders.get_mut(sender).and_then(|states| {
+            states
+                .iter_mut()
+                .find(|state| state.key_id == message.key_id)
+        }) {
+            Some(state) => state,
+            None => {
+                return Err(PacketError {
+                    kind: PacketErrorKind::UnknownKey,
+                    message: format!("No sender key from {}", sender),
+                })
+            }
+        };
+
+        let ciphertext = decode_vec(&message.ciphertext)?;
+
+        let signature = match Signature::from_slice(&decode_vec(&message.signature)?) {
+            Ok(signature) => signature,
+            Err(err) => {
+                return Err(PacketError {
+                    kind: PacketErrorKind::BadSignature,
+                    message: err.to_string(),
+                })
+            }
+        };
+
+        let mut signed = aad.clone();
+        signed.extend_from_slice(&ciphertext);
+
+        if decode_key(&state.signing_key)?
+            .verify(&signed, &signature)
+            .is_err()
+        {
+            return Err(PacketError {
+                kind: PacketErrorKind::BadSignature,
+                message: String::from("Group message signature is invalid"),
+            });
+        }
+
+        let mut next = state.clone();
+        let message_key = next.message_key(message.n)?;
+        let plaintext = aead_open(&message_key, &[0; 12], &ciphertext, &aad)?;
+        *state = next;
+
+        Ok(plaintext)
+    }
+
+    fn message_aad(&self, key_id: u32, n: u32) -> Vec<u8> {
+        let mut aad = Vec::with_capacity(SENDER_KEY_CONTEXT.len() + 16 + 8);
+        aad.extend_from_slice(SENDER_KEY_CONTEXT);
+        aad.extend_from_slice(self.group.as_bytes());
+        aad.extend_from_slice(&key_id.to_be_bytes());
+        aad.extend_from_slice(&n.to_be_bytes());
+
+        aad
+    }
+}
+
+fn encode_message(message: GroupMessage) -> Result<String, PacketError> {
+    match serde_json::to_vec(&message) {
+        Ok(buf) => Ok(STANDARD.encode(buf)),
+        Err(err) => Err(PacketError {
+            kind: PacketErrorKind::Serialize,
+            message: err.to_string(),
+        }),
+    }
+}
+
+fn decode_message(message: &str) -> Result<GroupMessage, PacketError> {
+    match serde_json::from_slice(&decode_vec(message)?) {
+        Ok(message) => Ok(message),
+        Err(err) => Err(PacketError {
+            kind: PacketErrorKind::Malformed,
+            message: err.to_string(),
+        }),
+    }
+}
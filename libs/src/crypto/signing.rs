@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::rngs::OsRng;
+
+use crate::packet::{PacketError, PacketErrorKind};
+
+pub const DEFAULT_MAX_SKEW: i64 = 300;
+
+pub struct PacketSigner {
+    key: SigningKey,
+    seq: u64,
+}
+
+impl PacketSigner {
+    pub fn new(key: SigningKey) -> Self {
+        Self { key, seq: 0 }
+    }
+
+    pub fn generate() -> Self {
+        Self::new(SigningKey::generate(&mut OsRng))
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey {
+        self.key.verifying_key()
+    }
+
+    pub fn public_key(&self) -> String {
+        encode_key(&self.verifying_key())
+    }
+
+    pub(crate) fn next_seq(&mut self) -> u64 {
+        self.seq += 1;
+        self.seq
+    }
+
+    pub(crate) fn sign(&self, message: &[u8]) -> String {
+        STANDARD.encode(self.key.sign(message).to_bytes())
+    }
+}
+
+struct KnownKey {
+    key: VerifyingKey,
+    last_seq: u64,
+}
+
+pub struct PacketVerifier {
+    keys: HashMap<[u8; 32], KnownKey>,
+    max_skew: i64,
+}
+
+impl PacketVerifier {
+    pub fn new() -> Self {
+        Self::with_max_skew(DEFAULT_MAX_SKEW)
+    }
+
+    pub fn with_max_skew(max_skew: i64) -> Self {
+        Self {
+            keys: HashMap::new(),
+            max_skew,
+        }
+    }
+
+    pub fn trust(&mut self, key: VerifyingKey) {
+        self.keys
+            .entry(key.to_bytes())
+            .or_insert(KnownKey { key, last_seq: 0 });
+    }
+
+    pub fn trust_encoded(&mut self, key: &str) -> Result<(), PacketError> {
+        self.trust(decode_key(key)?);
+
+        Ok(())
+    }
+
+    pub fn max_skew(&self) -> i64 {
+        self.max_skew
+    }
+
+    pub(crate) fn verify(
+        &mut self,
+        key: &str,
+        signature: &str,
+        message: &[u8],
+        seq: u64,
+    ) -> Result<(), PacketError> {
+        let key = decode_key(key)?;
+
+        let known = match self.keys.get_mut(&key.to_bytes()) {
+            Some(known) => known,
+            None => {
+                return Err(PacketError {
+                    kind: PacketErrorKind::UnknownKey,
+                    message: String::from("Packet signed by unknown key"),
+                })
+            }
+        };
+
+        let signature = match STANDARD.decode(signature) {
+            Ok(signature) => signature,
+            Err(err) => {
+                return Err(PacketError {
+                    kind: PacketErrorKind::BadSignature,
+                    message: err.to_string(),
+                })
+            }
+        };
+
+        let signature = match Signature::from_slice(&signature) {
+            Ok(signature) => signature,
+            Err(err) => {
+                return Err(PacketError {
+                    kind: PacketErrorKind::BadSignature,
+                    message: err.to_string(),
+                })
+            }
+        };
+
+        if let Err(err) = known.key.verify(message, &signature) {
+            return Err(PacketError {
+                kind: PacketErrorKind::BadSignature,
+                message: err.to_string(),
+            });
+        }
+
+        if seq <= known.last_seq {
+            return Err(PacketError {
+                kind: PacketErrorKind::Replay,
+                message: format!("Packet sequence {} already seen", seq),
+            });
+        }
+
+        known.last_seq = seq;
+
+        Ok(())
+    }
+}
+
+impl Default for PacketVerifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn encode_key(key: &VerifyingKey) -> String {
+    STANDARD.encode(key.to_bytes())
+}
+
+pub fn decode_key(key: &str) -> Result<VerifyingKey, PacketError> {
+    let bytes = match STANDARD.decode(key) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            return Err(PacketError {
+                kind: PacketErrorKind::UnknownKey,
+                message: err.to_string(),
+            })
+        }
+    };
+
+    let bytes: [u8; 32] = match bytes.try_into() {
+        Ok(bytes) => bytes,
+        Err(_) => {
+            return Err(PacketError {
+                kind: PacketErrorKind::UnknownKey,
+                message: String::from("Public key must be 32 bytes"),
+            })
+        }
+    };
+
+    match VerifyingKey::from_bytes(&bytes) {
+        Ok(key) => Ok(key),
+        Err(err) => Err(PacketError {
+            kind: PacketErrorKind::UnknownKey,
+            message: err.to_string(),
+        }),
+    }
+}
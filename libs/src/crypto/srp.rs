@@ -0,0 +1,328 @@
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use num_bigint::BigUint;
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::crypto::x3dh::{decode_vec, expand};
+use crate::packet::{PacketError, PacketErrorKind};
+use crate::PacketModels;
+
+const GROUP_PRIME: &str = "\
+    AC6BDB41324A9A9BF166DE5E1389582FAF72B6651987EE07FC3192943DB56050\
+    A37329CBB4A099ED8193E0757767A13DD52312AB4B03310DCD7F48A9DA04FD50\
+    E8083969EDB767B0CF6095179A163AB3661A05FBD5FAAAE82918A9962F0B93B8\
+    55F97993EC975EEAA80D740ADBF4FF747359D041D5C33EA71D281E446B14773B\
+    CA97B43A23FB801676BD207A436C6481F1D2B9078717461A5B9D32E688F87748\
+    544523B524B0D57D5EA77A2775D2ECFA032CFBDBF52FB3786160279004E57AE6\
+    AF874E7303CE53299CCC041C7BC308D82A5698F3A8D0C38271AE35F8E9DBFBB6\
+    94B5C803D89F7AE435DE236D525F54759B65E372FCD68EF20FA7111F9E4AFF73";
+const GROUP_GENERATOR: u32 = 2;
+const GROUP_SIZE: usize = 256;
+const SALT_SIZE: usize = 16;
+const SECRET_SIZE: usize = 32;
+const SESSION_INFO: &[u8] = b"secure_chat/srp/v1/session";
+
+struct Group {
+    n: BigUint,
+    g: BigUint,
+    k: BigUint,
+}
+
+impl Group {
+    fn new() -> Self {
+        let n = BigUint::parse_bytes(GROUP_PRIME.as_bytes(), 16).unwrap();
+        let g = BigUint::from(GROUP_GENERATOR);
+        let k = BigUint::from_bytes_be(&hash(&[&n.to_bytes_be(), &pad(&g)]));
+
+        Self { n, g, k }
+    }
+
+    fn scramble(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        BigUint::from_bytes_be(&hash(&[&pad(a), &pad(b)]))
+    }
+
+    fn client_proof(
+        &self,
+        username: &str,
+        salt: &[u8],
+        a: &BigUint,
+        b: &BigUint,
+        key: &[u8],
+    ) -> [u8; 32] {
+        let hn = hash(&[&self.n.to_bytes_be()]);
+        let hg = hash(&[&self.g.to_bytes_be()]);
+
+        let mut group = [0; 32];
+        for (i, byte) in group.iter_mut().enumerate() {
+            *byte = hn[i] ^ hg[i];
+        }
+
+        hash(&[
+            &group,
+            &hash(&[username.as_bytes()]),
+            salt,
+            &pad(a),
+            &pad(b),
+            key,
+        ])
+    }
+
+    fn server_proof(&self, a: &BigUint, client_proof: &[u8], key: &[u8]) -> [u8; 32] {
+        hash(&[&pad(a), client_proof, key])
+    }
+}
+
+pub fn register(username: &str, password: &str) -> Result<(String, String), PacketError> {
+    let group = Group::new();
+
+    let mut salt = [0; SALT_SIZE];
+    OsRng.fill_bytes(&mut salt);
+
+    let x = private_key(username, password, &salt)?;
+    let verifier = group.g.modpow(&x, &group.n);
+
+    Ok((
+        STANDARD.encode(salt),
+        STANDARD.encode(verifier.to_bytes_be()),
+    ))
+}
+
+pub fn fake_salt(seed: &[u8; 32], username: &str) -> Result<String, PacketError> {
+    let mut salt = [0; SALT_SIZE];
+    expand(
+        Some(seed),
+        username.as_bytes(),
+        b"secure_chat/srp/v1/salt",
+        &mut salt,
+    )?;
+
+    Ok(STANDARD.encode(salt))
+}
+
+pub struct SrpClient {
+    username: String,
+    secret: BigUint,
+    public: BigUint,
+}
+
+impl SrpClient {
+    pub fn start(username: &str) -> (Self, PacketModels::SrpStart) {
+        let group = Group::new();
+
+        let secret = random_secret();
+        let public = group.g.modpow(&secret, &group.n);
+
+        let start = PacketModels::SrpStart::new(
+            String::from(username),
+            STANDARD.encode(public.to_bytes_be()),
+        );
+
+        (
+            Self {
+                username: String::from(username),
+                secret,
+                public,
+            },
+            start,
+        )
+    }
+
+    pub fn finish(
+        self,
+        password: &str,
+        challenge: PacketModels::SrpChallenge,
+    ) -> Result<(SrpClientProof, PacketModels::SrpProof), PacketError> {
+        let group = Group::new();
+
+        let salt = decode_vec(&challenge.get_salt())?;
+        let server_public = BigUint::from_bytes_be(&decode_vec(&challenge.get_public_key())?);
+
+        if (&server_public % &group.n) == BigUint::ZERO {
+            return Err(handshake_error("Server sent an invalid public value"));
+        }
+
+        let u = group.scramble(&self.public, &server_public);
+
+        if u == BigUint::ZERO {
+            return Err(handshake_error("Server sent an invalid public value"));
+        }
+
+        let x = private_key(&self.username, password, &salt)?;
+
+        let gx = group.g.modpow(&x, &group.n);
+        let base = (&server_public + &group.n - (&group.k * gx) % &group.n) % &group.n;
+        let secret = base.modpow(&(&self.secret + &u * &x), &group.n);
+
+        let key = hash(&[&pad(&secret)]);
+
+        let proof = group.client_proof(&self.username, &salt, &self.public, &server_public, &key);
+        let server_proof = group.server_proof(&self.public, &proof, &key);
+
+        Ok((
+            SrpClientProof { key, server_proof },
+            PacketModels::SrpProof::new(STANDARD.encode(proof)),
+        ))
+    }
+}
+
+pub struct SrpClientProof {
+    key: [u8; 32],
+    server_proof: [u8; 32],
+}
+
+impl SrpClientProof {
+    pub fn verify(self, proof: &str) -> Result<[u8; 32], PacketError> {
+        let proof = decode_vec(proof)?;
+
+        if !bool::from(proof.ct_eq(&self.server_proof)) {
+            return Err(PacketError {
+                kind: PacketErrorKind::BadSignature,
+                message: String::from("Server failed to prove knowledge of the verifier"),
+            });
+        }
+
+        session_key(&self.key)
+    }
+}
+
+pub struct SrpServer {
+    username: String,
+    salt: Vec<u8>,
+    client_public: BigUint,
+    public: BigUint,
+    key: [u8; 32],
+}
+
+impl SrpServer {
+    pub fn start(
+        start: &PacketModels::SrpStart,
+        salt: &str,
+        verifier: Option<&str>,
+    ) -> Result<(Self, PacketModels::SrpChallenge), PacketError> {
+        let group = Group::new();
+
+        let client_public = BigUint::from_bytes_be(&decode_vec(&start.get_public_key())?);
+
+        if (&client_public % &group.n) == BigUint::ZERO {
+            return Err(handshake_error("Client sent an invalid public value"));
+        }
+
+        let verifier = match verifier {
+            Some(verifier) => BigUint::from_bytes_be(&decode_vec(verifier)?),
+            None => group.g.modpow(&random_secret(), &group.n),
+        };
+
+        let secret = random_secret();
+        let public = (&group.k * &verifier + group.g.modpow(&secret, &group.n)) % &group.n;
+
+        let u = group.scramble(&client_public, &public);
+
+        let shared = (&client_public * verifier.modpow(&u, &group.n)) % &group.n;
+        let shared = shared.modpow(&secret, &group.n);
+
+        let key = hash(&[&pad(&shared)]);
+
+        let challenge = PacketModels::SrpChallenge::new(
+            String::from(salt),
+            STANDARD.encode(public.to_bytes_be()),
+        );
+
+        Ok((
+            Self {
+                username: start.get_username(),
+                salt: decode_vec(salt)?,
+                client_public,
+                public,
+                key,
+            },
+            challenge,
+        ))
+    }
+
+    pub fn get_username(&self) -> String {
+        self.username.clone()
+    }
+
+    pub fn finish(self, proof: &PacketModels::SrpProof) -> Result<([u8; 32], String), PacketError> {
+        let group = Group::new();
+
+        let expected = group.client_proof(
+            &self.username,
+            &self.salt,
+            &self.client_public,
+            &self.public,
+            &self.key,
+        );
+
+        let proof = decode_vec(&proof.get_proof())?;
+
+        if !bool::from(proof.ct_eq(&expected)) {
+            return Err(PacketError {
+                kind: PacketErrorKind::BadSignature,
+                message: String::from("Invalid Username or Password"),
+            });
+        }
+
+        let server_proof = group.server_proof(&self.client_public, &expected, &self.key);
+
+        Ok((session_key(&self.key)?, STANDARD.encode(server_proof)))
+    }
+}
+
+fn private_key(username: &str, password: &str, salt: &[u8]) -> Result<BigUint, PacketError> {
+    let mut identity = Vec::with_capacity(username.len() + 1 + password.len());
+    identity.extend_from_slice(username.as_bytes());
+    identity.push(b':');
+    identity.extend_from_slice(password.as_bytes());
+
+    let mut x = [0; 32];
+    if let Err(err) = Argon2::default().hash_password_into(&identity, salt, &mut x) {
+        return Err(PacketError {
+            kind: PacketErrorKind::Crypto,
+            message: err.to_string(),
+        });
+    }
+
+    Ok(BigUint::from_bytes_be(&hash(&[salt, &x])))
+}
+
+fn session_key(key: &[u8; 32]) -> Result<[u8; 32], PacketError> {
+    let mut session = [0; 32];
+    expand(None, key, SESSION_INFO, &mut session)?;
+
+    Ok(session)
+}
+
+fn random_secret() -> BigUint {
+    let mut secret = [0; SECRET_SIZE];
+    OsRng.fill_bytes(&mut secret);
+
+    BigUint::from_bytes_be(&secret)
+}
+
+fn hash(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+
+    hasher.finalize().into()
+}
+
+fn pad(value: &BigUint) -> Vec<u8> {
+    let bytes = value.to_bytes_be();
+
+    let mut padded = vec![0; GROUP_SIZE.saturating_sub(bytes.len())];
+    padded.extend_from_slice(&bytes);
+
+    padded
+}
+
+fn handshake_error(message: &str) -> PacketError {
+    PacketError {
+        kind: PacketErrorKind::Handshake,
+        message: String::from(message),
+    }
+}
@@ -0,0 +1,319 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Write},
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, SystemTime},
+};
+
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+    Certificate, ClientConfig, ClientConnection, Connection, PrivateKey, RootCertStore,
+    ServerConfig, ServerConnection, ServerName,
+};
+use sha2::{Digest, Sha256};
+
+use crate::packet::{PacketError, PacketErrorKind};
+use crate::packet_manager::{Stream, Transport};
+
+const READ_CHUNK: usize = 16 * 1024;
+
+/// How a client decides whether to trust the certificate a server presents.
+pub enum ServerTrust {
+    /// Accept whatever certificate is offered; the caller pins the returned
+    /// fingerprint so later connections can use `Pinned`.
+    FirstUse,
+    /// Only accept the certificate with this SHA-256 fingerprint.
+    Pinned(String),
+    /// Verify the chain and host name against these certificate authorities.
+    Authority(RootCertStore),
+}
+
+impl ServerTrust {
+    pub fn authority(path: &Path) -> Result<Self, PacketError> {
+        let mut roots = RootCertStore::empty();
+
+        for cert in load_certs(path)? {
+            if let Err(err) = roots.add(&cert) {
+                return Err(tls_error(format!("{}: {}", path.display(), err)));
+            }
+        }
+
+        Ok(ServerTrust::Authority(roots))
+    }
+}
+
+/// A TLS session over another transport that can be cloned like the socket
+/// it wraps, so one clone can block in `read` while another writes. Only one
+/// clone should read at a time.
+pub struct TlsStream {
+    conn: Arc<Mutex<Connection>>,
+    socket: Stream,
+    incoming: Vec<u8>,
+}
+
+impl TlsStream {
+    fn handshake(conn: Connection, mut socket: Stream) -> Result<Self, PacketError> {
+        let mut conn = conn;
+
+        while conn.is_handshaking() {
+            if let Err(err) = conn.complete_io(&mut socket) {
+                return Err(tls_error(err));
+            }
+        }
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            socket,
+            incoming: Vec::new(),
+        })
+    }
+
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            conn: Arc::clone(&self.conn),
+            socket: self.socket.try_clone()?,
+            incoming: Vec::new(),
+        })
+    }
+
+    /// Sends close_notify when nobody else is mid-write, then closes the socket.
+    pub fn shutdown(&self) {
+        if let (Ok(mut conn), Ok(mut socket)) = (self.conn.try_lock(), self.socket.try_clone()) {
+            conn.send_close_notify();
+            let _ = flush_tls(&mut conn, &mut socket);
+        }
+
+        self.socket.shutdown();
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.socket.set_read_timeout(timeout)
+    }
+
+    /// SHA-256 fingerprint of the certificate the peer presented, if any.
+    pub fn peer_fingerprint(&self) -> Option<String> {
+        let conn = lock(&self.conn);
+
+        conn.peer_certificates()
+            .and_then(|certs| certs.first())
+            .map(|cert| fingerprint(&cert.0))
+    }
+}
+
+impl Transport for TlsStream {
+    fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(self.try_clone()?))
+    }
+
+    fn close(&self) {
+        self.shutdown();
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TlsStream::set_read_timeout(self, timeout)
+    }
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            {
+                let mut conn = lock(&self.conn);
+
+                match conn.reader().read(buf) {
+                    Ok(read) => return Ok(read),
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                    Err(err) => return Err(err),
+                }
+
+                if !self.incoming.is_empty() {
+                    let consumed = conn.read_tls(&mut &self.incoming[..])?;
+                    self.incoming.drain(..consumed);
+
+                    if let Err(err) = conn.process_new_packets() {
+                        let _ = flush_tls(&mut conn, &mut self.socket);
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, err));
+                    }
+
+                    flush_tls(&mut conn, &mut self.socket)?;
+                    continue;
+                }
+            }
+
+            // Wait for the peer without holding the lock so writers can proceed.
+            let mut chunk = [0; READ_CHUNK];
+            let read = self.socket.read(&mut chunk)?;
+
+            if read == 0 {
+                return Ok(0);
+            }
+
+            self.incoming.extend_from_slice(&chunk[..read]);
+        }
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut conn = lock(&self.conn);
+
+        let written = conn.writer().write(buf)?;
+        flush_tls(&mut conn, &mut self.socket)?;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut conn = lock(&self.conn);
+
+        conn.writer().flush()?;
+        flush_tls(&mut conn, &mut self.socket)
+    }
+}
+
+pub fn server_config(cert: &Path, key: &Path) -> Result<Arc<ServerConfig>, PacketError> {
+    let certs = load_certs(cert)?;
+    let key = load_key(key)?;
+
+    match ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+    {
+        Ok(config) => Ok(Arc::new(config)),
+        Err(err) => Err(tls_error(err)),
+    }
+}
+
+pub fn accept(
+    config: Arc<ServerConfig>,
+    socket: impl Into<Stream>,
+) -> Result<TlsStream, PacketError> {
+    match ServerConnection::new(config) {
+        Ok(conn) => TlsStream::handshake(Connection::Server(conn), socket.into()),
+        Err(err) => Err(tls_error(err)),
+    }
+}
+
+pub fn connect(
+    trust: ServerTrust,
+    host: &str,
+    socket: impl Into<Stream>,
+) -> Result<TlsStream, PacketError> {
+    let verifier: Arc<dyn ServerCertVerifier> = match trust {
+        ServerTrust::FirstUse => Arc::new(PinVerifier { pin: None }),
+        ServerTrust::Pinned(pin) => Arc::new(PinVerifier { pin: Some(pin) }),
+        ServerTrust::Authority(roots) => Arc::new(WebPkiVerifier::new(roots, None)),
+    };
+
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(verifier)
+        .with_no_client_auth();
+
+    let name = match ServerName::try_from(host) {
+        Ok(name) => name,
+        Err(err) => return Err(tls_error(format!("{}: {}", host, err))),
+    };
+
+    match ClientConnection::new(Arc::new(config), name) {
+        Ok(conn) => TlsStream::handshake(Connection::Client(conn), socket.into()),
+        Err(err) => Err(tls_error(err)),
+    }
+}
+
+pub fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub fn load_certs(path: &Path) -> Result<Vec<Certificate>, PacketError> {
+    let certs = match rustls_pemfile::certs(&mut open(path)?) {
+        Ok(certs) => certs,
+        Err(err) => return Err(tls_error(format!("{}: {}", path.display(), err))),
+    };
+
+    if certs.is_empty() {
+        return Err(tls_error(format!(
+            "{}: no certificates found",
+            path.display()
+        )));
+    }
+
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+pub fn load_key(path: &Path) -> Result<PrivateKey, PacketError> {
+    let mut reader = open(path)?;
+
+    loop {
+        match rustls_pemfile::read_one(&mut reader) {
+            Ok(Some(rustls_pemfile::Item::PKCS8Key(key)))
+            | Ok(Some(rustls_pemfile::Item::RSAKey(key)))
+            | Ok(Some(rustls_pemfile::Item::ECKey(key))) => return Ok(PrivateKey(key)),
+            Ok(Some(_)) => continue,
+            Ok(None) => {
+                return Err(tls_error(format!(
+                    "{}: no private key found",
+                    path.display()
+                )))
+            }
+            Err(err) => return Err(tls_error(format!("{}: {}", path.display(), err))),
+        }
+    }
+}
+
+struct PinVerifier {
+    pin: Option<String>,
+}
+
+impl ServerCertVerifier for PinVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match &self.pin {
+            Some(pin) if *pin != fingerprint(&end_entity.0) => Err(rustls::Error::General(
+                String::from("Server certificate does not match the pinned fingerprint"),
+            )),
+            _ => Ok(ServerCertVerified::assertion()),
+        }
+    }
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, PacketError> {
+    match File::open(path) {
+        Ok(file) => Ok(BufReader::new(file)),
+        Err(err) => Err(tls_error(format!("{}: {}", path.display(), err))),
+    }
+}
+
+fn flush_tls(conn: &mut Connection, socket: &mut Stream) -> io::Result<()> {
+    while conn.wants_write() {
+        conn.write_tls(socket)?;
+    }
+
+    Ok(())
+}
+
+fn lock(conn: &Mutex<Connection>) -> MutexGuard<'_, Connection> {
+    match conn.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+fn tls_error(err: impl ToString) -> PacketError {
+    PacketError {
+        kind: PacketErrorKind::Tls,
+        message: err.to_string(),
+    }
+}
@@ -0,0 +1,298 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey, ReusableSecret, StaticSecret};
+
+use crate::packet::{PacketError, PacketErrorKind};
+use crate::PacketModels;
+
+const HANDSHAKE_SALT: &[u8] = b"secure_chat/transport/v1";
+const TRANSPORT_AAD: &[u8] = b"secure_chat/transport/v1/record";
+const REKEY_INFO: &[u8] = b"secure_chat/transport/v1/rekey";
+
+pub struct ServerIdentity {
+    secret: StaticSecret,
+}
+
+impl ServerIdentity {
+    pub fn generate() -> Self {
+        Self {
+            secret: StaticSecret::random_from_rng(OsRng),
+        }
+    }
+
+    pub fn from_encoded(secret: &str) -> Result<Self, PacketError> {
+        let secret = decode_point(secret)?;
+
+        Ok(Self {
+            secret: StaticSecret::from(secret),
+        })
+    }
+
+    pub fn encode(&self) -> String {
+        STANDARD.encode(self.secret.to_bytes())
+    }
+
+    pub fn public_key(&self) -> String {
+        STANDARD.encode(PublicKey::from(&self.secret).as_bytes())
+    }
+
+    pub fn derive_secret(&self, info: &[u8]) -> Result<[u8; 32], PacketError> {
+        let mut okm = [0; 32];
+        if Hkdf::<Sha256>::new(Some(HANDSHAKE_SALT), self.secret.as_bytes())
+            .expand(info, &mut okm)
+            .is_err()
+        {
+            return Err(PacketError {
+                kind: PacketErrorKind::Crypto,
+                message: String::from("Failed to derive server secret"),
+            });
+        }
+
+        Ok(okm)
+    }
+
+    pub fn respond(
+        &self,
+        hello: PacketModels::PubKey,
+    ) -> Result<(PacketModels::KeyExchange, TransportCipher), PacketError> {
+        let client_ephemeral = PublicKey::from(decode_point(&hello.get_key())?);
+
+        let ephemeral = EphemeralSecret::random_from_rng(OsRng);
+        let server_ephemeral = PublicKey::from(&ephemeral);
+        let server_static = PublicKey::from(&self.secret);
+
+        let ee = ephemeral.diffie_hellman(&client_ephemeral);
+        let es = self.secret.diffie_hellman(&client_ephemeral);
+
+        let (client_to_server, server_to_client) = derive_keys(
+            ee.as_bytes(),
+            es.as_bytes(),
+            &client_ephemeral,
+            &server_ephemeral,
+            &server_static,
+        )?;
+
+        let reply = PacketModels::KeyExchange::new(
+            STANDARD.encode(server_ephemeral.as_bytes()),
+            STANDARD.encode(server_static.as_bytes()),
+        );
+
+        Ok((
+            reply,
+            TransportCipher::new(server_to_client, client_to_server),
+        ))
+    }
+}
+
+pub struct ClientHandshake {
+    secret: ReusableSecret,
+    public: PublicKey,
+}
+
+impl ClientHandshake {
+    pub fn start() -> (Self, PacketModels::PubKey) {
+        let secret = ReusableSecret::random_from_rng(OsRng);
+        let public = PublicKey::from(&secret);
+
+        let hello = PacketModels::PubKey::new(STANDARD.encode(public.as_bytes()));
+
+        (Self { secret, public }, hello)
+    }
+
+    pub fn finish(
+        self,
+        reply: PacketModels::KeyExchange,
+        expected_server_key: Option<&str>,
+    ) -> Result<TransportCipher, PacketError> {
+        if let Some(expected) = expected_server_key {
+            if expected != reply.get_static_key() {
+                return Err(PacketError {
+                    kind: PacketErrorKind::UnknownKey,
+                    message: String::from("Server key does not match the pinned key"),
+                });
+            }
+        }
+
+        let server_ephemeral = PublicKey::from(decode_point(&reply.get_ephemeral_key())?);
+        let server_static = PublicKey::from(decode_point(&reply.get_static_key())?);
+
+        let ee = self.secret.diffie_hellman(&server_ephemeral);
+        let es = self.secret.diffie_hellman(&server_static);
+
+        let (client_to_server, server_to_client) = derive_keys(
+            ee.as_bytes(),
+            es.as_bytes(),
+            &self.public,
+            &server_ephemeral,
+            &server_static,
+        )?;
+
+        Ok(TransportCipher::new(client_to_server, server_to_client))
+    }
+}
+
+struct CipherState {
+    key: [u8; 32],
+    cipher: ChaCha20Poly1305,
+    nonce: u64,
+}
+
+impl CipherState {
+    fn new(key: [u8; 32]) -> Self {
+        Self {
+            key,
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&key)),
+            nonce: 0,
+        }
+    }
+
+    fn rekey(&mut self, secret: &[u8; 32]) -> Result<(), PacketError> {
+        let mut key = [0; 32];
+        if Hkdf::<Sha256>::new(Some(secret), &self.key)
+            .expand(REKEY_INFO, &mut key)
+            .is_err()
+        {
+            return Err(PacketError {
+                kind: PacketErrorKind::Crypto,
+                message: String::from("Failed to rekey transport"),
+            });
+        }
+
+        *self = Self::new(key);
+
+        Ok(())
+    }
+
+    fn next_nonce(&mut self) -> Result<Nonce, PacketError> {
+        let counter = self.nonce;
+
+        self.nonce = match counter.checked_add(1) {
+            Some(nonce) => nonce,
+            None => {
+                return Err(PacketError {
+                    kind: PacketErrorKind::Crypto,
+                    message: String::from("Transport nonce exhausted"),
+                })
+            }
+        };
+
+        let mut nonce = [0; 12];
+        nonce[4..].copy_from_slice(&counter.to_be_bytes());
+
+        Ok(*Nonce::from_slice(&nonce))
+    }
+}
+
+pub struct TransportCipher {
+    send: CipherState,
+    recv: CipherState,
+}
+
+impl TransportCipher {
+    fn new(send: [u8; 32], recv: [u8; 32]) -> Self {
+        Self {
+            send: CipherState::new(send),
+            recv: CipherState::new(recv),
+        }
+    }
+
+    pub fn rekey(&mut self, secret: &[u8; 32]) -> Result<(), PacketError> {
+        self.send.rekey(secret)?;
+        self.recv.rekey(secret)
+    }
+
+    pub fn seal(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, PacketError> {
+        let nonce = self.send.next_nonce()?;
+
+        let payload = Payload {
+            msg: plaintext,
+            aad: TRANSPORT_AAD,
+        };
+
+        match self.send.cipher.encrypt(&nonce, payload) {
+            Ok(ciphertext) => Ok(ciphertext),
+            Err(err) => Err(PacketError {
+                kind: PacketErrorKind::Crypto,
+                message: err.to_string(),
+            }),
+        }
+    }
+
+    pub fn open(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>, PacketError> {
+        let nonce = self.recv.next_nonce()?;
+
+        let payload = Payload {
+            msg: ciphertext,
+            aad: TRANSPORT_AAD,
+        };
+
+        match self.recv.cipher.decrypt(&nonce, payload) {
+            Ok(plaintext) => Ok(plaintext),
+            Err(_) => Err(PacketError {
+                kind: PacketErrorKind::Crypto,
+                message: String::from("Failed to decrypt packet"),
+            }),
+        }
+    }
+}
+
+fn derive_keys(
+    ee: &[u8; 32],
+    es: &[u8; 32],
+    client_ephemeral: &PublicKey,
+    server_ephemeral: &PublicKey,
+    server_static: &PublicKey,
+) -> Result<([u8; 32], [u8; 32]), PacketError> {
+    let mut ikm = [0; 64];
+    ikm[..32].copy_from_slice(ee);
+    ikm[32..].copy_from_slice(es);
+
+    let mut transcript = Vec::with_capacity(96);
+    transcript.extend_from_slice(client_ephemeral.as_bytes());
+    transcript.extend_from_slice(server_ephemeral.as_bytes());
+    transcript.extend_from_slice(server_static.as_bytes());
+
+    let mut okm = [0; 64];
+    if Hkdf::<Sha256>::new(Some(HANDSHAKE_SALT), &ikm)
+        .expand(&transcript, &mut okm)
+        .is_err()
+    {
+        return Err(PacketError {
+            kind: PacketErrorKind::Crypto,
+            message: String::from("Failed to derive transport keys"),
+        });
+    }
+
+    let mut client_to_server = [0; 32];
+    let mut server_to_client = [0; 32];
+    client_to_server.copy_from_slice(&okm[..32]);
+    server_to_client.copy_from_slice(&okm[32..]);
+
+    Ok((client_to_server, server_to_client))
+}
+
+fn decode_point(key: &str) -> Result<[u8; 32], PacketError> {
+    let bytes = match STANDARD.decode(key) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            return Err(PacketError {
+                kind: PacketErrorKind::Crypto,
+                message: err.to_string(),
+            })
+        }
+    };
+
+    match bytes.try_into() {
+        Ok(bytes) => Ok(bytes),
+        Err(_) => Err(PacketError {
+            kind: PacketErrorKind::Crypto,
+            message: String::from("Key must be 32 bytes"),
+        }),
+    }
+}
@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier};
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::crypto::ratchet::Ratchet;
+use crate::crypto::signing::decode_key;
+use crate::packet::{PacketError, PacketErrorKind};
+use crate::{BaseModels, PacketModels};
+
+const X3DH_INFO: &[u8] = b"secure_chat/x3dh/v1";
+
+#[derive(Serialize, Deserialize, Clone)]
+struct StoredPreKey {
+    id: u32,
+    secret: String,
+}
+
+impl StoredPreKey {
+    fn generate(id: u32) -> Self {
+        Self {
+            id,
+            secret: STANDARD.encode(StaticSecret::random_from_rng(OsRng).to_bytes()),
+        }
+    }
+
+    fn secret(&self) -> Result<StaticSecret, PacketError> {
+        Ok(StaticSecret::from(decode_bytes(&self.secret)?))
+    }
+
+    fn public(&self) -> Result<PacketModels::PreKey, PacketError> {
+        let public = PublicKey::from(&self.secret()?);
+
+        Ok(PacketModels::PreKey::new(
+            self.id,
+            STANDARD.encode(public.as_bytes()),
+        ))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LocalKeys {
+    identity_key: String,
+    signing_key: String,
+    signed_prekey: StoredPreKey,
+    one_time_prekeys: Vec<StoredPreKey>,
+    next_prekey_id: u32,
+    published_prekey_id: u32,
+    known_identities: HashMap<String, String>,
+}
+
+impl LocalKeys {
+    pub fn generate(one_time_prekeys: u32) -> Self {
+        let identity = StaticSecret::random_from_rng(OsRng);
+        let signing = SigningKey::generate(&mut OsRng);
+
+        let mut keys = Self {
+            identity_key: STANDARD.encode(identity.to_bytes()),
+            signing_key: STANDARD.encode(signing.to_bytes()),
+            signed_prekey: StoredPreKey::generate(0),
+            one_time_prekeys: Vec::new(),
+            next_prekey_id: 1,
+            published_prekey_id: 0,
+            known_identities: HashMap::new(),
+        };
+
+        keys.refill(one_time_prekeys as usize);
+
+        keys
+    }
+
+    pub fn refill(&mut self, count: usize) {
+        while self.one_time_prekeys.len() < count {
+            self.one_time_prekeys
+                .push(StoredPreKey::generate(self.next_prekey_id));
+            self.next_prekey_id += 1;
+        }
+    }
+
+    pub fn has_unpublished(&self) -> bool {
+        self.published_prekey_id < self.next_prekey_id
+    }
+
+    pub fn mark_published(&mut self) {
+        self.published_prekey_id = self.next_prekey_id;
+    }
+
+    pub fn identity_key(&self) -> Result<String, PacketError> {
+        let public = PublicKey::from(&self.identity()?);
+
+        Ok(STANDARD.encode(public.as_bytes()))
+    }
+
+    pub fn bundle(&self) -> Result<PacketModels::KeyBundle, PacketError> {
+        let signing = self.signing()?;
+        let signed_prekey = self.signed_prekey.public()?;
+
+        let signature = signing.sign(&prekey_message(
+            &self.identity_key()?,
+            &signed_prekey.get_key(),
+        ));
+
+        let mut one_time_prekeys = Vec::new();
+        for prekey in &self.one_time_prekeys {
+            if prekey.id >= self.published_prekey_id {
+                one_time_prekeys.push(prekey.public()?);
+            }
+        }
+
+        Ok(PacketModels::KeyBundle::new(
+            self.identity_key()?,
+            STANDARD.encode(signing.verifying_key().to_bytes()),
+            signed_prekey,
+            STANDARD.encode(signature.to_bytes()),
+            one_time_prekeys,
+        ))
+    }
+
+    pub fn initiate(
+        &mut self,
+        recipient: BaseModels::User,
+        bundle: PacketModels::PreKeyBundle,
+        plaintext: &[u8],
+    ) -> Result<(Ratchet, PacketModels::E2E), PacketError> {
+        let username = recipient.get_username();
+        self.check_identity(&username, &bundle.get_identity_key())?;
+
+        let signing_key = decode_key(&bundle.get_signing_key())?;
+        let signature = match Signature::from_slice(&decode_vec(&bundle.get_signature())?) {
+            Ok(signature) => signature,
+            Err(err) => {
+                return Err(PacketError {
+                    kind: PacketErrorKind::BadSignature,
+                    message: err.to_string(),
+                })
+            }
+        };
+
+        let signed_prekey = bundle.get_signed_prekey();
+        let message = prekey_message(&bundle.get_identity_key(), &signed_prekey.get_key());
+
+        if signing_key.verify(&message, &signature).is_err() {
+            return Err(PacketError {
+                kind: PacketErrorKind::BadSignature,
+                message: String::from("Signed prekey signature is invalid"),
+            });
+        }
+
+        let identity = self.identity()?;
+        let ephemeral = StaticSecret::random_from_rng(OsRng);
+
+        let their_identity = PublicKey::from(decode_bytes(&bundle.get_identity_key())?);
+        let their_signed_prekey = PublicKey::from(decode_bytes(&signed_prekey.get_key())?);
+
+        let mut dh = Vec::with_capacity(128);
+        dh.extend_from_slice(identity.diffie_hellman(&their_signed_prekey).as_bytes());
+        dh.extend_from_slice(ephemeral.diffie_hellman(&their_identity).as_bytes());
+        dh.extend_from_slice(ephemeral.diffie_hellman(&their_signed_prekey).as_bytes());
+
+        let one_time_prekey = match bundle.get_one_time_prekey() {
+            Some(prekey) => {
+                let key = PublicKey::from(decode_bytes(&prekey.get_key())?);
+                dh.extend_from_slice(ephemeral.diffie_hellman(&key).as_bytes());
+                Some(prekey.get_id())
+            }
+            None => None,
+        };
+
+        let our_identity = self.identity_key()?;
+        let secret = SharedSecret::derive(
+            &dh,
+            associated_data(&our_identity, &bundle.get_identity_key()),
+        )?;
+
+        let mut ratchet = Ratchet::initiator(&secret, &signed_prekey.get_key())?;
+        let ciphertext = ratchet.encrypt(plaintext)?;
+
+        self.known_identities
+            .insert(username, bundle.get_identity_key());
+
+        let init = PacketModels::E2E::new(
+            our_identity,
+            recipient,
+            STANDARD.encode(PublicKey::from(&ephemeral).as_bytes()),
+            signed_prekey.get_id(),
+            one_time_prekey,
+            ciphertext,
+        );
+
+        Ok((ratchet, init))
+    }
+
+    pub fn accept(&mut self, init: &PacketModels::E2E) -> Result<(Ratchet, Vec<u8>), PacketError> {
+        self.check_identity(&init.get_user().get_username(), &init.get_public_key())?;
+
+        if init.get_signed_prekey() != self.signed_prekey.id {
+            return Err(PacketError {
+                kind: PacketErrorKind::UnknownKey,
+                message: String::from("Unknown signed prekey"),
+            });
+        }
+
+        let identity = self.identity()?;
+        let signed_prekey = self.signed_prekey.secret()?;
+
+        let their_identity = PublicKey::from(decode_bytes(&init.get_public_key())?);
+        let their_ephemeral = PublicKey::from(decode_bytes(&init.get_ephemeral_key())?);
+
+        let mut dh = Vec::with_capacity(128);
+        dh.extend_from_slice(signed_prekey.diffie_hellman(&their_identity).as_bytes());
+        dh.extend_from_slice(identity.diffie_hellman(&their_ephemeral).as_bytes());
+        dh.extend_from_slice(signed_prekey.diffie_hellman(&their_ephemeral).as_bytes());
+
+        let used = match init.get_one_time_prekey() {
+            Some(id) => {
+                let index = match self.one_time_prekeys.iter().position(|key| key.id == id) {
+                    Some(index) => index,
+                    None => {
+                        return Err(PacketError {
+                            kind: PacketErrorKind::UnknownKey,
+                            message: String::from("Unknown one-time prekey"),
+                        })
+                    }
+                };
+
+                let prekey = self.one_time_prekeys[index].secret()?;
+                dh.extend_from_slice(prekey.diffie_hellman(&their_ephemeral).as_bytes());
+
+                Some(index)
+            }
+            None => None,
+        };
+
+        let secret = SharedSecret::derive(
+            &dh,
+            associated_data(&init.get_public_key(), &self.identity_key()?),
+        )?;
+
+        let mut ratchet = Ratchet::responder(&secret, &signed_prekey)?;
+        let plaintext = ratchet.decrypt(&init.get_ciphertext())?;
+
+        if let Some(index) = used {
+            self.one_time_prekeys.remove(index);
+        }
+
+        self.known_identities
+            .insert(init.get_user().get_username(), init.get_public_key());
+
+        Ok((ratchet, plaintext))
+    }
+
+    fn check_identity(&self, username: &str, identity: &str) -> Result<(), PacketError> {
+        match self.known_identities.get(username) {
+            Some(known) if known != identity => Err(PacketError {
+                kind: PacketErrorKind::UnknownKey,
+                message: format!("Identity key of {} has changed", username),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn identity(&self) -> Result<StaticSecret, PacketError> {
+        Ok(StaticSecret::from(decode_bytes(&self.identity_key)?))
+    }
+
+    fn signing(&self) -> Result<SigningKey, PacketError> {
+        Ok(SigningKey::from_bytes(&decode_bytes(&self.signing_key)?))
+    }
+}
+
+pub(crate) struct SharedSecret {
+    key: [u8; 32],
+    associated_data: Vec<u8>,
+}
+
+impl SharedSecret {
+    fn derive(dh: &[u8], associated_data: Vec<u8>) -> Result<Self, PacketError> {
+        let mut ikm = vec![0xff; 32];
+        ikm.extend_from_slice(dh);
+
+        let mut key = [0; 32];
+        expand(Some(&[0; 32]), &ikm, X3DH_INFO, &mut key)?;
+
+        Ok(Self {
+            key,
+            associated_data,
+        })
+    }
+
+    pub(crate) fn key(&self) -> &[u8; 32] {
+        &self.key
+    }
+
+    pub(crate) fn associated_data(&self) -> &[u8] {
+        &self.associated_data
+    }
+}
+
+fn prekey_message(identity_key: &str, signed_prekey: &str) -> Vec<u8> {
+    let mut message = Vec::new();
+    message.extend_from_slice(identity_key.as_bytes());
+    message.push(b':');
+    message.extend_from_slice(signed_prekey.as_bytes());
+
+    message
+}
+
+fn associated_data(initiator: &str, responder: &str) -> Vec<u8> {
+    let mut associated_data = Vec::new();
+    associated_data.extend_from_slice(initiator.as_bytes());
+    associated_data.push(b':');
+    associated_data.extend_from_slice(responder.as_bytes());
+
+    associated_data
+}
+
+pub(crate) fn expand(
+    salt: Option<&[u8]>,
+    ikm: &[u8],
+    info: &[u8],
+    okm: &mut [u8],
+) -> Result<(), PacketError> {
+    match Hkdf::<Sha256>::new(salt, ikm).expand(info, okm) {
+        Ok(()) => Ok(()),
+        Err(err) => Err(PacketError {
+            kind: PacketErrorKind::Crypto,
+            message: err.to_string(),
+        }),
+    }
+}
+
+pub(crate) fn aead_seal(
+    key: &[u8; 32],
+    nonce: &[u8],
+    plaintext: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, PacketError> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+
+    let payload = Payload {
+        msg: plaintext,
+        aad,
+    };
+
+    match cipher.encrypt(Nonce::from_slice(nonce), payload) {
+        Ok(ciphertext) => Ok(ciphertext),
+        Err(err) => Err(PacketError {
+            kind: PacketErrorKind::Crypto,
+            message: err.to_string(),
+        }),
+    }
+}
+
+pub(crate) fn aead_open(
+    key: &[u8; 32],
+    nonce: &[u8],
+    ciphertext: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, PacketError> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+
+    let payload = Payload {
+        msg: ciphertext,
+        aad,
+    };
+
+    match cipher.decrypt(Nonce::from_slice(nonce), payload) {
+        Ok(plaintext) => Ok(plaintext),
+        Err(_) => Err(PacketError {
+            kind: PacketErrorKind::Crypto,
+            message: String::from("Failed to decrypt message"),
+        }),
+    }
+}
+
+pub(crate) fn decode_vec(value: &str) -> Result<Vec<u8>, PacketError> {
+    match STANDARD.decode(value) {
+        Ok(bytes) => Ok(bytes),
+        Err(err) => Err(PacketError {
+            kind: PacketErrorKind::Crypto,
+            message: err.to_string(),
+        }),
+    }
+}
+
+pub(crate) fn decode_bytes(value: &str) -> Result<[u8; 32], PacketError> {
+    match decode_vec(value)?.try_into() {
+        Ok(bytes) => Ok(bytes),
+        Err(_) => Err(PacketError {
+            kind: PacketErrorKind::Crypto,
+            message: String::from("Key must be 32 bytes"),
+        }),
+    }
+}
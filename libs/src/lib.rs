@@ -1,3 +1,4 @@
+pub mod crypto;
 mod manager;
 mod models;
 pub mod packet;
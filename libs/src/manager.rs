@@ -1,116 +1,718 @@
 pub mod packet_manager {
     use std::{
-        io::{Read, Write},
-        net::TcpStream,
+        collections::VecDeque,
+        io::{self, Read, Write},
+        net::{Shutdown, TcpStream},
+        sync::{Arc, Condvar, Mutex, MutexGuard},
+        task::Waker,
+        time::Duration,
     };
 
-    use crate::packet::{DataPacket, PacketError};
+    #[cfg(unix)]
+    use std::os::unix::net::UnixStream;
 
-    pub fn send_packet(stream: &mut TcpStream, packet: DataPacket) -> Result<(), PacketError> {
-        let buf = match packet.buf() {
-            Ok(buf) => buf,
-            Err(err) => {
+    #[cfg(feature = "tokio")]
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+    #[cfg(feature = "tokio")]
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use time::OffsetDateTime;
+
+    use crate::crypto::signing::{PacketSigner, PacketVerifier};
+    use crate::crypto::transport::TransportCipher;
+    use crate::packet::{
+        Codec, DataPacket, PacketError, PacketErrorKind, PacketSignature, PacketType,
+    };
+
+    pub const FRAME_MAGIC: [u8; 2] = *b"SC";
+    pub const PROTOCOL_VERSION: u8 = 1;
+    pub const HEADER_SIZE: usize = 8;
+    pub const DEFAULT_MAX_FRAME_SIZE: u32 = 16 * 1024 * 1024;
+
+    const LEGACY_START: u8 = 2;
+
+    pub struct FrameHeader {
+        pub version: u8,
+        pub flags: u8,
+        pub length: u32,
+    }
+
+    impl FrameHeader {
+        pub fn new(flags: u8, length: u32) -> Self {
+            Self {
+                version: PROTOCOL_VERSION,
+                flags,
+                length,
+            }
+        }
+
+        pub fn encode(&self) -> [u8; HEADER_SIZE] {
+            let mut buf = [0; HEADER_SIZE];
+            buf[..2].copy_from_slice(&FRAME_MAGIC);
+            buf[2] = self.version;
+            buf[3] = self.flags;
+            buf[4..].copy_from_slice(&self.length.to_be_bytes());
+
+            buf
+        }
+
+        pub fn decode(buf: &[u8; HEADER_SIZE], max_frame_size: u32) -> Result<Self, PacketError> {
+            if buf[0] == LEGACY_START {
                 return Err(PacketError {
-                    message: err.to_string(),
-                })
+                    kind: PacketErrorKind::LegacyFraming,
+                    message: String::from("Peer uses legacy STX/ETX framing"),
+                });
+            }
+
+            if buf[..2] != FRAME_MAGIC {
+                return Err(PacketError {
+                    kind: PacketErrorKind::Malformed,
+                    message: String::from("Bad Packet"),
+                });
+            }
+
+            if buf[2] != PROTOCOL_VERSION {
+                return Err(PacketError {
+                    kind: PacketErrorKind::UnsupportedVersion,
+                    message: format!("Unsupported protocol version {}", buf[2]),
+                });
             }
-        };
 
-        let buf = buf.as_bytes();
+            let length = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]);
 
-        let start: [u8; 1] = [2];
-        let end: [u8; 1] = [3];
+            if length > max_frame_size {
+                return Err(PacketError {
+                    kind: PacketErrorKind::FrameTooLarge,
+                    message: format!(
+                        "Frame of {} bytes exceeds limit of {} bytes",
+                        length, max_frame_size
+                    ),
+                });
+            }
 
-        if let Err(err) = stream.write(&start) {
-            return Err(PacketError {
-                message: err.to_string(),
-            });
+            Ok(Self {
+                version: buf[2],
+                flags: buf[3],
+                length,
+            })
         }
+    }
 
-        if let Err(err) = stream.write(buf) {
-            return Err(PacketError {
-                message: err.to_string(),
-            });
+    /// A byte stream packets can be framed over. Besides reading and writing,
+    /// a transport must hand out a second handle for a reader thread and be
+    /// closable from either handle.
+    pub trait Transport: Read + Write + Send {
+        fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>>;
+
+        fn close(&self);
+
+        fn set_read_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+            Ok(())
         }
+    }
 
-        if let Err(err) = stream.write(&end) {
-            return Err(PacketError {
-                message: err.to_string(),
-            });
+    impl Transport for TcpStream {
+        fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>> {
+            Ok(Box::new(self.try_clone()?))
+        }
+
+        fn close(&self) {
+            let _ = self.shutdown(Shutdown::Both);
+        }
+
+        fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+            TcpStream::set_read_timeout(self, timeout)
+        }
+    }
+
+    #[cfg(unix)]
+    impl Transport for UnixStream {
+        fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>> {
+            Ok(Box::new(self.try_clone()?))
+        }
+
+        fn close(&self) {
+            let _ = self.shutdown(Shutdown::Both);
+        }
+
+        fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+            UnixStream::set_read_timeout(self, timeout)
+        }
+    }
+
+    /// A connection to a peer over any `Transport`.
+    pub struct Stream {
+        inner: Box<dyn Transport>,
+    }
+
+    impl Stream {
+        pub fn new(transport: impl Transport + 'static) -> Self {
+            Self {
+                inner: Box::new(transport),
+            }
+        }
+
+        pub fn try_clone(&self) -> io::Result<Self> {
+            Ok(Self {
+                inner: self.inner.try_clone_transport()?,
+            })
+        }
+
+        pub fn shutdown(&self) {
+            self.inner.close();
+        }
+
+        pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+            self.inner.set_read_timeout(timeout)
+        }
+    }
+
+    impl<T: Transport + 'static> From<T> for Stream {
+        fn from(transport: T) -> Self {
+            Self::new(transport)
+        }
+    }
+
+    impl Read for Stream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.inner.read(buf)
+        }
+    }
+
+    impl Write for Stream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    /// Connected in-process transports: bytes written to one end are read
+    /// from the other. Closing either end, or dropping its last handle,
+    /// closes both directions.
+    pub fn duplex() -> (Duplex, Duplex) {
+        let left = Arc::new(Pipe::default());
+        let right = Arc::new(Pipe::default());
+
+        (
+            Duplex {
+                inbound: Arc::clone(&left),
+                outbound: Arc::clone(&right),
+                handles: Arc::new(()),
+            },
+            Duplex {
+                inbound: right,
+                outbound: left,
+                handles: Arc::new(()),
+            },
+        )
+    }
+
+    pub struct Duplex {
+        inbound: Arc<Pipe>,
+        outbound: Arc<Pipe>,
+        handles: Arc<()>,
+    }
+
+    #[derive(Default)]
+    struct Pipe {
+        state: Mutex<PipeState>,
+        ready: Condvar,
+    }
+
+    #[derive(Default)]
+    struct PipeState {
+        buf: VecDeque<u8>,
+        closed: bool,
+        waker: Option<Waker>,
+    }
+
+    impl Pipe {
+        fn lock(&self) -> MutexGuard<'_, PipeState> {
+            match self.state.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner(),
+            }
+        }
+
+        fn notify(&self, state: &mut PipeState) {
+            self.ready.notify_all();
+
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+
+        fn push(&self, buf: &[u8]) -> io::Result<usize> {
+            let mut state = self.lock();
+
+            if state.closed {
+                return Err(io::Error::from(io::ErrorKind::BrokenPipe));
+            }
+
+            state.buf.extend(buf);
+            self.notify(&mut state);
+
+            Ok(buf.len())
+        }
+
+        fn close(&self) {
+            let mut state = self.lock();
+
+            state.closed = true;
+            self.notify(&mut state);
+        }
+    }
+
+    fn drain(state: &mut PipeState, buf: &mut [u8]) -> usize {
+        let read = buf.len().min(state.buf.len());
+        for (slot, byte) in buf.iter_mut().zip(state.buf.drain(..read)) {
+            *slot = byte;
+        }
+
+        read
+    }
+
+    impl Duplex {
+        fn try_clone(&self) -> Self {
+            Self {
+                inbound: Arc::clone(&self.inbound),
+                outbound: Arc::clone(&self.outbound),
+                handles: Arc::clone(&self.handles),
+            }
+        }
+    }
+
+    impl Drop for Duplex {
+        fn drop(&mut self) {
+            if Arc::strong_count(&self.handles) == 1 {
+                self.close();
+            }
+        }
+    }
+
+    impl Read for Duplex {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut state = self.inbound.lock();
+
+            while state.buf.is_empty() && !state.closed {
+                state = match self.inbound.ready.wait(state) {
+                    Ok(guard) => guard,
+                    Err(poisoned) => poisoned.into_inner(),
+                };
+            }
+
+            Ok(drain(&mut state, buf))
+        }
+    }
+
+    impl Write for Duplex {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.outbound.push(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Transport for Duplex {
+        fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>> {
+            Ok(Box::new(self.try_clone()))
+        }
+
+        fn close(&self) {
+            self.inbound.close();
+            self.outbound.close();
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    impl AsyncRead for Duplex {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let mut state = self.inbound.lock();
+
+            if state.buf.is_empty() && !state.closed {
+                state.waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+
+            let read = drain(&mut state, buf.initialize_unfilled());
+            buf.advance(read);
+
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    impl AsyncWrite for Duplex {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Poll::Ready(self.outbound.push(buf))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Transport::close(&*self);
+
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    pub fn send_packet<W>(stream: &mut W, packet: DataPacket) -> Result<(), PacketError>
+    where
+        W: Write + ?Sized,
+    {
+        let frame = encode_frame(packet)?;
+
+        if let Err(err) = stream.write_all(&frame) {
+            return Err(io_error(err));
         }
 
         if let Err(err) = stream.flush() {
-            return Err(PacketError {
-                message: err.to_string(),
-            });
+            return Err(io_error(err));
+        }
+
+        Ok(())
+    }
+
+    pub fn recv_packet<R>(stream: &mut R) -> Result<DataPacket, PacketError>
+    where
+        R: Read + ?Sized,
+    {
+        recv_packet_with_limit(stream, DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    pub fn recv_packet_with_limit<R>(
+        stream: &mut R,
+        max_frame_size: u32,
+    ) -> Result<DataPacket, PacketError>
+    where
+        R: Read + ?Sized,
+    {
+        let mut header = [0; HEADER_SIZE];
+        if let Err(err) = stream.read_exact(&mut header) {
+            return Err(io_error(err));
+        }
+
+        let header = FrameHeader::decode(&header, max_frame_size)?;
+
+        // the buffer grows with the bytes that arrive, not the claimed length
+        let mut buf = Vec::new();
+        if let Err(err) = Read::take(&mut *stream, header.length as u64).read_to_end(&mut buf) {
+            return Err(io_error(err));
+        }
+
+        check_length(&header, &buf)?;
+        decode_body(&header, buf)
+    }
+
+    #[cfg(feature = "tokio")]
+    pub async fn send_packet_async<W>(stream: &mut W, packet: DataPacket) -> Result<(), PacketError>
+    where
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        let frame = encode_frame(packet)?;
+
+        if let Err(err) = stream.write_all(&frame).await {
+            return Err(io_error(err));
+        }
+
+        if let Err(err) = stream.flush().await {
+            return Err(io_error(err));
         }
 
         Ok(())
     }
 
-    pub fn recv_packet(stream: &mut TcpStream) -> Result<DataPacket, PacketError> {
-        let mut start: [u8; 1] = [0];
-        if let Err(err) = stream.read_exact(&mut start) {
+    #[cfg(feature = "tokio")]
+    pub async fn recv_packet_async<R>(
+        stream: &mut R,
+        max_frame_size: u32,
+    ) -> Result<DataPacket, PacketError>
+    where
+        R: AsyncRead + Unpin + ?Sized,
+    {
+        let mut header = [0; HEADER_SIZE];
+        if let Err(err) = stream.read_exact(&mut header).await {
+            return Err(io_error(err));
+        }
+
+        let header = FrameHeader::decode(&header, max_frame_size)?;
+
+        let mut buf = Vec::new();
+        if let Err(err) = AsyncReadExt::take(&mut *stream, header.length as u64)
+            .read_to_end(&mut buf)
+            .await
+        {
+            return Err(io_error(err));
+        }
+
+        check_length(&header, &buf)?;
+        decode_body(&header, buf)
+    }
+
+    fn encode_frame(packet: DataPacket) -> Result<Vec<u8>, PacketError> {
+        let codec = packet.get_codec();
+        let buf = codec.encode(&packet)?;
+
+        let length = match u32::try_from(buf.len()) {
+            Ok(length) => length,
+            Err(_) => {
+                return Err(PacketError {
+                    kind: PacketErrorKind::FrameTooLarge,
+                    message: String::from("Packet does not fit in a frame"),
+                })
+            }
+        };
+
+        let mut frame = Vec::with_capacity(HEADER_SIZE + buf.len());
+        frame.extend_from_slice(&FrameHeader::new(codec.flags(), length).encode());
+        frame.extend_from_slice(&buf);
+
+        Ok(frame)
+    }
+
+    fn decode_body(header: &FrameHeader, buf: Vec<u8>) -> Result<DataPacket, PacketError> {
+        Codec::from_flags(header.flags)?.decode(&buf)
+    }
+
+    fn check_length(header: &FrameHeader, buf: &[u8]) -> Result<(), PacketError> {
+        if buf.len() == header.length as usize {
+            return Ok(());
+        }
+
+        Err(io_error(io::Error::from(io::ErrorKind::UnexpectedEof)))
+    }
+
+    fn io_error(err: io::Error) -> PacketError {
+        PacketError {
+            kind: PacketErrorKind::Io,
+            message: err.to_string(),
+        }
+    }
+
+    pub fn sign_packet(
+        mut packet: DataPacket,
+        signer: &mut PacketSigner,
+    ) -> Result<DataPacket, PacketError> {
+        let seq = signer.next_seq();
+        let timestamp = OffsetDateTime::now_utc().unix_timestamp();
+
+        packet.set_envelope(seq, timestamp);
+
+        let value = signer.sign(&packet.signing_bytes());
+
+        packet.set_signature(PacketSignature {
+            key: signer.public_key(),
+            value,
+        });
+
+        Ok(packet)
+    }
+
+    pub fn check_signed_packet(
+        packet: &DataPacket,
+        verifier: &mut PacketVerifier,
+    ) -> Result<(), PacketError> {
+        let signature = match packet.get_signature() {
+            Some(signature) => signature,
+            None => {
+                return Err(PacketError {
+                    kind: PacketErrorKind::Unsigned,
+                    message: String::from("Packet is not signed"),
+                })
+            }
+        };
+
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        if (now - packet.get_timestamp()).abs() > verifier.max_skew() {
             return Err(PacketError {
-                message: err.to_string(),
+                kind: PacketErrorKind::Replay,
+                message: String::from("Packet timestamp outside allowed window"),
             });
         }
 
-        if start[0] != 2 {
+        verifier.verify(
+            &signature.key,
+            &signature.value,
+            &packet.signing_bytes(),
+            packet.get_seq(),
+        )
+    }
+
+    /// Seals `packet` in its own codec. JSON carries the sealed bytes as
+    /// base64 text; MessagePack carries them as they are.
+    pub fn encrypt_packet(
+        packet: DataPacket,
+        cipher: &mut TransportCipher,
+    ) -> Result<DataPacket, PacketError> {
+        let codec = packet.get_codec();
+        let sealed = cipher.seal(&codec.encode(&packet)?)?;
+
+        let data = match codec {
+            Codec::Json => STANDARD.encode(sealed).into_bytes(),
+            Codec::MessagePack => sealed,
+        };
+
+        Ok(DataPacket::with_body(PacketType::Encrypted, data, codec))
+    }
+
+    pub fn decrypt_packet(
+        packet: DataPacket,
+        cipher: &mut TransportCipher,
+    ) -> Result<DataPacket, PacketError> {
+        if !matches!(packet.get_type(), PacketType::Encrypted) {
             return Err(PacketError {
-                message: String::from("Bad Packet"),
+                kind: PacketErrorKind::Handshake,
+                message: String::from("Plaintext packet on encrypted channel"),
             });
         }
 
-        let mut buf = Vec::new();
-
-        while let Some(data) = stream.bytes().next() {
-            match data {
-                Ok(byte) => {
-                    if byte == 3 {
-                        break;
-                    }
+        let codec = packet.get_codec();
 
-                    buf.push(byte);
-                }
+        let opened = match codec {
+            Codec::Json => match STANDARD.decode(packet.get_body()) {
+                Ok(sealed) => cipher.open(&sealed)?,
                 Err(err) => {
                     return Err(PacketError {
+                        kind: PacketErrorKind::Malformed,
                         message: err.to_string(),
-                    });
+                    })
                 }
+            },
+            Codec::MessagePack => cipher.open(packet.get_body())?,
+        };
+
+        codec.decode(&opened)
+    }
+
+    /// The transport cipher state of one side of a connection, kept apart from
+    /// the stream so callers doing their own I/O can seal and open packets.
+    #[derive(Default)]
+    pub struct Encryption {
+        cipher: Option<TransportCipher>,
+    }
+
+    impl Encryption {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn seal(&mut self, packet: DataPacket) -> Result<DataPacket, PacketError> {
+            match &mut self.cipher {
+                Some(cipher) => encrypt_packet(packet, cipher),
+                None => Ok(packet),
             }
         }
 
-        let buf = match String::from_utf8(buf) {
-            Ok(buf) => buf,
-            Err(err) => {
-                return Err(PacketError {
-                    message: err.to_string(),
-                });
+        pub fn open(&mut self, packet: DataPacket) -> Result<DataPacket, PacketError> {
+            match &mut self.cipher {
+                Some(cipher) => decrypt_packet(packet, cipher),
+                None => Ok(packet),
             }
-        };
+        }
 
-        match DataPacket::new(buf) {
-            Ok(packet) => Ok(packet),
-            Err(err) => Err(PacketError {
-                message: err.to_string(),
-            }),
+        pub fn set_cipher(&mut self, cipher: TransportCipher) {
+            self.cipher = Some(cipher);
         }
-    }
 
-    pub fn sign_packet(packet: DataPacket) -> Result<DataPacket, PacketError> {
-        todo!()
-    }
+        pub fn rekey(&mut self, secret: &[u8; 32]) -> Result<(), PacketError> {
+            match &mut self.cipher {
+                Some(cipher) => cipher.rekey(secret),
+                None => Err(PacketError {
+                    kind: PacketErrorKind::Handshake,
+                    message: String::from("Key Exchange Required"),
+                }),
+            }
+        }
 
-    pub fn check_signed_packet(packet: DataPacket) -> Result<(), PacketError> {
-        todo!()
+        pub fn is_encrypted(&self) -> bool {
+            self.cipher.is_some()
+        }
     }
 
-    pub fn encrypt_packet(packet: DataPacket) -> Result<DataPacket, PacketError> {
-        todo!()
+    pub struct Channel {
+        stream: Stream,
+        encryption: Encryption,
+        codec: Codec,
     }
 
-    pub fn decrypt_packet(packet: DataPacket) -> Result<DataPacket, PacketError> {
-        todo!()
+    impl Channel {
+        pub fn new(stream: impl Into<Stream>) -> Self {
+            Self {
+                stream: stream.into(),
+                encryption: Encryption::new(),
+                codec: Codec::default(),
+            }
+        }
+
+        /// Sends every packet in `codec`. The server answers in the codec of
+        /// the first packet it receives, so set this before sending anything.
+        pub fn with_codec(mut self, codec: Codec) -> Self {
+            self.codec = codec;
+            self
+        }
+
+        pub fn get_codec(&self) -> Codec {
+            self.codec
+        }
+
+        pub fn send(&mut self, packet: DataPacket) -> Result<(), PacketError> {
+            let packet = packet.transcode(self.codec)?;
+            let packet = self.encryption.seal(packet)?;
+
+            send_packet(&mut self.stream, packet)
+        }
+
+        pub fn recv(&mut self) -> Result<DataPacket, PacketError> {
+            let packet = recv_packet(&mut self.stream)?;
+
+            self.open(packet)
+        }
+
+        pub fn open(&mut self, packet: DataPacket) -> Result<DataPacket, PacketError> {
+            self.encryption.open(packet)
+        }
+
+        pub fn try_clone_stream(&self) -> Result<Stream, PacketError> {
+            match self.stream.try_clone() {
+                Ok(stream) => Ok(stream),
+                Err(err) => Err(io_error(err)),
+            }
+        }
+
+        pub fn shutdown(&self) {
+            self.stream.shutdown();
+        }
+
+        pub fn set_cipher(&mut self, cipher: TransportCipher) {
+            self.encryption.set_cipher(cipher);
+        }
+
+        pub fn rekey(&mut self, secret: &[u8; 32]) -> Result<(), PacketError> {
+            self.encryption.rekey(secret)
+        }
+
+        pub fn is_encrypted(&self) -> bool {
+            self.encryption.is_encrypted()
+        }
     }
 }
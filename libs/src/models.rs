@@ -2,10 +2,10 @@ pub mod base {
     use std::collections::HashMap;
 
     use serde::{Deserialize, Serialize};
-    use time::PrimitiveDateTime;
+    use time::{OffsetDateTime, PrimitiveDateTime};
     use uuid::Uuid;
 
-    #[derive(Serialize, Deserialize)]
+    #[derive(Serialize, Deserialize, Clone)]
     pub struct User {
         name: String,
         username: String,
@@ -30,10 +30,11 @@ pub mod base {
         }
 
         pub fn get_hash(self) -> (String, Vec<(u8, String)>) {
-            let mut key_pairs = Vec::new();
-            key_pairs.push((0, self.name));
-            key_pairs.push((1, self.username.clone()));
-            key_pairs.push((2, self.password));
+            let key_pairs = vec![
+                (0, self.name),
+                (1, self.username.clone()),
+                (2, self.password),
+            ];
 
             (self.username, key_pairs)
         }
@@ -51,11 +52,35 @@ pub mod base {
         }
 
         pub fn get_key(self) -> String {
-            return self.username;
+            self.username
+        }
+
+        pub fn get_username(&self) -> String {
+            self.username.clone()
+        }
+
+        pub fn get_name(&self) -> String {
+            self.name.clone()
+        }
+
+        pub fn get_password(&self) -> String {
+            self.password.clone()
+        }
+
+        pub fn with_password(self, password: String) -> Self {
+            Self { password, ..self }
+        }
+
+        pub fn redact(self) -> Self {
+            Self {
+                name: self.name,
+                username: self.username,
+                password: String::new(),
+            }
         }
     }
 
-    #[derive(Serialize, Deserialize)]
+    #[derive(Serialize, Deserialize, Clone)]
     pub struct Message {
         id: Uuid,
         member: Member,
@@ -63,40 +88,676 @@ pub mod base {
         created_at: PrimitiveDateTime,
     }
 
-    #[derive(Serialize, Deserialize)]
+    impl Message {
+        pub fn new(member: Member, body: String) -> Self {
+            let now = OffsetDateTime::now_utc();
+
+            Self {
+                id: Uuid::nil(),
+                member,
+                body,
+                created_at: PrimitiveDateTime::new(now.date(), now.time()),
+            }
+        }
+
+        pub fn with_id(self, id: Uuid) -> Self {
+            Self { id, ..self }
+        }
+
+        pub fn with_body(self, body: String) -> Self {
+            Self { body, ..self }
+        }
+
+        pub fn get_id(&self) -> Uuid {
+            self.id
+        }
+
+        pub fn get_member(&self) -> &Member {
+            &self.member
+        }
+
+        pub fn get_body(&self) -> String {
+            self.body.clone()
+        }
+
+        pub fn get_created_at(&self) -> PrimitiveDateTime {
+            self.created_at
+        }
+
+        pub fn into_member(self) -> Member {
+            self.member
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone)]
     pub struct Group {
         id: Uuid,
         name: String,
+        #[serde(default)]
+        owner: String,
+        #[serde(default)]
+        admins: Vec<String>,
+        #[serde(default)]
+        members: Vec<String>,
     }
 
-    #[derive(Serialize, Deserialize)]
+    impl Group {
+        pub fn new(name: String) -> Self {
+            Self {
+                id: Uuid::nil(),
+                name,
+                owner: String::new(),
+                admins: Vec::new(),
+                members: Vec::new(),
+            }
+        }
+
+        pub fn full(
+            id: Uuid,
+            name: String,
+            owner: String,
+            admins: Vec<String>,
+            members: Vec<String>,
+        ) -> Self {
+            Self {
+                id,
+                name,
+                owner,
+                admins,
+                members,
+            }
+        }
+
+        pub fn get_id(&self) -> Uuid {
+            self.id
+        }
+
+        pub fn get_name(&self) -> String {
+            self.name.clone()
+        }
+
+        pub fn get_owner(&self) -> String {
+            self.owner.clone()
+        }
+
+        pub fn get_admins(&self) -> Vec<String> {
+            self.admins.clone()
+        }
+
+        pub fn get_members(&self) -> Vec<String> {
+            self.members.clone()
+        }
+
+        pub fn is_admin(&self, username: &str) -> bool {
+            if username.is_empty() {
+                return false;
+            }
+
+            self.owner == username || self.admins.iter().any(|admin| admin == username)
+        }
+
+        pub fn is_member(&self, username: &str) -> bool {
+            self.is_admin(username) || self.members.iter().any(|member| member == username)
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone)]
     pub struct Member {
         group: Group,
         user: User,
     }
+
+    impl Member {
+        pub fn new(group: Group, user: User) -> Self {
+            Self { group, user }
+        }
+
+        pub fn get_group(&self) -> &Group {
+            &self.group
+        }
+
+        pub fn get_user(&self) -> &User {
+            &self.user
+        }
+    }
 }
 
 pub mod packet {
+    use std::collections::HashMap;
+
     use crate::models::base::*;
     use serde::{Deserialize, Serialize};
     use uuid::Uuid;
 
+    #[derive(Serialize, Deserialize)]
+    pub struct PubKey {
+        public_key: String,
+    }
+
+    impl PubKey {
+        pub fn new(public_key: String) -> Self {
+            Self { public_key }
+        }
+
+        pub fn get_key(&self) -> String {
+            self.public_key.clone()
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct SrpRegistration {
+        user: User,
+        salt: String,
+        verifier: String,
+    }
+
+    impl SrpRegistration {
+        pub fn new(user: User, salt: String, verifier: String) -> Self {
+            Self {
+                user,
+                salt,
+                verifier,
+            }
+        }
+
+        pub fn get_user(&self) -> &User {
+            &self.user
+        }
+
+        pub fn get_salt(&self) -> String {
+            self.salt.clone()
+        }
+
+        pub fn get_verifier(&self) -> String {
+            self.verifier.clone()
+        }
+
+        pub fn into_user(self) -> User {
+            self.user
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct SrpStart {
+        username: String,
+        public_key: String,
+    }
+
+    impl SrpStart {
+        pub fn new(username: String, public_key: String) -> Self {
+            Self {
+                username,
+                public_key,
+            }
+        }
+
+        pub fn get_username(&self) -> String {
+            self.username.clone()
+        }
+
+        pub fn get_public_key(&self) -> String {
+            self.public_key.clone()
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct SrpChallenge {
+        salt: String,
+        public_key: String,
+    }
+
+    impl SrpChallenge {
+        pub fn new(salt: String, public_key: String) -> Self {
+            Self { salt, public_key }
+        }
+
+        pub fn get_salt(&self) -> String {
+            self.salt.clone()
+        }
+
+        pub fn get_public_key(&self) -> String {
+            self.public_key.clone()
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct SrpProof {
+        proof: String,
+    }
+
+    impl SrpProof {
+        pub fn new(proof: String) -> Self {
+            Self { proof }
+        }
+
+        pub fn get_proof(&self) -> String {
+            self.proof.clone()
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct SrpVerify {
+        user: User,
+        proof: String,
+        #[serde(default)]
+        token: Option<SessionToken>,
+    }
+
+    impl SrpVerify {
+        pub fn new(user: User, proof: String, token: Option<SessionToken>) -> Self {
+            Self { user, proof, token }
+        }
+
+        pub fn get_proof(&self) -> String {
+            self.proof.clone()
+        }
+
+        pub fn take_token(&mut self) -> Option<SessionToken> {
+            self.token.take()
+        }
+
+        pub fn into_user(self) -> User {
+            self.user
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct SessionToken {
+        token: String,
+        expires_at: i64,
+    }
+
+    impl SessionToken {
+        pub fn new(token: String, expires_at: i64) -> Self {
+            Self { token, expires_at }
+        }
+
+        pub fn get_token(&self) -> String {
+            self.token.clone()
+        }
+
+        pub fn get_expires_at(&self) -> i64 {
+            self.expires_at
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct KeyExchange {
+        ephemeral_key: String,
+        static_key: String,
+    }
+
+    impl KeyExchange {
+        pub fn new(ephemeral_key: String, static_key: String) -> Self {
+            Self {
+                ephemeral_key,
+                static_key,
+            }
+        }
+
+        pub fn get_ephemeral_key(&self) -> String {
+            self.ephemeral_key.clone()
+        }
+
+        pub fn get_static_key(&self) -> String {
+            self.static_key.clone()
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct PreKey {
+        id: u32,
+        key: String,
+    }
+
+    impl PreKey {
+        pub fn new(id: u32, key: String) -> Self {
+            Self { id, key }
+        }
+
+        pub fn get_id(&self) -> u32 {
+            self.id
+        }
+
+        pub fn get_key(&self) -> String {
+            self.key.clone()
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct KeyBundle {
+        identity_key: String,
+        signing_key: String,
+        signed_prekey: PreKey,
+        signature: String,
+        one_time_prekeys: Vec<PreKey>,
+    }
+
+    impl KeyBundle {
+        pub fn new(
+            identity_key: String,
+            signing_key: String,
+            signed_prekey: PreKey,
+            signature: String,
+            one_time_prekeys: Vec<PreKey>,
+        ) -> Self {
+            Self {
+                identity_key,
+                signing_key,
+                signed_prekey,
+                signature,
+                one_time_prekeys,
+            }
+        }
+
+        pub fn split(self) -> (PreKeyBundle, Vec<PreKey>) {
+            let bundle = PreKeyBundle {
+                identity_key: self.identity_key,
+                signing_key: self.signing_key,
+                signed_prekey: self.signed_prekey,
+                signature: self.signature,
+                one_time_prekey: None,
+            };
+
+            (bundle, self.one_time_prekeys)
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct PreKeyBundle {
+        identity_key: String,
+        signing_key: String,
+        signed_prekey: PreKey,
+        signature: String,
+        one_time_prekey: Option<PreKey>,
+    }
+
+    impl PreKeyBundle {
+        pub fn with_one_time_prekey(self, one_time_prekey: Option<PreKey>) -> Self {
+            Self {
+                one_time_prekey,
+                ..self
+            }
+        }
+
+        pub fn get_identity_key(&self) -> String {
+            self.identity_key.clone()
+        }
+
+        pub fn get_signing_key(&self) -> String {
+            self.signing_key.clone()
+        }
+
+        pub fn get_signed_prekey(&self) -> PreKey {
+            self.signed_prekey.clone()
+        }
+
+        pub fn get_signature(&self) -> String {
+            self.signature.clone()
+        }
+
+        pub fn get_one_time_prekey(&self) -> Option<PreKey> {
+            self.one_time_prekey.clone()
+        }
+    }
+
     #[derive(Serialize, Deserialize)]
     pub struct E2E {
         public_key: String,
         user: User,
+        ephemeral_key: String,
+        signed_prekey: u32,
+        one_time_prekey: Option<u32>,
+        ciphertext: String,
+    }
+
+    impl E2E {
+        pub fn new(
+            public_key: String,
+            user: User,
+            ephemeral_key: String,
+            signed_prekey: u32,
+            one_time_prekey: Option<u32>,
+            ciphertext: String,
+        ) -> Self {
+            Self {
+                public_key,
+                user,
+                ephemeral_key,
+                signed_prekey,
+                one_time_prekey,
+                ciphertext,
+            }
+        }
+
+        pub fn get_public_key(&self) -> String {
+            self.public_key.clone()
+        }
+
+        pub fn get_user(&self) -> &User {
+            &self.user
+        }
+
+        pub fn set_user(&mut self, user: User) {
+            self.user = user;
+        }
+
+        pub fn get_ephemeral_key(&self) -> String {
+            self.ephemeral_key.clone()
+        }
+
+        pub fn get_signed_prekey(&self) -> u32 {
+            self.signed_prekey
+        }
+
+        pub fn get_one_time_prekey(&self) -> Option<u32> {
+            self.one_time_prekey
+        }
+
+        pub fn get_ciphertext(&self) -> String {
+            self.ciphertext.clone()
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct E2EInbox {
+        sessions: Vec<E2E>,
+    }
+
+    impl E2EInbox {
+        pub fn new(sessions: Vec<E2E>) -> Self {
+            Self { sessions }
+        }
+
+        pub fn get(self) -> Vec<E2E> {
+            self.sessions
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct SenderKey {
+        group: Uuid,
+        user: User,
+        session: Option<E2E>,
+        ciphertext: Option<String>,
+    }
+
+    impl SenderKey {
+        pub fn new(
+            group: Uuid,
+            user: User,
+            session: Option<E2E>,
+            ciphertext: Option<String>,
+        ) -> Self {
+            Self {
+                group,
+                user,
+                session,
+                ciphertext,
+            }
+        }
+
+        pub fn get_group(&self) -> Uuid {
+            self.group
+        }
+
+        pub fn get_user(&self) -> &User {
+            &self.user
+        }
+
+        pub fn set_user(&mut self, user: User) {
+            if let Some(session) = &mut self.session {
+                session.set_user(User::full(
+                    user.get_name(),
+                    user.get_username(),
+                    String::new(),
+                ));
+            }
+
+            self.user = user;
+        }
+
+        pub fn get_session(&self) -> Option<&E2E> {
+            self.session.as_ref()
+        }
+
+        pub fn get_ciphertext(&self) -> Option<String> {
+            self.ciphertext.clone()
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct SenderKeyInbox {
+        sender_keys: Vec<SenderKey>,
+    }
+
+    impl SenderKeyInbox {
+        pub fn new(sender_keys: Vec<SenderKey>) -> Self {
+            Self { sender_keys }
+        }
+
+        pub fn get(self) -> Vec<SenderKey> {
+            self.sender_keys
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct UserSearch {
+        query: String,
+    }
+
+    impl UserSearch {
+        pub fn new(query: String) -> Self {
+            Self { query }
+        }
+
+        pub fn get_query(&self) -> String {
+            self.query.clone()
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct Users {
+        users: Vec<User>,
+    }
+
+    impl Users {
+        pub fn new(users: Vec<User>) -> Self {
+            Self { users }
+        }
+
+        pub fn get(self) -> Vec<User> {
+            self.users
+        }
     }
 
     #[derive(Serialize, Deserialize)]
     pub struct Chats {
         is_new: bool,
         groups: Vec<Group>,
+        #[serde(default)]
+        unread: HashMap<Uuid, u64>,
+    }
+
+    impl Chats {
+        pub fn new(groups: Vec<Group>, unread: HashMap<Uuid, u64>) -> Self {
+            Self {
+                is_new: unread.values().any(|count| *count > 0),
+                groups,
+                unread,
+            }
+        }
+
+        pub fn is_new(&self) -> bool {
+            self.is_new
+        }
+
+        pub fn get_groups(&self) -> &[Group] {
+            &self.groups
+        }
+
+        pub fn get_unread(&self, group: Uuid) -> u64 {
+            self.unread.get(&group).copied().unwrap_or(0)
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct GetMessages {
+        group: Uuid,
+        cursor: Option<u64>,
+        limit: u32,
+    }
+
+    impl GetMessages {
+        pub fn new(group: Uuid, cursor: Option<u64>, limit: u32) -> Self {
+            Self {
+                group,
+                cursor,
+                limit,
+            }
+        }
+
+        pub fn get_group(&self) -> Uuid {
+            self.group
+        }
+
+        pub fn get_cursor(&self) -> Option<u64> {
+            self.cursor
+        }
+
+        pub fn get_limit(&self) -> u32 {
+            self.limit
+        }
     }
 
     #[derive(Serialize, Deserialize)]
     pub struct Messages {
         group: Group,
         messages: Vec<Message>,
+        #[serde(default)]
+        next_cursor: Option<u64>,
+    }
+
+    impl Messages {
+        pub fn new(group: Group, messages: Vec<Message>, next_cursor: Option<u64>) -> Self {
+            Self {
+                group,
+                messages,
+                next_cursor,
+            }
+        }
+
+        pub fn get_group(&self) -> &Group {
+            &self.group
+        }
+
+        pub fn get_next_cursor(&self) -> Option<u64> {
+            self.next_cursor
+        }
+
+        pub fn get(self) -> Vec<Message> {
+            self.messages
+        }
     }
 
     #[derive(Serialize, Deserialize)]
@@ -106,6 +767,51 @@ pub mod packet {
         is_message: bool,
     }
 
+    impl Refresh {
+        pub fn new(id: Uuid, is_group: bool, is_message: bool) -> Self {
+            Self {
+                id,
+                is_group,
+                is_message,
+            }
+        }
+
+        pub fn get_id(&self) -> Uuid {
+            self.id
+        }
+
+        pub fn is_group(&self) -> bool {
+            self.is_group
+        }
+
+        pub fn is_message(&self) -> bool {
+            self.is_message
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct Shutdown {
+        reason: String,
+        grace_period: u64,
+    }
+
+    impl Shutdown {
+        pub fn new(reason: String, grace_period: u64) -> Self {
+            Self {
+                reason,
+                grace_period,
+            }
+        }
+
+        pub fn get_reason(&self) -> String {
+            self.reason.clone()
+        }
+
+        pub fn get_grace_period(&self) -> u64 {
+            self.grace_period
+        }
+    }
+
     #[derive(Serialize, Deserialize)]
     pub struct Empty {}
 }
@@ -1,44 +1,320 @@
 use serde::{Deserialize, Serialize};
+use serde_bytes::{ByteBuf, Bytes};
 use std::fmt;
 
-#[derive(Serialize, Deserialize)]
+const SIGNING_CONTEXT: &[u8] = b"secure_chat/packet/v1";
+
+/// Frame header flag bits naming the codec of the frame body.
+const CODEC_FLAGS: u8 = 0x0f;
+
+/// How packets and their bodies are written on the wire. Frames name their
+/// codec, so either side can read both; each connection settles on the one
+/// its client sent first.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Codec {
+    /// Readable for debugging: the body is a JSON string inside a JSON packet.
+    #[default]
+    Json,
+    /// MessagePack with the body kept as raw bytes, so it is never escaped.
+    MessagePack,
+}
+
+#[derive(Clone)]
 pub struct DataPacket {
     p_type: PacketType,
-    data: String,
+    data: Vec<u8>,
+    codec: Codec,
+    seq: u64,
+    timestamp: i64,
+    request_id: u64,
+    event: bool,
+    signature: Option<PacketSignature>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PacketSignature {
+    pub key: String,
+    pub value: String,
+}
+
+/// The wire layout shared by both codecs; only the type of `data` differs.
+#[derive(Serialize, Deserialize)]
+struct Wire<D, S> {
+    p_type: PacketType,
+    data: D,
+    #[serde(default)]
+    seq: u64,
+    #[serde(default)]
+    timestamp: i64,
+    #[serde(default)]
+    request_id: u64,
+    #[serde(default)]
+    event: bool,
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    signature: Option<S>,
+}
+
+impl Codec {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "json" => Ok(Codec::Json),
+            "msgpack" | "messagepack" => Ok(Codec::MessagePack),
+            other => Err(format!("unknown codec '{}'", other)),
+        }
+    }
+
+    pub fn from_flags(flags: u8) -> Result<Self, PacketError> {
+        match flags & CODEC_FLAGS {
+            0 => Ok(Codec::Json),
+            1 => Ok(Codec::MessagePack),
+            other => Err(PacketError {
+                kind: PacketErrorKind::UnsupportedVersion,
+                message: format!("Unsupported codec {}", other),
+            }),
+        }
+    }
+
+    pub fn flags(self) -> u8 {
+        match self {
+            Codec::Json => 0,
+            Codec::MessagePack => 1,
+        }
+    }
+
+    pub fn encode_body<T: Serialize>(self, body: &T) -> Result<Vec<u8>, PacketError> {
+        match self {
+            Codec::Json => serde_json::to_vec(body).map_err(serialize_error),
+            Codec::MessagePack => to_message_pack(body),
+        }
+    }
+
+    pub fn decode_body<'a, T: Deserialize<'a>>(self, buf: &'a [u8]) -> Result<T, PacketError> {
+        match self {
+            Codec::Json => serde_json::from_slice(buf).map_err(malformed_error),
+            Codec::MessagePack => from_message_pack(buf),
+        }
+    }
+
+    /// Writes `packet`, transcoding its body first if it was built for the
+    /// other codec.
+    pub fn encode(self, packet: &DataPacket) -> Result<Vec<u8>, PacketError> {
+        if packet.codec != self {
+            return self.encode(&packet.clone().transcode(self)?);
+        }
+
+        match self {
+            Codec::Json => {
+                let data = match std::str::from_utf8(&packet.data) {
+                    Ok(data) => data,
+                    Err(err) => return Err(serialize_error(err)),
+                };
+
+                serde_json::to_vec(&packet.wire(data)).map_err(serialize_error)
+            }
+            Codec::MessagePack => to_message_pack(&packet.wire(Bytes::new(&packet.data))),
+        }
+    }
+
+    pub fn decode(self, buf: &[u8]) -> Result<DataPacket, PacketError> {
+        match self {
+            Codec::Json => {
+                let wire: Wire<String, PacketSignature> =
+                    serde_json::from_slice(buf).map_err(malformed_error)?;
+
+                Ok(DataPacket::from_wire(wire, self, String::into_bytes))
+            }
+            Codec::MessagePack => {
+                let wire: Wire<ByteBuf, PacketSignature> = from_message_pack(buf)?;
+
+                Ok(DataPacket::from_wire(wire, self, ByteBuf::into_vec))
+            }
+        }
+    }
 }
 
 impl DataPacket {
-    pub fn error_message(message: String) -> Self {
+    pub(crate) fn with(p_type: PacketType, data: String) -> Self {
+        Self::with_body(p_type, data.into_bytes(), Codec::Json)
+    }
+
+    pub(crate) fn with_body(p_type: PacketType, data: Vec<u8>, codec: Codec) -> Self {
         Self {
-            p_type: PacketType::Error,
-            data: message,
+            p_type,
+            data,
+            codec,
+            seq: 0,
+            timestamp: 0,
+            request_id: 0,
+            event: false,
+            signature: None,
         }
     }
 
+    pub fn error_message(message: String) -> Self {
+        Self::with(PacketType::Error, message)
+    }
+
     pub fn ok_message(message: String) -> Self {
-        Self {
-            p_type: PacketType::Ok,
-            data: message,
-        }
+        Self::with(PacketType::Ok, message)
     }
 
     pub fn new(buf: String) -> Result<Self, serde_json::Error> {
-        let packet: Self = serde_json::from_str(&buf)?;
-        Ok(packet)
+        let wire: Wire<String, PacketSignature> = serde_json::from_str(&buf)?;
+
+        Ok(Self::from_wire(wire, Codec::Json, String::into_bytes))
     }
 
     pub fn buf(&self) -> Result<String, serde_json::Error> {
-        let buf = serde_json::to_string(self)?;
+        let buf = match Codec::Json.encode(self) {
+            Ok(buf) => buf,
+            Err(err) => return Err(serde::ser::Error::custom(err.message)),
+        };
+
+        match String::from_utf8(buf) {
+            Ok(buf) => Ok(buf),
+            Err(err) => Err(serde::ser::Error::custom(err)),
+        }
+    }
 
-        Ok(buf)
+    fn wire<D>(&self, data: D) -> Wire<D, &PacketSignature> {
+        Wire {
+            p_type: self.p_type,
+            data,
+            seq: self.seq,
+            timestamp: self.timestamp,
+            request_id: self.request_id,
+            event: self.event,
+            signature: self.signature.as_ref(),
+        }
+    }
+
+    fn from_wire<D>(wire: Wire<D, PacketSignature>, codec: Codec, data: fn(D) -> Vec<u8>) -> Self {
+        Self {
+            p_type: wire.p_type,
+            data: data(wire.data),
+            codec,
+            seq: wire.seq,
+            timestamp: wire.timestamp,
+            request_id: wire.request_id,
+            event: wire.event,
+            signature: wire.signature,
+        }
+    }
+
+    /// Re-encodes the body for `codec`. Text bodies of `Ok` and `Error`
+    /// packets read the same in both codecs. Sealed and signed packets are
+    /// bound to their bytes and cannot be transcoded.
+    pub fn transcode(mut self, codec: Codec) -> Result<Self, PacketError> {
+        if self.codec == codec || matches!(self.p_type, PacketType::Ok | PacketType::Error) {
+            self.codec = codec;
+            return Ok(self);
+        }
+
+        if matches!(self.p_type, PacketType::Encrypted) || self.signature.is_some() {
+            return Err(PacketError {
+                kind: PacketErrorKind::Serialize,
+                message: String::from("Sealed or signed packets cannot be transcoded"),
+            });
+        }
+
+        let mut data = Vec::with_capacity(self.data.len());
+
+        let transcoded = match (self.codec, codec) {
+            (Codec::Json, Codec::MessagePack) => serde_transcode::transcode(
+                &mut serde_json::Deserializer::from_slice(&self.data),
+                &mut rmp_serde::Serializer::new(&mut data)
+                    .with_struct_map()
+                    .with_human_readable(),
+            )
+            .map_err(serialize_error),
+            (Codec::MessagePack, Codec::Json) => serde_transcode::transcode(
+                &mut rmp_serde::Deserializer::from_read_ref(&self.data).with_human_readable(),
+                &mut serde_json::Serializer::new(&mut data),
+            )
+            .map_err(serialize_error),
+            _ => Ok(()),
+        };
+
+        transcoded?;
+
+        self.data = data;
+        self.codec = codec;
+
+        Ok(self)
     }
 
     pub fn get_type(&self) -> PacketType {
         self.p_type
     }
 
+    /// The body as text, for the messages carried by `Ok` and `Error`
+    /// packets.
     pub fn get_data(&self) -> String {
-        self.data.clone()
+        String::from_utf8_lossy(&self.data).into_owned()
+    }
+
+    pub fn get_body(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn get_codec(&self) -> Codec {
+        self.codec
+    }
+
+    pub fn get_seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn get_timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    pub fn get_request_id(&self) -> u64 {
+        self.request_id
+    }
+
+    pub fn set_request_id(&mut self, request_id: u64) {
+        self.request_id = request_id;
+        self.event = false;
+    }
+
+    pub fn is_event(&self) -> bool {
+        self.event
+    }
+
+    pub fn set_event(&mut self) {
+        self.request_id = 0;
+        self.event = true;
+    }
+
+    pub fn get_signature(&self) -> Option<&PacketSignature> {
+        self.signature.as_ref()
+    }
+
+    pub(crate) fn set_envelope(&mut self, seq: u64, timestamp: i64) {
+        self.seq = seq;
+        self.timestamp = timestamp;
+        self.signature = None;
+    }
+
+    pub(crate) fn set_signature(&mut self, signature: PacketSignature) {
+        self.signature = Some(signature);
+    }
+
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let data = &self.data;
+
+        let mut buf = Vec::with_capacity(SIGNING_CONTEXT.len() + 1 + 8 + data.len() + 25);
+        buf.extend_from_slice(SIGNING_CONTEXT);
+        buf.push(self.p_type as u8);
+        buf.extend_from_slice(&(data.len() as u64).to_be_bytes());
+        buf.extend_from_slice(data);
+        buf.extend_from_slice(&self.seq.to_be_bytes());
+        buf.extend_from_slice(&self.timestamp.to_be_bytes());
+        buf.extend_from_slice(&self.request_id.to_be_bytes());
+        buf.push(self.event as u8);
+
+        buf
     }
 }
 
@@ -51,8 +327,8 @@ impl<'a, T> Packet<T>
 where
     T: Serialize + Deserialize<'a>,
 {
-    pub fn from(packet: &'a DataPacket) -> Result<Self, serde_json::Error> {
-        let body: T = serde_json::from_str(packet.data.as_str())?;
+    pub fn from(packet: &'a DataPacket) -> Result<Self, PacketError> {
+        let body: T = packet.codec.decode_body(&packet.data)?;
 
         let new_packet = Self {
             p_type: packet.p_type,
@@ -65,14 +341,17 @@ where
     pub fn to(&self) -> Result<DataPacket, serde_json::Error> {
         let data = serde_json::to_string(&self.body)?;
 
-        let packet = DataPacket {
-            p_type: self.p_type,
-            data,
-        };
+        let packet = DataPacket::with(self.p_type, data);
 
         Ok(packet)
     }
 
+    pub fn encode(&self, codec: Codec) -> Result<DataPacket, PacketError> {
+        let data = codec.encode_body(&self.body)?;
+
+        Ok(DataPacket::with_body(self.p_type, data, codec))
+    }
+
     pub fn new(p_type: PacketType, body: T) -> Self {
         Self { p_type, body }
     }
@@ -104,13 +383,44 @@ pub enum PacketType {
     Register,
     CreateGroup,
     AddUser,
+    RemoveUser,
     CreateMessage,
     GetMessages,
     GetChats,
     Listen,
+    Encrypted,
+    PublishKeys,
+    GetKeyBundle,
+    GetE2E,
+    SenderKey,
+    GetSenderKeys,
+    SrpRegister,
+    SrpStart,
+    SrpProof,
+    Resume,
+    SearchUsers,
+    Shutdown,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PacketErrorKind {
+    Io,
+    Serialize,
+    Malformed,
+    LegacyFraming,
+    UnsupportedVersion,
+    FrameTooLarge,
+    Unsigned,
+    BadSignature,
+    UnknownKey,
+    Replay,
+    Crypto,
+    Handshake,
+    Tls,
 }
 
 pub struct PacketError {
+    pub kind: PacketErrorKind,
     pub message: String,
 }
 
@@ -123,7 +433,43 @@ impl fmt::Display for PacketError {
 impl fmt::Debug for PacketError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("PacketError")
+            .field("kind", &self.kind)
             .field("message", &self.message)
             .finish()
     }
 }
+
+/// MessagePack keeps the human-readable forms of ids and timestamps, so a
+/// body means the same thing in either codec and transcodes losslessly.
+fn to_message_pack<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, PacketError> {
+    let mut buf = Vec::new();
+
+    value
+        .serialize(
+            &mut rmp_serde::Serializer::new(&mut buf)
+                .with_struct_map()
+                .with_human_readable(),
+        )
+        .map_err(serialize_error)?;
+
+    Ok(buf)
+}
+
+fn from_message_pack<'a, T: Deserialize<'a>>(buf: &'a [u8]) -> Result<T, PacketError> {
+    T::deserialize(&mut rmp_serde::Deserializer::from_read_ref(buf).with_human_readable())
+        .map_err(malformed_error)
+}
+
+fn serialize_error(err: impl ToString) -> PacketError {
+    PacketError {
+        kind: PacketErrorKind::Serialize,
+        message: err.to_string(),
+    }
+}
+
+fn malformed_error(err: impl ToString) -> PacketError {
+    PacketError {
+        kind: PacketErrorKind::Malformed,
+        message: err.to_string(),
+    }
+}
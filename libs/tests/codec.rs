@@ -0,0 +1,151 @@
+use libs::{
+    crypto::transport::{ClientHandshake, ServerIdentity},
+    packet::{Codec, Packet, PacketErrorKind, PacketType},
+    packet_manager::{self, Channel, FrameHeader},
+    BaseModels, PacketModels,
+};
+
+fn messages() -> PacketModels::Messages {
+    let group = BaseModels::Group::new(String::from("codecs"));
+    let member = BaseModels::Member::new(
+        group.clone(),
+        BaseModels::User::simple(String::from("alice"), String::new()),
+    );
+
+    let messages = (0..4)
+        .map(|i| BaseModels::Message::new(member.clone(), format!("\"quoted\" message {}", i)))
+        .collect();
+
+    PacketModels::Messages::new(group, messages, Some(4))
+}
+
+#[test]
+fn message_pack_bodies_round_trip() {
+    let body = messages();
+    let expected = serde_json::to_value(&body).unwrap();
+    let packet = Packet::new(PacketType::GetMessages, body)
+        .encode(Codec::MessagePack)
+        .unwrap();
+    assert_eq!(packet.get_codec(), Codec::MessagePack);
+
+    let buf = Codec::MessagePack.encode(&packet).unwrap();
+    let packet = Codec::MessagePack.decode(&buf).unwrap();
+
+    let (p_type, decoded): (PacketType, PacketModels::Messages) =
+        Packet::from(&packet).unwrap().get();
+    assert!(matches!(p_type, PacketType::GetMessages));
+    assert_eq!(serde_json::to_value(&decoded).unwrap(), expected);
+}
+
+#[test]
+fn message_pack_frames_are_smaller_than_nested_json() {
+    let packet = Packet::new(PacketType::GetMessages, messages())
+        .encode(Codec::Json)
+        .unwrap();
+
+    let json = Codec::Json.encode(&packet).unwrap();
+    let message_pack = Codec::MessagePack.encode(&packet).unwrap();
+
+    assert!(message_pack.len() < json.len());
+    assert!(!message_pack.windows(2).any(|bytes| bytes == b"\\\""));
+}
+
+#[test]
+fn bodies_transcode_between_codecs() {
+    let packet = Packet::new(PacketType::GetMessages, messages())
+        .encode(Codec::Json)
+        .unwrap();
+    let original = packet.get_body().to_vec();
+
+    let packet = packet.transcode(Codec::MessagePack).unwrap();
+    assert_eq!(packet.get_codec(), Codec::MessagePack);
+    assert_ne!(packet.get_body(), &original[..]);
+
+    let packet = packet.transcode(Codec::Json).unwrap();
+    assert_eq!(packet.get_codec(), Codec::Json);
+    assert_eq!(packet.get_body(), &original[..]);
+}
+
+#[test]
+fn channels_negotiate_the_codec_from_each_frame() {
+    let (left, right) = packet_manager::duplex();
+
+    let mut client = Channel::new(left).with_codec(Codec::MessagePack);
+    let mut server = Channel::new(right);
+
+    client
+        .send(
+            Packet::new(PacketType::GetMessages, messages())
+                .encode(Codec::Json)
+                .unwrap(),
+        )
+        .unwrap();
+
+    let packet = server.recv().unwrap();
+    assert_eq!(packet.get_codec(), Codec::MessagePack);
+
+    let body: PacketModels::Messages = Packet::from(&packet).unwrap().get().1;
+    assert_eq!(body.get().len(), 4);
+}
+
+#[test]
+fn encrypted_packets_keep_their_codec() {
+    let identity = ServerIdentity::generate();
+
+    let (handshake, hello) = ClientHandshake::start();
+    let (reply, mut server) = identity.respond(hello).unwrap();
+    let mut client = handshake.finish(reply, None).unwrap();
+
+    let packet = Packet::new(PacketType::GetMessages, messages())
+        .encode(Codec::MessagePack)
+        .unwrap();
+    let body = packet.get_body().to_vec();
+
+    let packet = packet_manager::encrypt_packet(packet, &mut client).unwrap();
+    assert!(matches!(packet.get_type(), PacketType::Encrypted));
+    assert_eq!(packet.get_codec(), Codec::MessagePack);
+
+    let buf = Codec::MessagePack.encode(&packet).unwrap();
+    let packet = Codec::MessagePack.decode(&buf).unwrap();
+
+    let packet = packet_manager::decrypt_packet(packet, &mut server).unwrap();
+    assert!(matches!(packet.get_type(), PacketType::GetMessages));
+    assert_eq!(packet.get_codec(), Codec::MessagePack);
+    assert_eq!(packet.get_body(), &body[..]);
+}
+
+#[test]
+fn sealed_packets_cannot_be_transcoded() {
+    let identity = ServerIdentity::generate();
+
+    let (handshake, hello) = ClientHandshake::start();
+    let (reply, _) = identity.respond(hello).unwrap();
+    let mut client = handshake.finish(reply, None).unwrap();
+
+    let packet = Packet::new(PacketType::GetMessages, messages())
+        .encode(Codec::Json)
+        .unwrap();
+    let packet = packet_manager::encrypt_packet(packet, &mut client).unwrap();
+
+    assert_eq!(
+        packet.transcode(Codec::MessagePack).err().unwrap().kind,
+        PacketErrorKind::Serialize
+    );
+}
+
+#[test]
+fn unknown_codec_flags_are_rejected() {
+    let mut buf = FrameHeader::new(0x0f, 2).encode().to_vec();
+    buf.extend_from_slice(b"{}");
+
+    let err = packet_manager::recv_packet(&mut &buf[..]).err().unwrap();
+    assert_eq!(err.kind, PacketErrorKind::UnsupportedVersion);
+}
+
+#[test]
+fn codec_names_parse() {
+    assert_eq!(Codec::parse("json").unwrap(), Codec::Json);
+    assert_eq!(Codec::parse("msgpack").unwrap(), Codec::MessagePack);
+    assert_eq!(Codec::parse("messagepack").unwrap(), Codec::MessagePack);
+    assert!(Codec::parse("cbor").is_err());
+}
@@ -0,0 +1,129 @@
+use std::{io::Write, thread};
+
+use libs::{
+    packet::{DataPacket, PacketErrorKind},
+    packet_manager::{self, Channel, FrameHeader, Stream},
+};
+
+#[test]
+fn frames_round_trip_through_any_reader_and_writer() {
+    let mut buf = Vec::new();
+
+    packet_manager::send_packet(&mut buf, DataPacket::ok_message(String::from("one"))).unwrap();
+    packet_manager::send_packet(&mut buf, DataPacket::ok_message(String::from("two"))).unwrap();
+
+    let mut reader = &buf[..];
+    assert_eq!(
+        packet_manager::recv_packet(&mut reader).unwrap().get_data(),
+        "one"
+    );
+    assert_eq!(
+        packet_manager::recv_packet(&mut reader).unwrap().get_data(),
+        "two"
+    );
+    assert_eq!(
+        packet_manager::recv_packet(&mut reader).err().unwrap().kind,
+        PacketErrorKind::Io
+    );
+}
+
+#[test]
+fn oversized_frames_are_rejected_before_reading_the_body() {
+    let mut buf = Vec::new();
+    buf.write_all(&FrameHeader::new(0, 1024).encode()).unwrap();
+
+    let err = packet_manager::recv_packet_with_limit(&mut &buf[..], 512)
+        .err()
+        .unwrap();
+    assert_eq!(err.kind, PacketErrorKind::FrameTooLarge);
+}
+
+#[test]
+fn truncated_bodies_are_reported_as_io_errors() {
+    let mut buf = Vec::new();
+    buf.write_all(&FrameHeader::new(0, 1024).encode()).unwrap();
+    buf.write_all(b"{\"p_type\":").unwrap();
+
+    let err = packet_manager::recv_packet(&mut &buf[..]).err().unwrap();
+    assert_eq!(err.kind, PacketErrorKind::Io);
+}
+
+#[test]
+fn channels_talk_over_an_in_process_duplex() {
+    let (left, right) = packet_manager::duplex();
+
+    let mut client = Channel::new(left);
+    let mut server = Channel::new(right);
+
+    let echo = thread::spawn(move || {
+        let packet = server.recv().unwrap();
+        server.send(packet).unwrap();
+        server.shutdown();
+    });
+
+    client
+        .send(DataPacket::ok_message(String::from("ping")))
+        .unwrap();
+    assert_eq!(client.recv().unwrap().get_data(), "ping");
+
+    echo.join().unwrap();
+    assert_eq!(client.recv().err().unwrap().kind, PacketErrorKind::Io);
+}
+
+#[test]
+fn closing_a_duplex_clone_wakes_the_reader() {
+    let (left, right) = packet_manager::duplex();
+
+    let mut reader = Stream::from(left);
+    let closer = reader.try_clone().unwrap();
+
+    let blocked = thread::spawn(move || packet_manager::recv_packet(&mut reader));
+    closer.shutdown();
+
+    assert!(blocked.join().unwrap().is_err());
+
+    let mut right = Stream::from(right);
+    assert!(right.write_all(b"late").is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn channels_talk_over_unix_sockets() {
+    use std::os::unix::net::UnixStream;
+
+    let (left, right) = UnixStream::pair().unwrap();
+
+    let mut client = Channel::new(left);
+    let mut server = Channel::new(right);
+
+    client
+        .send(DataPacket::ok_message(String::from("hello")))
+        .unwrap();
+    assert_eq!(server.recv().unwrap().get_data(), "hello");
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn async_frames_interoperate_with_blocking_peers() {
+    let (left, mut right) = packet_manager::duplex();
+
+    let peer = thread::spawn(move || {
+        let mut stream = Stream::from(left);
+        let packet = packet_manager::recv_packet(&mut stream).unwrap();
+        packet_manager::send_packet(&mut stream, packet).unwrap();
+    });
+
+    packet_manager::send_packet_async(&mut right, DataPacket::ok_message(String::from("ping")))
+        .await
+        .unwrap();
+
+    let echo = packet_manager::recv_packet_async(&mut right, 1024)
+        .await
+        .unwrap();
+    assert_eq!(echo.get_data(), "ping");
+
+    peer.join().unwrap();
+
+    let closed = packet_manager::recv_packet_async(&mut right, 1024).await;
+    assert_eq!(closed.err().unwrap().kind, PacketErrorKind::Io);
+}
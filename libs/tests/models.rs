@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+
+use libs::{BaseModels, PacketModels};
+use uuid::Uuid;
+
+fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| String::from(*name)).collect()
+}
+
+#[test]
+fn owner_is_admin_and_member() {
+    let group = BaseModels::Group::full(
+        Uuid::from_u128(1),
+        String::from("friends"),
+        String::from("alice"),
+        Vec::new(),
+        names(&["bob"]),
+    );
+
+    assert!(group.is_admin("alice"));
+    assert!(group.is_member("alice"));
+    assert!(!group.is_admin("bob"));
+    assert!(group.is_member("bob"));
+    assert!(!group.is_member("carol"));
+}
+
+#[test]
+fn admins_are_members() {
+    let group = BaseModels::Group::full(
+        Uuid::from_u128(2),
+        String::from("friends"),
+        String::from("alice"),
+        names(&["bob"]),
+        Vec::new(),
+    );
+
+    assert!(group.is_admin("bob"));
+    assert!(group.is_member("bob"));
+}
+
+#[test]
+fn group_without_membership_decodes() {
+    let group: BaseModels::Group = serde_json::from_str(&format!(
+        r#"{{"id":"{}","name":"friends"}}"#,
+        Uuid::from_u128(3)
+    ))
+    .unwrap();
+
+    assert_eq!(group.get_name(), "friends");
+    assert!(group.get_owner().is_empty());
+    assert!(group.get_members().is_empty());
+    assert!(!group.is_admin(""));
+}
+
+#[test]
+fn message_keeps_server_assigned_id() {
+    let group = BaseModels::Group::new(String::from("friends"));
+    let member = BaseModels::Member::new(
+        group,
+        BaseModels::User::simple(String::from("alice"), String::new()),
+    );
+
+    let message = BaseModels::Message::new(member, String::from("hello"));
+    assert!(message.get_id().is_nil());
+
+    let message = message.with_id(Uuid::from_u128(4));
+    let encoded = serde_json::to_string(&message).unwrap();
+    let decoded: BaseModels::Message = serde_json::from_str(&encoded).unwrap();
+
+    assert_eq!(decoded.get_id(), Uuid::from_u128(4));
+    assert_eq!(decoded.get_body(), "hello");
+    assert_eq!(decoded.get_created_at(), message.get_created_at());
+    assert_eq!(decoded.get_member().get_user().get_username(), "alice");
+}
+
+#[test]
+fn chats_flag_new_messages() {
+    let group = BaseModels::Group::full(
+        Uuid::from_u128(5),
+        String::from("friends"),
+        String::from("alice"),
+        Vec::new(),
+        Vec::new(),
+    );
+
+    let chats = PacketModels::Chats::new(vec![group.clone()], HashMap::from([(group.get_id(), 0)]));
+    assert!(!chats.is_new());
+
+    let chats = PacketModels::Chats::new(vec![group], HashMap::from([(Uuid::from_u128(5), 3)]));
+    assert!(chats.is_new());
+    assert_eq!(chats.get_unread(Uuid::from_u128(5)), 3);
+    assert_eq!(chats.get_unread(Uuid::from_u128(6)), 0);
+}
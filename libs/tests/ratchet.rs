@@ -0,0 +1,109 @@
+use libs::{
+    crypto::{ratchet::Ratchet, x3dh::LocalKeys},
+    BaseModels,
+};
+
+fn user(name: &str) -> BaseModels::User {
+    BaseModels::User::simple(String::from(name), String::new())
+}
+
+fn pair() -> (Ratchet, Ratchet) {
+    let mut alice = LocalKeys::generate(1);
+    let mut bob = LocalKeys::generate(1);
+
+    let (bundle, mut prekeys) = bob.bundle().unwrap().split();
+    let bundle = bundle.with_one_time_prekey(prekeys.pop());
+
+    let (alice_ratchet, mut init) = alice.initiate(user("bob"), bundle, b"hello").unwrap();
+    init.set_user(user("alice"));
+
+    let (bob_ratchet, _) = bob.accept(&init).unwrap();
+
+    (alice_ratchet, bob_ratchet)
+}
+
+#[test]
+fn conversation_round_trip() {
+    let (mut alice, mut bob) = pair();
+
+    for i in 0..3 {
+        let body = alice.encrypt(format!("a{}", i).as_bytes()).unwrap();
+        assert_eq!(bob.decrypt(&body).unwrap(), format!("a{}", i).as_bytes());
+
+        let body = bob.encrypt(format!("b{}", i).as_bytes()).unwrap();
+        assert_eq!(alice.decrypt(&body).unwrap(), format!("b{}", i).as_bytes());
+    }
+}
+
+#[test]
+fn every_message_uses_a_fresh_key() {
+    let (mut alice, _) = pair();
+
+    let first = alice.encrypt(b"same").unwrap();
+    let second = alice.encrypt(b"same").unwrap();
+
+    assert_ne!(first, second);
+}
+
+#[test]
+fn out_of_order_delivery() {
+    let (mut alice, mut bob) = pair();
+
+    let first = alice.encrypt(b"first").unwrap();
+    let second = alice.encrypt(b"second").unwrap();
+    let third = alice.encrypt(b"third").unwrap();
+
+    assert_eq!(bob.decrypt(&third).unwrap(), b"third");
+    assert_eq!(bob.decrypt(&first).unwrap(), b"first");
+
+    let reply = bob.encrypt(b"reply").unwrap();
+    assert_eq!(alice.decrypt(&reply).unwrap(), b"reply");
+
+    assert_eq!(bob.decrypt(&second).unwrap(), b"second");
+}
+
+#[test]
+fn replayed_message_is_rejected() {
+    let (mut alice, mut bob) = pair();
+
+    let body = alice.encrypt(b"once").unwrap();
+
+    bob.decrypt(&body).unwrap();
+    assert!(bob.decrypt(&body).is_err());
+}
+
+#[test]
+fn tampered_message_does_not_advance_state() {
+    let (mut alice, mut bob) = pair();
+
+    let body = alice.encrypt(b"hello").unwrap();
+    let mut tampered = body.clone().into_bytes();
+    let last = tampered.len() - 3;
+    tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+
+    assert!(bob.decrypt(&String::from_utf8(tampered).unwrap()).is_err());
+    assert_eq!(bob.decrypt(&body).unwrap(), b"hello");
+}
+
+#[test]
+fn state_survives_serialization() {
+    let (mut alice, bob) = pair();
+
+    let stored = serde_json::to_string(&bob).unwrap();
+    let mut bob: Ratchet = serde_json::from_str(&stored).unwrap();
+
+    let body = alice.encrypt(b"after restart").unwrap();
+    assert_eq!(bob.decrypt(&body).unwrap(), b"after restart");
+}
+
+#[test]
+fn too_many_skipped_messages_are_rejected() {
+    let (mut alice, mut bob) = pair();
+
+    let mut last = String::new();
+    for _ in 0..1002 {
+        last = alice.encrypt(b"spam").unwrap();
+    }
+
+    assert!(bob.decrypt(&last).is_err());
+}
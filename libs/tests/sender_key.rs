@@ -0,0 +1,195 @@
+use libs::crypto::sender_key::{GroupSession, SenderKeyDistribution};
+use libs::packet::PacketErrorKind;
+use uuid::Uuid;
+
+fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| String::from(*name)).collect()
+}
+
+fn share(from: &GroupSession, sender: &str, to: &mut GroupSession) {
+    let buf = from.distribution().unwrap().encode().unwrap();
+    to.accept(sender, SenderKeyDistribution::decode(&buf).unwrap())
+        .unwrap();
+}
+
+fn group() -> (GroupSession, GroupSession, GroupSession) {
+    let id = Uuid::from_u128(1);
+
+    let mut alice = GroupSession::new(id, names(&["bob", "carol"]));
+    let mut bob = GroupSession::new(id, names(&["alice", "carol"]));
+    let mut carol = GroupSession::new(id, names(&["alice", "bob"]));
+
+    share(&alice, "alice", &mut bob);
+    share(&alice, "alice", &mut carol);
+    share(&bob, "bob", &mut alice);
+    share(&bob, "bob", &mut carol);
+    share(&carol, "carol", &mut alice);
+    share(&carol, "carol", &mut bob);
+
+    for (session, members) in [
+        (&mut alice, ["bob", "carol"]),
+        (&mut bob, ["alice", "carol"]),
+        (&mut carol, ["alice", "bob"]),
+    ] {
+        for member in members {
+            session.mark_distributed(member);
+        }
+    }
+
+    (alice, bob, carol)
+}
+
+#[test]
+fn group_message_round_trip() {
+    let (mut alice, mut bob, mut carol) = group();
+
+    let body = alice.encrypt(b"hello group").unwrap();
+    assert_eq!(bob.decrypt("alice", &body).unwrap(), b"hello group");
+    assert_eq!(carol.decrypt("alice", &body).unwrap(), b"hello group");
+
+    let body = carol.encrypt(b"hi").unwrap();
+    assert_eq!(alice.decrypt("carol", &body).unwrap(), b"hi");
+    assert_eq!(bob.decrypt("carol", &body).unwrap(), b"hi");
+}
+
+#[test]
+fn message_is_bound_to_its_sender() {
+    let (mut alice, mut bob, _) = group();
+
+    let body = alice.encrypt(b"from alice").unwrap();
+
+    assert!(bob.decrypt("carol", &body).is_err());
+    assert_eq!(bob.decrypt("alice", &body).unwrap(), b"from alice");
+}
+
+#[test]
+fn out_of_order_and_replay() {
+    let (mut alice, mut bob, _) = group();
+
+    let first = alice.encrypt(b"first").unwrap();
+    let second = alice.encrypt(b"second").unwrap();
+
+    assert_eq!(bob.decrypt("alice", &second).unwrap(), b"second");
+    assert_eq!(bob.decrypt("alice", &first).unwrap(), b"first");
+    assert!(bob.decrypt("alice", &first).is_err());
+}
+
+#[test]
+fn distribution_excludes_earlier_messages() {
+    let id = Uuid::from_u128(2);
+
+    let mut alice = GroupSession::new(id, names(&["bob"]));
+    let before = alice.encrypt(b"before").unwrap();
+
+    let mut dave = GroupSession::new(id, names(&["alice"]));
+    share(&alice, "alice", &mut dave);
+
+    assert!(dave.decrypt("alice", &before).is_err());
+
+    let after = alice.encrypt(b"after").unwrap();
+    assert_eq!(dave.decrypt("alice", &after).unwrap(), b"after");
+}
+
+#[test]
+fn membership_change_rotates_sender_key() {
+    let (mut alice, mut bob, mut carol) = group();
+
+    assert!(!alice.set_members(names(&["carol", "bob"])));
+    assert!(alice.pending().is_empty());
+
+    assert!(alice.set_members(names(&["bob"])));
+    assert_eq!(alice.pending(), names(&["bob"]));
+
+    share(&alice, "alice", &mut bob);
+    alice.mark_distributed("bob");
+
+    let body = alice.encrypt(b"without carol").unwrap();
+    assert_eq!(bob.decrypt("alice", &body).unwrap(), b"without carol");
+    assert!(carol.decrypt("alice", &body).is_err());
+
+    assert!(bob.set_members(names(&["alice"])));
+    assert!(bob
+        .decrypt("carol", &carol.encrypt(b"late").unwrap())
+        .is_err());
+}
+
+#[test]
+fn sender_keys_from_non_members_are_rejected() {
+    let id = Uuid::from_u128(3);
+
+    let mut alice = GroupSession::new(id, names(&["bob"]));
+    let mut dave = GroupSession::new(id, names(&["alice"]));
+
+    let err = alice
+        .accept("dave", dave.distribution().unwrap())
+        .err()
+        .unwrap();
+    assert_eq!(err.kind, PacketErrorKind::UnknownKey);
+    assert_eq!(alice.members(), names(&["bob"]));
+    assert!(alice.pending().contains(&String::from("bob")));
+
+    let body = dave.encrypt(b"not invited").unwrap();
+    assert!(alice.decrypt("dave", &body).is_err());
+}
+
+#[test]
+fn removed_members_cannot_read_after_rotation() {
+    let (mut alice, mut bob, mut carol) = group();
+
+    assert!(alice.set_members(names(&["bob"])));
+    assert!(bob.set_members(names(&["alice"])));
+    assert_eq!(alice.pending(), names(&["bob"]));
+    assert_eq!(bob.pending(), names(&["alice"]));
+
+    share(&alice, "alice", &mut bob);
+    share(&bob, "bob", &mut alice);
+
+    let body = alice.encrypt(b"after carol left").unwrap();
+    assert_eq!(bob.decrypt("alice", &body).unwrap(), b"after carol left");
+    assert!(carol.decrypt("alice", &body).is_err());
+
+    let body = bob.encrypt(b"still private").unwrap();
+    assert_eq!(alice.decrypt("bob", &body).unwrap(), b"still private");
+    assert!(carol.decrypt("bob", &body).is_err());
+}
+
+#[test]
+fn new_session_replaces_a_reused_key_id() {
+    let (alice, mut bob, _) = group();
+    let key_id = alice.distribution().unwrap().get_key_id();
+
+    // alice starts over, say after losing her keystore, under her old key id
+    let mut state = serde_json::to_value(GroupSession::new(
+        Uuid::from_u128(1),
+        names(&["bob", "carol"]),
+    ))
+    .unwrap();
+    state["sender_key"]["key_id"] = key_id.into();
+    let mut fresh: GroupSession = serde_json::from_value(state).unwrap();
+    assert_eq!(fresh.distribution().unwrap().get_key_id(), key_id);
+
+    share(&fresh, "alice", &mut bob);
+
+    let message = fresh.encrypt(b"hello again").unwrap();
+    assert_eq!(bob.decrypt("alice", &message).unwrap(), b"hello again");
+}
+
+#[test]
+fn distribution_for_another_group_is_rejected() {
+    let mut alice = GroupSession::new(Uuid::from_u128(4), names(&["bob"]));
+    let bob = GroupSession::new(Uuid::from_u128(5), names(&["alice"]));
+
+    let distribution = bob.distribution().unwrap();
+    assert!(alice.accept("bob", distribution).is_err());
+}
+
+#[test]
+fn state_survives_serialization() {
+    let (mut alice, bob, _) = group();
+
+    let stored = serde_json::to_string(&bob).unwrap();
+    let mut bob: GroupSession = serde_json::from_str(&stored).unwrap();
+
+    let body = alice.encrypt(b"after restart").unwrap();
+    assert_eq!(bob.decrypt("alice", &body).unwrap(), b"after restart");
+}
@@ -0,0 +1,145 @@
+use libs::{
+    crypto::signing::{PacketSigner, PacketVerifier},
+    packet::{DataPacket, Packet, PacketErrorKind, PacketType},
+    packet_manager, BaseModels,
+};
+
+fn login_packet() -> DataPacket {
+    let body = BaseModels::User::simple(String::from("alice"), String::from("secret"));
+
+    Packet::new(PacketType::Login, body).to().unwrap()
+}
+
+fn tamper(packet: &DataPacket, from: &str, to: &str) -> DataPacket {
+    let buf = packet.buf().unwrap();
+    assert!(buf.contains(from));
+
+    DataPacket::new(buf.replacen(from, to, 1)).unwrap()
+}
+
+#[test]
+fn signed_packet_round_trip() {
+    let mut signer = PacketSigner::generate();
+    let mut verifier = PacketVerifier::new();
+    verifier.trust(signer.verifying_key());
+
+    let packet = packet_manager::sign_packet(login_packet(), &mut signer).unwrap();
+    let packet = DataPacket::new(packet.buf().unwrap()).unwrap();
+
+    packet_manager::check_signed_packet(&packet, &mut verifier).unwrap();
+
+    let packet: Packet<BaseModels::User> = Packet::from(&packet).unwrap();
+    assert!(matches!(packet.get().0, PacketType::Login));
+}
+
+#[test]
+fn sequence_numbers_increase() {
+    let mut signer = PacketSigner::generate();
+
+    let first = packet_manager::sign_packet(login_packet(), &mut signer).unwrap();
+    let second = packet_manager::sign_packet(login_packet(), &mut signer).unwrap();
+
+    assert!(second.get_seq() > first.get_seq());
+}
+
+#[test]
+fn tampered_data_is_rejected() {
+    let mut signer = PacketSigner::generate();
+    let mut verifier = PacketVerifier::new();
+    verifier.trust(signer.verifying_key());
+
+    let packet = packet_manager::sign_packet(login_packet(), &mut signer).unwrap();
+    let packet = tamper(&packet, "alice", "mallory");
+
+    let err = packet_manager::check_signed_packet(&packet, &mut verifier).unwrap_err();
+    assert_eq!(err.kind, PacketErrorKind::BadSignature);
+}
+
+#[test]
+fn tampered_type_is_rejected() {
+    let mut signer = PacketSigner::generate();
+    let mut verifier = PacketVerifier::new();
+    verifier.trust(signer.verifying_key());
+
+    let packet = packet_manager::sign_packet(login_packet(), &mut signer).unwrap();
+    let packet = tamper(&packet, "\"Login\"", "\"Register\"");
+
+    let err = packet_manager::check_signed_packet(&packet, &mut verifier).unwrap_err();
+    assert_eq!(err.kind, PacketErrorKind::BadSignature);
+}
+
+#[test]
+fn tampered_sequence_is_rejected() {
+    let mut signer = PacketSigner::generate();
+    let mut verifier = PacketVerifier::new();
+    verifier.trust(signer.verifying_key());
+
+    let packet = packet_manager::sign_packet(login_packet(), &mut signer).unwrap();
+    let packet = tamper(&packet, "\"seq\":1", "\"seq\":2");
+
+    let err = packet_manager::check_signed_packet(&packet, &mut verifier).unwrap_err();
+    assert_eq!(err.kind, PacketErrorKind::BadSignature);
+}
+
+#[test]
+fn replayed_packet_is_rejected() {
+    let mut signer = PacketSigner::generate();
+    let mut verifier = PacketVerifier::new();
+    verifier.trust(signer.verifying_key());
+
+    let packet = packet_manager::sign_packet(login_packet(), &mut signer).unwrap();
+
+    packet_manager::check_signed_packet(&packet, &mut verifier).unwrap();
+    let err = packet_manager::check_signed_packet(&packet, &mut verifier).unwrap_err();
+    assert_eq!(err.kind, PacketErrorKind::Replay);
+}
+
+#[test]
+fn stale_packet_is_rejected() {
+    let mut signer = PacketSigner::generate();
+    let mut verifier = PacketVerifier::new();
+    verifier.trust(signer.verifying_key());
+
+    let packet = packet_manager::sign_packet(login_packet(), &mut signer).unwrap();
+    let timestamp = format!("\"timestamp\":{}", packet.get_timestamp());
+    let packet = tamper(&packet, &timestamp, "\"timestamp\":0");
+
+    let err = packet_manager::check_signed_packet(&packet, &mut verifier).unwrap_err();
+    assert_eq!(err.kind, PacketErrorKind::Replay);
+}
+
+#[test]
+fn unknown_key_is_rejected() {
+    let mut signer = PacketSigner::generate();
+    let mut verifier = PacketVerifier::new();
+    verifier.trust(PacketSigner::generate().verifying_key());
+
+    let packet = packet_manager::sign_packet(login_packet(), &mut signer).unwrap();
+
+    let err = packet_manager::check_signed_packet(&packet, &mut verifier).unwrap_err();
+    assert_eq!(err.kind, PacketErrorKind::UnknownKey);
+}
+
+#[test]
+fn unsigned_packet_is_rejected() {
+    let mut verifier = PacketVerifier::new();
+
+    let err = packet_manager::check_signed_packet(&login_packet(), &mut verifier).unwrap_err();
+    assert_eq!(err.kind, PacketErrorKind::Unsigned);
+}
+
+#[test]
+fn tampered_request_id_is_rejected() {
+    let mut signer = PacketSigner::generate();
+    let mut verifier = PacketVerifier::new();
+    verifier.trust(signer.verifying_key());
+
+    let mut packet = login_packet();
+    packet.set_request_id(7);
+
+    let packet = packet_manager::sign_packet(packet, &mut signer).unwrap();
+    let packet = tamper(&packet, "\"request_id\":7", "\"request_id\":8");
+
+    let err = packet_manager::check_signed_packet(&packet, &mut verifier).unwrap_err();
+    assert_eq!(err.kind, PacketErrorKind::BadSignature);
+}
@@ -0,0 +1,114 @@
+use libs::{
+    crypto::{
+        srp::{self, SrpClient, SrpClientProof, SrpServer},
+        transport::{ClientHandshake, ServerIdentity},
+    },
+    packet::{DataPacket, PacketError, PacketErrorKind},
+    packet_manager, PacketModels,
+};
+
+fn login(
+    user: &str,
+    pass: &str,
+    salt: &str,
+    verifier: Option<&str>,
+) -> (SrpClientProof, Result<([u8; 32], String), PacketError>) {
+    let (client, start) = SrpClient::start(user);
+    let (server, challenge) = SrpServer::start(&start, salt, verifier).unwrap();
+
+    let (proof, body) = client.finish(pass, challenge).unwrap();
+
+    (proof, server.finish(&body))
+}
+
+#[test]
+fn both_sides_derive_the_same_key() {
+    let (salt, verifier) = srp::register("alice", "secret").unwrap();
+
+    let (proof, result) = login("alice", "secret", &salt, Some(&verifier));
+    let (server_key, server_proof) = result.unwrap();
+
+    let client_key = proof.verify(&server_proof).unwrap();
+    assert_eq!(client_key, server_key);
+}
+
+#[test]
+fn verifier_does_not_contain_password() {
+    let (salt, verifier) = srp::register("alice", "secret").unwrap();
+    let (_, again) = srp::register("alice", "secret").unwrap();
+
+    assert_ne!(verifier, again);
+    assert!(!salt.contains("secret") && !verifier.contains("secret"));
+}
+
+#[test]
+fn wrong_password_is_rejected() {
+    let (salt, verifier) = srp::register("alice", "secret").unwrap();
+
+    let (_, result) = login("alice", "Secret", &salt, Some(&verifier));
+    assert_eq!(result.err().unwrap().kind, PacketErrorKind::BadSignature);
+}
+
+#[test]
+fn unknown_user_is_rejected() {
+    let salt = srp::fake_salt(&[7; 32], "mallory").unwrap();
+    assert_eq!(salt, srp::fake_salt(&[7; 32], "mallory").unwrap());
+
+    let (_, result) = login("mallory", "secret", &salt, None);
+    assert!(result.is_err());
+}
+
+#[test]
+fn forged_server_proof_is_rejected() {
+    let (salt, verifier) = srp::register("alice", "secret").unwrap();
+
+    let (proof, _) = login("alice", "secret", &salt, Some(&verifier));
+    let forged = srp::register("mallory", "x").unwrap().0;
+
+    assert!(proof.verify(&forged).is_err());
+}
+
+#[test]
+fn invalid_client_public_value_is_rejected() {
+    let start = PacketModels::SrpStart::new(String::from("alice"), String::from("AA=="));
+    let (salt, verifier) = srp::register("alice", "secret").unwrap();
+
+    assert!(SrpServer::start(&start, &salt, Some(&verifier)).is_err());
+}
+
+#[test]
+fn session_key_rekeys_transport() {
+    let identity = ServerIdentity::generate();
+
+    let (handshake, hello) = ClientHandshake::start();
+    let (reply, mut server) = identity.respond(hello).unwrap();
+    let mut client = handshake.finish(reply, None).unwrap();
+
+    let (salt, verifier) = srp::register("alice", "secret").unwrap();
+    let (proof, result) = login("alice", "secret", &salt, Some(&verifier));
+    let (server_key, server_proof) = result.unwrap();
+    let client_key = proof.verify(&server_proof).unwrap();
+
+    server.rekey(&server_key).unwrap();
+    client.rekey(&client_key).unwrap();
+
+    let packet = DataPacket::ok_message(String::from("rekeyed"));
+    let packet = packet_manager::encrypt_packet(packet, &mut client).unwrap();
+    let packet = packet_manager::decrypt_packet(packet, &mut server).unwrap();
+    assert_eq!(packet.get_data(), "rekeyed");
+}
+
+#[test]
+fn rekey_on_one_side_only_breaks_the_channel() {
+    let identity = ServerIdentity::generate();
+
+    let (handshake, hello) = ClientHandshake::start();
+    let (reply, mut server) = identity.respond(hello).unwrap();
+    let mut client = handshake.finish(reply, None).unwrap();
+
+    client.rekey(&[1; 32]).unwrap();
+
+    let packet = DataPacket::ok_message(String::from("hello"));
+    let packet = packet_manager::encrypt_packet(packet, &mut client).unwrap();
+    assert!(packet_manager::decrypt_packet(packet, &mut server).is_err());
+}
@@ -0,0 +1,92 @@
+use libs::{
+    crypto::transport::{ClientHandshake, ServerIdentity},
+    packet::{DataPacket, PacketErrorKind, PacketType},
+    packet_manager,
+};
+
+#[test]
+fn handshake_round_trip() {
+    let identity = ServerIdentity::generate();
+
+    let (handshake, hello) = ClientHandshake::start();
+    let (reply, mut server) = identity.respond(hello).unwrap();
+    let mut client = handshake
+        .finish(reply, Some(&identity.public_key()))
+        .unwrap();
+
+    let packet = DataPacket::ok_message(String::from("hello"));
+    let packet = packet_manager::encrypt_packet(packet, &mut client).unwrap();
+    assert!(matches!(packet.get_type(), PacketType::Encrypted));
+    assert!(!packet.get_data().contains("hello"));
+
+    let packet = packet_manager::decrypt_packet(packet, &mut server).unwrap();
+    assert!(matches!(packet.get_type(), PacketType::Ok));
+    assert_eq!(packet.get_data(), "hello");
+
+    let packet = DataPacket::ok_message(String::from("world"));
+    let packet = packet_manager::encrypt_packet(packet, &mut server).unwrap();
+    let packet = packet_manager::decrypt_packet(packet, &mut client).unwrap();
+    assert_eq!(packet.get_data(), "world");
+}
+
+#[test]
+fn pinned_key_mismatch_is_rejected() {
+    let identity = ServerIdentity::generate();
+    let other = ServerIdentity::generate();
+
+    let (handshake, hello) = ClientHandshake::start();
+    let (reply, _) = identity.respond(hello).unwrap();
+
+    let err = handshake
+        .finish(reply, Some(&other.public_key()))
+        .err()
+        .unwrap();
+    assert_eq!(err.kind, PacketErrorKind::UnknownKey);
+}
+
+#[test]
+fn replayed_record_is_rejected() {
+    let identity = ServerIdentity::generate();
+
+    let (handshake, hello) = ClientHandshake::start();
+    let (reply, mut server) = identity.respond(hello).unwrap();
+    let mut client = handshake.finish(reply, None).unwrap();
+
+    let packet = DataPacket::ok_message(String::from("hello"));
+    let packet = packet_manager::encrypt_packet(packet, &mut client).unwrap();
+    let copy = DataPacket::new(packet.buf().unwrap()).unwrap();
+
+    packet_manager::decrypt_packet(packet, &mut server).unwrap();
+    let err = packet_manager::decrypt_packet(copy, &mut server)
+        .err()
+        .unwrap();
+    assert_eq!(err.kind, PacketErrorKind::Crypto);
+}
+
+#[test]
+fn request_id_and_event_flag_survive_encryption() {
+    let identity = ServerIdentity::generate();
+
+    let (handshake, hello) = ClientHandshake::start();
+    let (reply, mut server) = identity.respond(hello).unwrap();
+    let mut client = handshake.finish(reply, None).unwrap();
+
+    let mut packet = DataPacket::ok_message(String::from("reply"));
+    packet.set_request_id(42);
+
+    let packet = packet_manager::encrypt_packet(packet, &mut server).unwrap();
+    assert_eq!(packet.get_request_id(), 0);
+
+    let packet = packet_manager::decrypt_packet(packet, &mut client).unwrap();
+    assert_eq!(packet.get_request_id(), 42);
+    assert!(!packet.is_event());
+
+    let mut packet = DataPacket::ok_message(String::from("push"));
+    packet.set_request_id(42);
+    packet.set_event();
+
+    let packet = packet_manager::encrypt_packet(packet, &mut server).unwrap();
+    let packet = packet_manager::decrypt_packet(packet, &mut client).unwrap();
+    assert_eq!(packet.get_request_id(), 0);
+    assert!(packet.is_event());
+}
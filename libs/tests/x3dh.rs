@@ -0,0 +1,74 @@
+use libs::{crypto::x3dh::LocalKeys, BaseModels};
+
+fn user(name: &str) -> BaseModels::User {
+    BaseModels::User::simple(String::from(name), String::new())
+}
+
+#[test]
+fn initial_message_round_trip() {
+    let mut alice = LocalKeys::generate(1);
+    let mut bob = LocalKeys::generate(1);
+
+    let (bundle, mut prekeys) = bob.bundle().unwrap().split();
+    let bundle = bundle.with_one_time_prekey(prekeys.pop());
+
+    let (mut alice_ratchet, mut init) = alice.initiate(user("bob"), bundle, b"hi bob").unwrap();
+    init.set_user(user("alice"));
+
+    let (mut bob_ratchet, plaintext) = bob.accept(&init).unwrap();
+    assert_eq!(plaintext, b"hi bob");
+
+    let body = alice_ratchet.encrypt(b"second").unwrap();
+    assert_eq!(bob_ratchet.decrypt(&body).unwrap(), b"second");
+
+    let body = bob_ratchet.encrypt(b"reply").unwrap();
+    assert_eq!(alice_ratchet.decrypt(&body).unwrap(), b"reply");
+
+    assert!(bob.accept(&init).is_err());
+}
+
+#[test]
+fn bundle_without_one_time_prekey() {
+    let mut alice = LocalKeys::generate(0);
+    let mut bob = LocalKeys::generate(0);
+
+    let (bundle, _) = bob.bundle().unwrap().split();
+
+    let (_, mut init) = alice.initiate(user("bob"), bundle, b"hi").unwrap();
+    init.set_user(user("alice"));
+
+    let (_, plaintext) = bob.accept(&init).unwrap();
+    assert_eq!(plaintext, b"hi");
+}
+
+#[test]
+fn forged_signed_prekey_is_rejected() {
+    let mut alice = LocalKeys::generate(0);
+    let bob = LocalKeys::generate(0);
+    let mallory = LocalKeys::generate(0);
+
+    let (bundle, _) = bob.bundle().unwrap().split();
+    let (forged, _) = mallory.bundle().unwrap().split();
+
+    let bundle: libs::PacketModels::PreKeyBundle =
+        serde_json::from_str(&serde_json::to_string(&bundle).unwrap().replace(
+            &bundle.get_signed_prekey().get_key(),
+            &forged.get_signed_prekey().get_key(),
+        ))
+        .unwrap();
+
+    assert!(alice.initiate(user("bob"), bundle, b"hi").is_err());
+}
+
+#[test]
+fn changed_identity_is_rejected() {
+    let mut alice = LocalKeys::generate(0);
+    let bob = LocalKeys::generate(0);
+    let impostor = LocalKeys::generate(0);
+
+    let (bundle, _) = bob.bundle().unwrap().split();
+    alice.initiate(user("bob"), bundle, b"hi").unwrap();
+
+    let (bundle, _) = impostor.bundle().unwrap().split();
+    assert!(alice.initiate(user("bob"), bundle, b"hi").is_err());
+}
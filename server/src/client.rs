@@ -1,141 +1,962 @@
-use std::{net::TcpStream, sync::Arc};
+use std::sync::Arc;
 
 use libs::{
-    packet::{DataPacket, Packet, PacketType},
-    packet_manager, BaseModels, PacketModels,
+    crypto::{
+        srp::{self, SrpServer},
+        transport::{ServerIdentity, TransportCipher},
+    },
+    packet::{Codec, DataPacket, Packet, PacketError, PacketType},
+    packet_manager::Encryption,
+    BaseModels, PacketModels,
 };
 
-use crate::Database;
+use serde::{Deserialize, Serialize};
+use tokio::task::{self, JoinSet};
+use uuid::Uuid;
+
+use crate::connection::{PacketReader, PacketWriter};
+use crate::database::Storage;
+use crate::log;
+use crate::password::{self, Verified};
+use crate::registry::{Inbound, Inbox, Registry};
+use crate::token::TokenIssuer;
+use crate::Limits;
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
 
 pub struct Client {
-    stream: TcpStream,
+    encryption: Encryption,
+    codec: Codec,
     me: Option<BaseModels::User>,
-    db: Arc<Database>,
+    db: Arc<dyn Storage>,
+    identity: Arc<ServerIdentity>,
+    tokens: Arc<TokenIssuer>,
+    registry: Arc<Registry>,
+    limits: Limits,
+    listener: Option<u64>,
+    inbox: Inbox,
+    session: Option<String>,
+    handshake: Option<TransportCipher>,
+    srp: Option<SrpServer>,
+    session_key: Option<[u8; 32]>,
+    proven: Option<String>,
 }
 
 impl Client {
-    pub fn new(stream: TcpStream, db: Arc<Database>) -> Self {
+    fn new(
+        db: Arc<dyn Storage>,
+        identity: Arc<ServerIdentity>,
+        tokens: Arc<TokenIssuer>,
+        registry: Arc<Registry>,
+        limits: Limits,
+        inbox: Inbox,
+    ) -> Self {
         Client {
-            stream,
+            encryption: Encryption::new(),
+            codec: Codec::default(),
             db,
+            identity,
+            tokens,
+            registry,
+            limits,
+            listener: None,
+            inbox,
+            session: None,
             me: None,
+            handshake: None,
+            srp: None,
+            session_key: None,
+            proven: None,
         }
     }
 
-    pub fn run(&mut self) {
+    /// Serves one connection until the peer goes away or the server shuts
+    /// down. Frames are read on their own task so events can be delivered
+    /// while the client is idle, and both share one bounded inbox.
+    pub(crate) async fn serve(
+        reader: PacketReader,
+        mut writer: PacketWriter,
+        db: Arc<dyn Storage>,
+        identity: Arc<ServerIdentity>,
+        tokens: Arc<TokenIssuer>,
+        registry: Arc<Registry>,
+        limits: Limits,
+    ) {
+        let (inbox, mut inbound) = Inbox::new();
+
+        let mut client = Client::new(db, identity, tokens, Arc::clone(&registry), limits, inbox);
+
+        let connection = match registry.connect(client.inbox.clone()) {
+            Ok(id) => id,
+            Err(notice) => return client.close(&mut writer, notice).await,
+        };
+
+        let mut frames = JoinSet::new();
+        frames.spawn(Self::read_frames(reader, client.inbox.clone()));
+
+        let mut negotiated = None;
+
         loop {
-            let packet = match packet_manager::recv_packet(&mut self.stream) {
-                Ok(packet) => packet,
+            let inbound = tokio::select! {
+                inbound = inbound.recv() => match inbound {
+                    Some(inbound) => inbound,
+                    None => break,
+                },
+                _ = client.inbox.lagging() => {
+                    log::warn("Closing a connection that fell behind on events");
+                    break;
+                }
+            };
+
+            let packet = match inbound {
+                Inbound::Request(packet) => {
+                    client.codec = *negotiated.get_or_insert(packet.get_codec());
+                    packet
+                }
+                Inbound::Event(mut packet) => {
+                    packet.set_event();
+
+                    if let Err(err) = client.send(&mut writer, packet).await {
+                        log::debug(err);
+                        break;
+                    }
+
+                    continue;
+                }
+                Inbound::Closed(err) => {
+                    log::debug(err);
+                    break;
+                }
+                Inbound::Shutdown(notice) => {
+                    registry.disconnect(connection);
+                    return client.close(&mut writer, notice).await;
+                }
+            };
+
+            let packet = match client.handle(packet).await {
+                Ok(Some(packet)) => packet,
+                Ok(None) => continue,
                 Err(err) => {
-                    println!("{}", err.to_string());
+                    log::warn(err);
                     break;
                 }
             };
 
-            let packet: DataPacket = match packet.get_type() {
-                PacketType::PubKey => todo!(),
-                PacketType::Error => continue,
-                PacketType::Empty => todo!(),
-                PacketType::Ok => todo!(),
-                PacketType::E2E => match Packet::parse(&packet, "Packet Type Error E2E") {
-                    Ok(packet) => self.start_e2e(packet),
-                    Err(packet) => packet,
-                },
-                PacketType::Login => match Packet::parse(&packet, "Packet Type Error Login") {
-                    Ok(packet) => self.login_user(packet),
+            if let Err(err) = client.send(&mut writer, packet).await {
+                log::debug(err);
+                break;
+            }
+
+            if let Some(cipher) = client.handshake.take() {
+                client.encryption.set_cipher(cipher);
+            }
+
+            if let Some(key) = client.session_key.take() {
+                if let Err(err) = client.encryption.rekey(&key) {
+                    log::warn(err);
+                    break;
+                }
+            }
+        }
+
+        registry.disconnect(connection);
+        client.unsubscribe();
+        writer.close().await;
+    }
+
+    /// Decrypts and answers one request. `None` means no reply is owed.
+    async fn handle(&mut self, packet: DataPacket) -> Result<Option<DataPacket>, PacketError> {
+        let packet = self.encryption.open(packet)?;
+
+        let request_id = packet.get_request_id();
+
+        let mut packet: DataPacket = match packet.get_type() {
+            PacketType::PubKey => match Packet::parse(&packet, "Packet Type Error PubKey") {
+                Ok(packet) => self.exchange_keys(packet),
+                Err(packet) => packet,
+            },
+            _ if !self.encryption.is_encrypted() => {
+                DataPacket::error_message(String::from("Key Exchange Required"))
+            }
+            PacketType::Error => return Ok(None),
+            PacketType::E2E => match Packet::parse(&packet, "Packet Type Error E2E") {
+                Ok(packet) => self.start_e2e(packet).await,
+                Err(packet) => packet,
+            },
+            PacketType::Login => match Packet::parse(&packet, "Packet Type Error Login") {
+                Ok(packet) => self.migrate_password(packet).await,
+                Err(packet) => packet,
+            },
+            PacketType::Logout => self.logout().await,
+            PacketType::Resume => match Packet::parse(&packet, "Packet Type Error Resume") {
+                Ok(packet) => self.resume(packet).await,
+                Err(packet) => packet,
+            },
+            PacketType::SrpRegister => {
+                match Packet::parse(&packet, "Packet Type Error SrpRegister") {
+                    Ok(packet) => self.srp_register(packet).await,
                     Err(packet) => packet,
-                },
-                PacketType::Logout => {
-                    self.me = None;
-                    DataPacket::ok_message(String::from("Logout Successfully"))
-                }
-                PacketType::Register => {
-                    match Packet::parse(&packet, "Packet Type Error Register") {
-                        Ok(packet) => self.register_user(packet),
-                        Err(packet) => packet,
-                    }
                 }
-                PacketType::CreateGroup => {
-                    match Packet::parse(&packet, "Packet Type Error CreateGroup") {
-                        Ok(packet) => self.create_group(packet),
-                        Err(packet) => packet,
-                    }
+            }
+            PacketType::SrpStart => match Packet::parse(&packet, "Packet Type Error SrpStart") {
+                Ok(packet) => self.srp_start(packet).await,
+                Err(packet) => packet,
+            },
+            PacketType::SrpProof => match Packet::parse(&packet, "Packet Type Error SrpProof") {
+                Ok(packet) => self.srp_proof(packet).await,
+                Err(packet) => packet,
+            },
+            PacketType::CreateGroup => {
+                match Packet::parse(&packet, "Packet Type Error CreateGroup") {
+                    Ok(packet) => self.create_group(packet).await,
+                    Err(packet) => packet,
                 }
-                PacketType::AddUser => match Packet::parse(&packet, "Packet Type Error AddUser") {
-                    Ok(packet) => self.add_user(packet),
+            }
+            PacketType::AddUser => match Packet::parse(&packet, "Packet Type Error AddUser") {
+                Ok(packet) => self.add_user(packet).await,
+                Err(packet) => packet,
+            },
+            PacketType::RemoveUser => {
+                match Packet::parse(&packet, "Packet Type Error RemoveUser") {
+                    Ok(packet) => self.remove_user(packet).await,
                     Err(packet) => packet,
-                },
+                }
+            }
 
-                PacketType::CreateMessage => {
-                    match Packet::parse(&packet, "Packet Type Error CreateMessage") {
-                        Ok(packet) => self.create_message(packet),
-                        Err(packet) => packet,
-                    }
+            PacketType::CreateMessage => {
+                match Packet::parse(&packet, "Packet Type Error CreateMessage") {
+                    Ok(packet) => self.create_message(packet).await,
+                    Err(packet) => packet,
                 }
-                PacketType::GetMessages => {
-                    match Packet::<PacketModels::Empty>::parse(
-                        &packet,
-                        "Packet Type Error GetMessages",
-                    ) {
-                        Ok(_) => self.get_messages(),
-                        Err(packet) => packet,
-                    }
+            }
+            PacketType::SearchUsers => {
+                match Packet::parse(&packet, "Packet Type Error SearchUsers") {
+                    Ok(packet) => self.search_users(packet).await,
+                    Err(packet) => packet,
                 }
-                PacketType::GetChats => {
-                    match Packet::<PacketModels::Empty>::parse(
-                        &packet,
-                        "Packet Type Error GetChats",
-                    ) {
-                        Ok(_) => self.get_chats(),
-                        Err(packet) => packet,
-                    }
+            }
+            PacketType::GetMessages => {
+                match Packet::parse(&packet, "Packet Type Error GetMessages") {
+                    Ok(packet) => self.get_messages(packet).await,
+                    Err(packet) => packet,
+                }
+            }
+            PacketType::GetChats => {
+                match Packet::<PacketModels::Empty>::parse(&packet, "Packet Type Error GetChats") {
+                    Ok(_) => self.get_chats().await,
+                    Err(packet) => packet,
+                }
+            }
+            PacketType::Listen => {
+                match Packet::<PacketModels::Empty>::parse(&packet, "Packet Type Error Listen") {
+                    Ok(_) => self.listen(),
+                    Err(packet) => packet,
+                }
+            }
+            PacketType::PublishKeys => {
+                match Packet::parse(&packet, "Packet Type Error PublishKeys") {
+                    Ok(packet) => self.publish_keys(packet).await,
+                    Err(packet) => packet,
+                }
+            }
+            PacketType::GetKeyBundle => {
+                match Packet::parse(&packet, "Packet Type Error GetKeyBundle") {
+                    Ok(packet) => self.get_key_bundle(packet).await,
+                    Err(packet) => packet,
+                }
+            }
+            PacketType::GetE2E => {
+                match Packet::<PacketModels::Empty>::parse(&packet, "Packet Type Error GetE2E") {
+                    Ok(_) => self.get_e2e().await,
+                    Err(packet) => packet,
+                }
+            }
+            PacketType::SenderKey => match Packet::parse(&packet, "Packet Type Error SenderKey") {
+                Ok(packet) => self.send_sender_key(packet).await,
+                Err(packet) => packet,
+            },
+            PacketType::GetSenderKeys => {
+                match Packet::<PacketModels::Empty>::parse(
+                    &packet,
+                    "Packet Type Error GetSenderKeys",
+                ) {
+                    Ok(_) => self.get_sender_keys().await,
+                    Err(packet) => packet,
+                }
+            }
+            _ => DataPacket::error_message(String::from("Packet Type Error")),
+        };
+
+        packet.set_request_id(request_id);
+
+        Ok(Some(packet))
+    }
+
+    async fn send(
+        &mut self,
+        writer: &mut PacketWriter,
+        packet: DataPacket,
+    ) -> Result<(), PacketError> {
+        let packet = packet.transcode(self.codec)?;
+        let packet = self.encryption.seal(packet)?;
+
+        writer.send(packet).await
+    }
+
+    async fn close(&mut self, writer: &mut PacketWriter, mut notice: DataPacket) {
+        notice.set_event();
+
+        if let Err(err) = self.send(writer, notice).await {
+            log::debug(err);
+        }
+
+        self.unsubscribe();
+        writer.close().await;
+    }
+
+    async fn read_frames(mut reader: PacketReader, inbox: Inbox) {
+        loop {
+            let inbound = match reader.recv().await {
+                Ok(packet) => Inbound::Request(packet),
+                Err(err) => {
+                    inbox.send(Inbound::Closed(err)).await;
+                    break;
                 }
-                PacketType::Listen => todo!(),
-                _ => DataPacket::error_message(String::from("Packet Type Error")),
             };
 
-            if let Err(err) = packet_manager::send_packet(&mut self.stream, packet) {
-                println!("{}", err.to_string());
+            if !inbox.send(inbound).await {
                 break;
             }
         }
     }
+    fn listen(&mut self) -> DataPacket {
+        let username = match &self.me {
+            Some(me) => me.get_username(),
+            None => return DataPacket::error_message(String::from("Login Required")),
+        };
+
+        if self.listener.is_none() {
+            self.listener = Some(self.registry.subscribe(username, self.inbox.clone()));
+        }
+
+        DataPacket::ok_message(String::from("Listening"))
+    }
+    fn unsubscribe(&mut self) {
+        if let (Some(id), Some(me)) = (self.listener.take(), &self.me) {
+            self.registry.unsubscribe(&me.get_username(), id);
+        }
+    }
+    fn publish<T>(&self, usernames: &[String], p_type: PacketType, body: T)
+    where
+        T: Serialize + for<'a> Deserialize<'a>,
+    {
+        match Packet::new(p_type, body).to() {
+            Ok(packet) => {
+                self.registry.publish(usernames, &packet);
+            }
+            Err(err) => log::error(err),
+        }
+    }
+    fn exchange_keys(&mut self, packet: Packet<PacketModels::PubKey>) -> DataPacket {
+        if self.encryption.is_encrypted() {
+            return DataPacket::error_message(String::from("Keys Already Exchanged"));
+        }
+
+        let (reply, cipher) = match self.identity.respond(packet.get().1) {
+            Ok(res) => res,
+            Err(err) => return DataPacket::error_message(err.message),
+        };
+
+        match Packet::new(PacketType::PubKey, reply).encode(self.codec) {
+            Ok(packet) => {
+                self.handshake = Some(cipher);
+                packet
+            }
+            Err(err) => DataPacket::error_message(err.to_string()),
+        }
+    }
+    async fn start_e2e(&self, packet: Packet<PacketModels::E2E>) -> DataPacket {
+        let me = match &self.me {
+            Some(me) => me,
+            None => return DataPacket::error_message(String::from("Login Required")),
+        };
+
+        let mut init = packet.get().1;
+
+        let recipient = match self.db.get_user(init.get_user().get_username()).await {
+            Ok(user) => user.get_username(),
+            Err(err) => return DataPacket::error_message(err.to_string()),
+        };
 
-    fn exchange_keys(&self) -> DataPacket {
-        todo!()
+        init.set_user(BaseModels::User::full(
+            me.get_name(),
+            me.get_username(),
+            String::new(),
+        ));
+
+        match self.db.push_e2e(recipient, init).await {
+            Ok(()) => DataPacket::ok_message(String::from("E2E Session Started")),
+            Err(err) => DataPacket::error_message(err.to_string()),
+        }
     }
-    fn start_e2e(&self, packet: Packet<PacketModels::E2E>) -> DataPacket {
-        todo!()
+    async fn publish_keys(&self, packet: Packet<PacketModels::KeyBundle>) -> DataPacket {
+        let me = match &self.me {
+            Some(me) => me,
+            None => return DataPacket::error_message(String::from("Login Required")),
+        };
+
+        match self
+            .db
+            .set_key_bundle(me.get_username(), packet.get().1)
+            .await
+        {
+            Ok(()) => DataPacket::ok_message(String::from("Keys Published Successfully")),
+            Err(err) => DataPacket::error_message(err.to_string()),
+        }
     }
-    fn register_user(&self, packet: Packet<BaseModels::User>) -> DataPacket {
-        let res_packet = match self.db.create_user(packet.get().1) {
-            Ok(()) => DataPacket::ok_message(String::from("User Created Successfully")),
+    async fn get_key_bundle(&self, packet: Packet<BaseModels::User>) -> DataPacket {
+        if self.me.is_none() {
+            return DataPacket::error_message(String::from("Login Required"));
+        }
+
+        match self.db.take_key_bundle(packet.get().1.get_key()).await {
+            Ok(bundle) => self.reply(PacketType::GetKeyBundle, bundle),
             Err(err) => DataPacket::error_message(err.to_string()),
+        }
+    }
+    async fn get_e2e(&self) -> DataPacket {
+        let me = match &self.me {
+            Some(me) => me,
+            None => return DataPacket::error_message(String::from("Login Required")),
         };
 
-        res_packet
+        match self.db.take_e2e(me.get_username()).await {
+            Ok(sessions) => self.reply(PacketType::GetE2E, PacketModels::E2EInbox::new(sessions)),
+            Err(err) => DataPacket::error_message(err.to_string()),
+        }
     }
-    fn login_user(&self, packet: Packet<BaseModels::User>) -> DataPacket {
-        let user = match self.db.get_user(packet.get().1.get_key()) {
-            Ok(user) => user,
+    async fn send_sender_key(&self, packet: Packet<PacketModels::SenderKey>) -> DataPacket {
+        let me = match &self.me {
+            Some(me) => me,
+            None => return DataPacket::error_message(String::from("Login Required")),
+        };
+
+        let mut sender_key = packet.get().1;
+
+        let recipient = match self.db.get_user(sender_key.get_user().get_username()).await {
+            Ok(user) => user.get_username(),
             Err(err) => return DataPacket::error_message(err.to_string()),
         };
 
-        DataPacket::ok_message(String::from("Login Successfully"))
+        for username in [me.get_username(), recipient.clone()] {
+            match self
+                .db
+                .is_group_member(sender_key.get_group(), username)
+                .await
+            {
+                Ok(true) => {}
+                Ok(false) => return DataPacket::error_message(String::from("Not A Group Member")),
+                Err(err) => return DataPacket::error_message(err.to_string()),
+            }
+        }
+
+        sender_key.set_user(BaseModels::User::full(
+            me.get_name(),
+            me.get_username(),
+            String::new(),
+        ));
+
+        match self.db.push_sender_key(recipient, sender_key).await {
+            Ok(()) => DataPacket::ok_message(String::from("Sender Key Delivered")),
+            Err(err) => DataPacket::error_message(err.to_string()),
+        }
     }
-    fn create_group(&self, packet: Packet<BaseModels::Group>) -> DataPacket {
-        todo!()
+    async fn get_sender_keys(&self) -> DataPacket {
+        let me = match &self.me {
+            Some(me) => me,
+            None => return DataPacket::error_message(String::from("Login Required")),
+        };
+
+        match self.db.take_sender_keys(me.get_username()).await {
+            Ok(sender_keys) => self.reply(
+                PacketType::GetSenderKeys,
+                PacketModels::SenderKeyInbox::new(sender_keys),
+            ),
+            Err(err) => DataPacket::error_message(err.to_string()),
+        }
     }
-    fn add_user(&self, packet: Packet<BaseModels::Member>) -> DataPacket {
-        todo!()
+    async fn srp_register(&mut self, packet: Packet<PacketModels::SrpRegistration>) -> DataPacket {
+        let registration = packet.get().1;
+        let username = registration.get_user().get_username();
+
+        // replacing a verifier takes a fresh proof of the old password on
+        // this connection; a resumed session alone is not enough
+        let result = match &self.me {
+            Some(me) if me.get_username() == username => {
+                if self.proven.take().as_ref() != Some(&username) {
+                    return DataPacket::error_message(String::from("Password Proof Required"));
+                }
+
+                self.db
+                    .set_srp(
+                        username,
+                        registration.get_salt(),
+                        registration.get_verifier(),
+                    )
+                    .await
+            }
+            Some(_) => return DataPacket::error_message(String::from("Permission Denied")),
+            None => self.db.create_srp_user(registration).await,
+        };
+
+        match result {
+            Ok(()) => DataPacket::ok_message(String::from("Registered Successfully")),
+            Err(err) => DataPacket::error_message(err.to_string()),
+        }
+    }
+    async fn srp_start(&mut self, packet: Packet<PacketModels::SrpStart>) -> DataPacket {
+        let start = packet.get().1;
+        let username = start.get_username();
+
+        let stored = match self.db.get_srp(username.clone()).await {
+            Ok(stored) => stored,
+            Err(err) => return DataPacket::error_message(err.to_string()),
+        };
+
+        let (salt, verifier) = match stored {
+            Some((salt, verifier)) => (salt, Some(verifier)),
+            // accounts still on a password hash get a fake challenge too;
+            // their proof fails with a migration error instead
+            None => {
+                let salt = match self
+                    .identity
+                    .derive_secret(b"secure_chat/srp/v1/unknown")
+                    .and_then(|seed| srp::fake_salt(&seed, &username))
+                {
+                    Ok(salt) => salt,
+                    Err(err) => return DataPacket::error_message(err.message),
+                };
+
+                (salt, None)
+            }
+        };
+
+        match SrpServer::start(&start, &salt, verifier.as_deref()) {
+            Ok((server, challenge)) => {
+                self.srp = Some(server);
+                self.reply(PacketType::SrpStart, challenge)
+            }
+            Err(err) => DataPacket::error_message(err.message),
+        }
+    }
+    async fn srp_proof(&mut self, packet: Packet<PacketModels::SrpProof>) -> DataPacket {
+        let server = match self.srp.take() {
+            Some(server) => server,
+            None => return DataPacket::error_message(String::from("SRP Start Required")),
+        };
+
+        let username = server.get_username();
+
+        let (key, proof) = match server.finish(&packet.get().1) {
+            Ok(res) => res,
+            // only a failed proof tells a legacy account apart, so clients
+            // know when offering the password through `migrate_password`
+            // is expected
+            Err(_) if self.needs_migration(username.clone()).await => {
+                return DataPacket::error_message(String::from("Password Migration Required"))
+            }
+            Err(_) => {
+                return DataPacket::error_message(String::from("Invalid Username or Password"))
+            }
+        };
+
+        let user = match self.db.get_user(username).await {
+            Ok(user) => user.redact(),
+            Err(err) => return DataPacket::error_message(err.to_string()),
+        };
+
+        let token = match self.issue_token(user.get_username()).await {
+            Ok(token) => token,
+            Err(err) => return DataPacket::error_message(err),
+        };
+
+        let packet = self.reply(
+            PacketType::SrpProof,
+            PacketModels::SrpVerify::new(
+                BaseModels::User::full(user.get_name(), user.get_username(), String::new()),
+                proof,
+                Some(token),
+            ),
+        );
+
+        self.unsubscribe();
+        self.proven = Some(user.get_username());
+        self.me = Some(user);
+        self.session_key = Some(key);
+
+        packet
+    }
+    /// Whether `username` exists but still has a password hash instead of
+    /// an SRP verifier.
+    async fn needs_migration(&self, username: String) -> bool {
+        match self.db.get_srp(username.clone()).await {
+            Ok(None) => self.db.get_user(username).await.is_ok(),
+            _ => false,
+        }
+    }
+    async fn issue_token(
+        &mut self,
+        username: String,
+    ) -> Result<PacketModels::SessionToken, String> {
+        let (claims, token) = self.tokens.issue(username)?;
+
+        if let Err(err) = self
+            .db
+            .create_session(claims.id.clone(), claims.username, self.tokens.ttl())
+            .await
+        {
+            return Err(err.to_string());
+        }
+
+        self.session = Some(claims.id);
+
+        Ok(token)
+    }
+    async fn resume(&mut self, packet: Packet<PacketModels::SessionToken>) -> DataPacket {
+        let claims = match self.tokens.verify(&packet.get().1.get_token()) {
+            Ok(claims) => claims,
+            Err(err) => return DataPacket::error_message(err),
+        };
+
+        match self
+            .db
+            .is_session_active(claims.id.clone(), claims.username.clone())
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => return DataPacket::error_message(String::from("Session Token Revoked")),
+            Err(err) => return DataPacket::error_message(err.to_string()),
+        }
+
+        self.restore(claims.username, claims.id).await
     }
-    fn create_message(&self, packet: Packet<BaseModels::Message>) -> DataPacket {
-        todo!()
+    async fn logout(&mut self) -> DataPacket {
+        if let (Some(id), Some(me)) = (self.session.take(), &self.me) {
+            if let Err(err) = self.db.revoke_session(id, me.get_username()).await {
+                return DataPacket::error_message(err.to_string());
+            }
+        }
+
+        self.unsubscribe();
+        self.me = None;
+        self.proven = None;
+
+        DataPacket::ok_message(String::from("Logout Successfully"))
     }
-    fn get_messages(&self) -> DataPacket {
-        todo!()
+    /// The one place a password still reaches the server: accounts created
+    /// before SRP trade their password hash for a verifier, once. The
+    /// connection stays logged out; the client follows up with SRP.
+    async fn migrate_password(&mut self, packet: Packet<BaseModels::User>) -> DataPacket {
+        let user = packet.get().1;
+        let username = user.get_username();
+        let pass = user.get_password();
+
+        let invalid = || DataPacket::error_message(String::from("Invalid Username or Password"));
+
+        let stored = match self.db.get_srp(username.clone()).await {
+            Ok(None) => self.db.get_user(username.clone()).await.ok(),
+            Ok(Some(_)) => None,
+            Err(err) => return DataPacket::error_message(err.to_string()),
+        };
+
+        // argon2 is too slow to run on an async worker, both to check the
+        // old hash and to derive the new verifier
+        let stored = stored.map(|stored| stored.get_password());
+        let registered = username.clone();
+
+        let migrated = task::spawn_blocking(move || {
+            let verified = match stored {
+                Some(stored) => password::verify(&pass, &stored),
+                None => {
+                    password::verify_dummy(&pass);
+                    Verified::Invalid
+                }
+            };
+
+            match verified {
+                Verified::Invalid => None,
+                _ => Some(srp::register(&registered, &pass)),
+            }
+        });
+
+        let (salt, verifier) = match migrated.await {
+            Ok(Some(Ok(res))) => res,
+            Ok(Some(Err(err))) => return DataPacket::error_message(err.message),
+            Ok(None) => return invalid(),
+            Err(err) => return DataPacket::error_message(err.to_string()),
+        };
+
+        match self.db.set_srp(username, salt, verifier).await {
+            Ok(()) => DataPacket::ok_message(String::from("Password Migrated")),
+            Err(err) => DataPacket::error_message(err.to_string()),
+        }
+    }
+    /// Logs the connection in as the holder of session `id`.
+    async fn restore(&mut self, username: String, id: String) -> DataPacket {
+        let user = match self.db.get_user(username).await {
+            Ok(user) => user.redact(),
+            Err(err) => return DataPacket::error_message(err.to_string()),
+        };
+
+        if let Err(err) = self.db.index_user(user.get_username()).await {
+            log::warn(err);
+        }
+
+        let packet = self.reply(
+            PacketType::Login,
+            BaseModels::User::full(user.get_name(), user.get_username(), String::new()),
+        );
+
+        self.unsubscribe();
+        self.me = Some(user);
+        self.session = Some(id);
+        self.proven = None;
+
+        packet
     }
-    fn get_chats(&self) -> DataPacket {
-        todo!()
+    fn reply<T>(&self, p_type: PacketType, body: T) -> DataPacket
+    where
+        T: Serialize + for<'a> Deserialize<'a>,
+    {
+        match Packet::new(p_type, body).encode(self.codec) {
+            Ok(packet) => packet,
+            Err(err) => DataPacket::error_message(err.to_string()),
+        }
+    }
+    async fn create_group(&self, packet: Packet<BaseModels::Group>) -> DataPacket {
+        let me = match &self.me {
+            Some(me) => me,
+            None => return DataPacket::error_message(String::from("Login Required")),
+        };
+
+        let name = packet.get().1.get_name();
+
+        if name.trim().is_empty() {
+            return DataPacket::error_message(String::from("Group Name Required"));
+        }
+
+        match self.db.create_group(me.get_username(), name).await {
+            Ok(group) => {
+                self.publish(
+                    &group.get_members(),
+                    PacketType::Refresh,
+                    PacketModels::Refresh::new(group.get_id(), true, false),
+                );
+
+                self.reply(PacketType::CreateGroup, group)
+            }
+            Err(err) => DataPacket::error_message(err.to_string()),
+        }
+    }
+    async fn add_user(&self, packet: Packet<BaseModels::Member>) -> DataPacket {
+        let me = match &self.me {
+            Some(me) => me,
+            None => return DataPacket::error_message(String::from("Login Required")),
+        };
+
+        let member = packet.get().1;
+
+        match self
+            .db
+            .add_member(
+                member.get_group().get_id(),
+                me.get_username(),
+                member.get_user().get_username(),
+            )
+            .await
+        {
+            Ok(group) => {
+                self.publish(
+                    &group.get_members(),
+                    PacketType::Refresh,
+                    PacketModels::Refresh::new(group.get_id(), true, false),
+                );
+
+                self.reply(PacketType::AddUser, group)
+            }
+            Err(err) => DataPacket::error_message(err.to_string()),
+        }
+    }
+    async fn remove_user(&self, packet: Packet<BaseModels::Member>) -> DataPacket {
+        let me = match &self.me {
+            Some(me) => me,
+            None => return DataPacket::error_message(String::from("Login Required")),
+        };
+
+        let member = packet.get().1;
+        let username = member.get_user().get_username();
+
+        match self
+            .db
+            .remove_member(
+                member.get_group().get_id(),
+                me.get_username(),
+                username.clone(),
+            )
+            .await
+        {
+            Ok(group) => {
+                let mut notify = group.get_members();
+                notify.push(username);
+
+                self.publish(
+                    &notify,
+                    PacketType::Refresh,
+                    PacketModels::Refresh::new(group.get_id(), true, false),
+                );
+
+                self.reply(PacketType::RemoveUser, group)
+            }
+            Err(err) => DataPacket::error_message(err.to_string()),
+        }
+    }
+    async fn search_users(&self, packet: Packet<PacketModels::UserSearch>) -> DataPacket {
+        if self.me.is_none() {
+            return DataPacket::error_message(String::from("Login Required"));
+        }
+
+        let query = packet.get().1.get_query();
+        let query = query.trim();
+
+        if query.is_empty() {
+            return DataPacket::error_message(String::from("Search Query Required"));
+        }
+
+        let usernames = match self
+            .db
+            .search_users(String::from(query), self.limits.max_search_results as isize)
+            .await
+        {
+            Ok(usernames) => usernames,
+            Err(err) => return DataPacket::error_message(err.to_string()),
+        };
+
+        let mut users = Vec::with_capacity(usernames.len());
+        for username in usernames {
+            if let Ok(user) = self.db.get_user(username).await {
+                users.push(BaseModels::User::full(
+                    user.get_name(),
+                    user.get_username(),
+                    String::new(),
+                ));
+            }
+        }
+
+        self.reply(PacketType::SearchUsers, PacketModels::Users::new(users))
+    }
+    async fn create_message(&self, packet: Packet<BaseModels::Message>) -> DataPacket {
+        let me = match &self.me {
+            Some(me) => me,
+            None => return DataPacket::error_message(String::from("Login Required")),
+        };
+
+        let message = packet.get().1;
+
+        if message.get_body().is_empty() {
+            return DataPacket::error_message(String::from("Message Body Required"));
+        }
+
+        let group = match self
+            .member_group(message.get_member().get_group().get_id())
+            .await
+        {
+            Ok(group) => group,
+            Err(packet) => return packet,
+        };
+
+        let body = message.get_body();
+        let members = group.get_members();
+        let member = BaseModels::Member::new(
+            BaseModels::Group::full(
+                group.get_id(),
+                group.get_name(),
+                group.get_owner(),
+                Vec::new(),
+                Vec::new(),
+            ),
+            BaseModels::User::full(me.get_name(), me.get_username(), String::new()),
+        );
+
+        let message = BaseModels::Message::new(member, body).with_id(Uuid::new_v4());
+
+        match self.db.create_message(&message).await {
+            Ok(()) => match Packet::new(PacketType::CreateMessage, message).encode(self.codec) {
+                Ok(packet) => {
+                    self.registry.publish(&members, &packet);
+                    packet
+                }
+                Err(err) => DataPacket::error_message(err.to_string()),
+            },
+            Err(err) => DataPacket::error_message(err.to_string()),
+        }
+    }
+    async fn get_messages(&self, packet: Packet<PacketModels::GetMessages>) -> DataPacket {
+        let query = packet.get().1;
+
+        let group = match self.member_group(query.get_group()).await {
+            Ok(group) => group,
+            Err(packet) => return packet,
+        };
+
+        let limit = match query.get_limit() {
+            0 => DEFAULT_PAGE_SIZE,
+            limit => limit.min(self.limits.max_page_size),
+        };
+
+        let id = group.get_id();
+
+        match self.db.get_messages(group, query.get_cursor(), limit).await {
+            Ok(page) => {
+                if query.get_cursor().is_none() {
+                    if let Some(me) = &self.me {
+                        if let Err(err) = self.db.mark_read(id, me.get_username()).await {
+                            log::warn(err);
+                        }
+                    }
+                }
+
+                self.reply(PacketType::GetMessages, page)
+            }
+            Err(err) => DataPacket::error_message(err.to_string()),
+        }
+    }
+    async fn member_group(&self, id: Uuid) -> Result<BaseModels::Group, DataPacket> {
+        let me = match &self.me {
+            Some(me) => me,
+            None => return Err(DataPacket::error_message(String::from("Login Required"))),
+        };
+
+        let group = match self.db.get_group(id).await {
+            Ok(group) => group,
+            Err(err) => return Err(DataPacket::error_message(err.to_string())),
+        };
+
+        match group.is_member(&me.get_username()) {
+            true => Ok(group),
+            false => Err(DataPacket::error_message(String::from(
+                "Not A Group Member",
+            ))),
+        }
+    }
+    async fn get_chats(&self) -> DataPacket {
+        let me = match &self.me {
+            Some(me) => me,
+            None => return DataPacket::error_message(String::from("Login Required")),
+        };
+
+        match self.db.get_chats(me.get_username()).await {
+            Ok(chats) => self.reply(PacketType::GetChats, chats),
+            Err(err) => DataPacket::error_message(err.to_string()),
+        }
     }
 }
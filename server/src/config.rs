@@ -0,0 +1,415 @@
+use std::{env, fmt, fs, path::PathBuf};
+
+use libs::packet_manager::DEFAULT_MAX_FRAME_SIZE;
+use serde::Deserialize;
+
+use crate::database::Backend;
+use crate::log::Level;
+
+pub const DEFAULT_ADDR: &str = "127.0.0.1:8080";
+pub const DEFAULT_WORKERS: usize = 4;
+pub const DEFAULT_MAX_PAGE_SIZE: u32 = 200;
+pub const DEFAULT_MAX_SEARCH_RESULTS: u32 = 20;
+pub const DEFAULT_SHUTDOWN_TIMEOUT: u32 = 10;
+
+const ENV_PREFIX: &str = "SECURE_CHAT_";
+
+/// Options that may come from `SECURE_CHAT_*` variables. Clients share the
+/// prefix for their own settings, so anything else is left alone.
+const ENV_OPTIONS: [&str; 12] = [
+    "addr",
+    "websocket",
+    "workers",
+    "storage",
+    "tls-cert",
+    "tls-key",
+    "max-frame-size",
+    "max-page-size",
+    "max-search-results",
+    "shutdown-timeout",
+    "log-level",
+    "config",
+];
+
+const USAGE: &str = "\
+Usage: server [OPTIONS]
+
+Options:
+  -c, --config <PATH>              TOML config file       [env: SECURE_CHAT_CONFIG]
+  -a, --addr <ADDR>                host:port or unix:<path>
+                                                          [env: SECURE_CHAT_ADDR]
+      --websocket <ADDR>           WebSocket host:port or unix:<path>
+                                                          [env: SECURE_CHAT_WEBSOCKET]
+  -w, --workers <N>                Worker threads         [env: SECURE_CHAT_WORKERS]
+  -s, --storage <URL>              memory, redis://… or sqlite://<path>
+                                                          [env: SECURE_CHAT_STORAGE]
+      --tls-cert <PATH>            TLS certificate chain  [env: SECURE_CHAT_TLS_CERT]
+      --tls-key <PATH>             TLS private key        [env: SECURE_CHAT_TLS_KEY]
+      --max-frame-size <BYTES>     Largest accepted frame [env: SECURE_CHAT_MAX_FRAME_SIZE]
+      --max-page-size <N>          Largest message page   [env: SECURE_CHAT_MAX_PAGE_SIZE]
+      --max-search-results <N>     Largest user search    [env: SECURE_CHAT_MAX_SEARCH_RESULTS]
+      --shutdown-timeout <SECS>    Drain deadline on exit [env: SECURE_CHAT_SHUTDOWN_TIMEOUT]
+  -l, --log-level <LEVEL>          error, warn, info or debug
+                                                          [env: SECURE_CHAT_LOG_LEVEL]
+  -h, --help                       Print this help
+
+Precedence: command line, then environment, then config file, then defaults.";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub addr: String,
+    pub websocket: Option<String>,
+    pub max_workers: usize,
+    pub storage: Backend,
+    pub tls: Option<TlsConfig>,
+    pub limits: Limits,
+    pub log_level: Level,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    pub max_frame_size: u32,
+    pub max_page_size: u32,
+    pub max_search_results: u32,
+    pub shutdown_timeout: u32,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            max_page_size: DEFAULT_MAX_PAGE_SIZE,
+            max_search_results: DEFAULT_MAX_SEARCH_RESULTS,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            addr: String::from(DEFAULT_ADDR),
+            websocket: None,
+            max_workers: DEFAULT_WORKERS,
+            storage: Backend::default(),
+            tls: None,
+            limits: Limits::default(),
+            log_level: Level::Info,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    Help,
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Help => write!(f, "{}", USAGE),
+            ConfigError::Invalid(message) => write!(f, "{}\n\n{}", message, USAGE),
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    addr: Option<String>,
+    websocket: Option<String>,
+    workers: Option<usize>,
+    storage: Option<String>,
+    log_level: Option<String>,
+    tls: Option<FileTls>,
+    limits: Option<FileLimits>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileTls {
+    cert: Option<PathBuf>,
+    key: Option<PathBuf>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileLimits {
+    max_frame_size: Option<u32>,
+    max_page_size: Option<u32>,
+    max_search_results: Option<u32>,
+    shutdown_timeout: Option<u32>,
+}
+
+/// Settings collected before validation; TLS paths are only checked as a pair
+/// once every source has been applied.
+#[derive(Default)]
+struct Builder {
+    config: Config,
+    tls_cert: Option<PathBuf>,
+    tls_key: Option<PathBuf>,
+}
+
+impl Config {
+    pub fn new(mut args: env::Args) -> Result<Self, ConfigError> {
+        args.next();
+
+        Self::parse(args, env::vars())
+    }
+
+    pub fn parse<A, V>(args: A, vars: V) -> Result<Self, ConfigError>
+    where
+        A: IntoIterator<Item = String>,
+        V: IntoIterator<Item = (String, String)>,
+    {
+        let flags = parse_flags(args)?;
+
+        let vars: Vec<(String, String)> = vars
+            .into_iter()
+            .filter(|(key, _)| key.starts_with(ENV_PREFIX))
+            .collect();
+
+        let path = match flags.iter().find(|(key, _)| key == "config") {
+            Some((_, path)) => Some(path.clone()),
+            None => vars
+                .iter()
+                .find(|(key, _)| key == "SECURE_CHAT_CONFIG")
+                .map(|(_, path)| path.clone()),
+        };
+
+        let mut builder = Builder::default();
+
+        if let Some(path) = path {
+            builder.apply_file(&path)?;
+        }
+
+        for (key, value) in &vars {
+            let name = key[ENV_PREFIX.len()..]
+                .to_ascii_lowercase()
+                .replace('_', "-");
+
+            if name == "config" || !ENV_OPTIONS.contains(&name.as_str()) {
+                continue;
+            }
+
+            builder.set(&name, value, key)?;
+        }
+
+        for (name, value) in &flags {
+            if name == "config" {
+                continue;
+            }
+
+            builder.set(name, value, &format!("--{}", name))?;
+        }
+
+        builder.build()
+    }
+}
+
+impl Builder {
+    fn apply_file(&mut self, path: &str) -> Result<(), ConfigError> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                return Err(ConfigError::Invalid(format!(
+                    "Unable to read config file {}: {}",
+                    path, err
+                )))
+            }
+        };
+
+        let file: FileConfig = match toml::from_str(&contents) {
+            Ok(file) => file,
+            Err(err) => {
+                return Err(ConfigError::Invalid(format!(
+                    "Invalid config file {}: {}",
+                    path, err
+                )))
+            }
+        };
+
+        let origin = |key: &str| format!("{} in {}", key, path);
+
+        if let Some(addr) = file.addr {
+            self.set("addr", &addr, &origin("addr"))?;
+        }
+
+        if let Some(addr) = file.websocket {
+            self.set("websocket", &addr, &origin("websocket"))?;
+        }
+
+        if let Some(workers) = file.workers {
+            self.set("workers", &workers.to_string(), &origin("workers"))?;
+        }
+
+        if let Some(storage) = file.storage {
+            self.set("storage", &storage, &origin("storage"))?;
+        }
+
+        if let Some(level) = file.log_level {
+            self.set("log-level", &level, &origin("log_level"))?;
+        }
+
+        let tls = file.tls.unwrap_or_default();
+        self.tls_cert = tls.cert.or(self.tls_cert.take());
+        self.tls_key = tls.key.or(self.tls_key.take());
+
+        let limits = file.limits.unwrap_or_default();
+
+        if let Some(size) = limits.max_frame_size {
+            let key = origin("limits.max_frame_size");
+            self.set("max-frame-size", &size.to_string(), &key)?;
+        }
+
+        if let Some(size) = limits.max_page_size {
+            let key = origin("limits.max_page_size");
+            self.set("max-page-size", &size.to_string(), &key)?;
+        }
+
+        if let Some(count) = limits.max_search_results {
+            let key = origin("limits.max_search_results");
+            self.set("max-search-results", &count.to_string(), &key)?;
+        }
+
+        if let Some(seconds) = limits.shutdown_timeout {
+            let key = origin("limits.shutdown_timeout");
+            self.set("shutdown-timeout", &seconds.to_string(), &key)?;
+        }
+
+        Ok(())
+    }
+
+    fn set(&mut self, name: &str, value: &str, origin: &str) -> Result<(), ConfigError> {
+        let invalid = |reason: String| {
+            ConfigError::Invalid(format!("Invalid value for {}: {}", origin, reason))
+        };
+
+        match name {
+            "addr" => {
+                if value.trim().is_empty() {
+                    return Err(invalid(String::from("address is empty")));
+                }
+                self.config.addr = String::from(value.trim());
+            }
+            "websocket" => {
+                if value.trim().is_empty() {
+                    return Err(invalid(String::from("address is empty")));
+                }
+                self.config.websocket = Some(String::from(value.trim()));
+            }
+            "workers" => self.config.max_workers = positive(value).map_err(invalid)?,
+            "storage" => {
+                self.config.storage =
+                    Backend::parse(value).map_err(|err| invalid(format!("{} '{}'", err, value)))?
+            }
+            "tls-cert" => self.tls_cert = Some(path(value).map_err(invalid)?),
+            "tls-key" => self.tls_key = Some(path(value).map_err(invalid)?),
+            "max-frame-size" => {
+                self.config.limits.max_frame_size = positive(value).map_err(invalid)?
+            }
+            "max-page-size" => {
+                self.config.limits.max_page_size = positive(value).map_err(invalid)?
+            }
+            "max-search-results" => {
+                self.config.limits.max_search_results = positive(value).map_err(invalid)?
+            }
+            "shutdown-timeout" => {
+                self.config.limits.shutdown_timeout = positive(value).map_err(invalid)?
+            }
+            "log-level" => self.config.log_level = Level::parse(value).map_err(invalid)?,
+            _ => return Err(ConfigError::Invalid(format!("Unknown option {}", origin))),
+        }
+
+        Ok(())
+    }
+
+    fn build(self) -> Result<Config, ConfigError> {
+        let mut config = self.config;
+
+        config.tls = match (self.tls_cert, self.tls_key) {
+            (Some(cert), Some(key)) => Some(TlsConfig { cert, key }),
+            (None, None) => None,
+            (Some(_), None) => {
+                return Err(ConfigError::Invalid(String::from(
+                    "TLS certificate given without a private key (set --tls-key)",
+                )))
+            }
+            (None, Some(_)) => {
+                return Err(ConfigError::Invalid(String::from(
+                    "TLS private key given without a certificate (set --tls-cert)",
+                )))
+            }
+        };
+
+        Ok(config)
+    }
+}
+
+fn parse_flags<A>(args: A) -> Result<Vec<(String, String)>, ConfigError>
+where
+    A: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter();
+    let mut flags = Vec::new();
+
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (String::from(flag), Some(String::from(value)))
+            }
+            _ => (arg.clone(), None),
+        };
+
+        let name = match flag.as_str() {
+            "-h" | "--help" => return Err(ConfigError::Help),
+            "-c" => "config",
+            "-a" => "addr",
+            "-w" => "workers",
+            "-s" => "storage",
+            "-l" => "log-level",
+            long => match long.strip_prefix("--") {
+                Some(name) if !name.is_empty() => name,
+                _ => {
+                    return Err(ConfigError::Invalid(format!(
+                        "Unexpected argument '{}'",
+                        arg
+                    )))
+                }
+            },
+        };
+
+        let value = match inline.or_else(|| args.next()) {
+            Some(value) => value,
+            None => return Err(ConfigError::Invalid(format!("Missing value for {}", flag))),
+        };
+
+        flags.push((String::from(name), value));
+    }
+
+    Ok(flags)
+}
+
+fn positive<T>(value: &str) -> Result<T, String>
+where
+    T: std::str::FromStr + Default + PartialEq,
+{
+    match value.trim().parse::<T>() {
+        Ok(parsed) if parsed != T::default() => Ok(parsed),
+        _ => Err(format!("expected a positive integer, got '{}'", value)),
+    }
+}
+
+fn path(value: &str) -> Result<PathBuf, String> {
+    match value.trim() {
+        "" => Err(String::from("path is empty")),
+        value => Ok(PathBuf::from(value)),
+    }
+}
@@ -0,0 +1,163 @@
+use futures_util::{
+    stream::{SplitSink, SplitStream},
+    SinkExt, StreamExt,
+};
+use libs::{
+    packet::{Codec, DataPacket, PacketError, PacketErrorKind},
+    packet_manager,
+};
+use tokio::io::{self, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio_rustls::TlsAcceptor;
+use tokio_tungstenite::{
+    tungstenite::{error::CapacityError, protocol::WebSocketConfig, Error, Message},
+    WebSocketStream,
+};
+
+use crate::listener::{Connection, Protocol};
+
+type WebSocket = WebSocketStream<Connection>;
+
+/// Reads whole packets from a connection, whatever framing its listener uses.
+/// WebSocket clients send JSON as text frames and MessagePack as binary ones.
+pub(crate) enum PacketReader {
+    Framed(ReadHalf<Connection>, u32),
+    WebSocket(SplitStream<WebSocket>),
+}
+
+pub(crate) enum PacketWriter {
+    Framed(WriteHalf<Connection>),
+    WebSocket(SplitSink<WebSocket, Message>),
+}
+
+/// Completes the TLS and protocol handshakes, if any, and splits the
+/// connection so one task can read while another writes.
+pub(crate) async fn open(
+    stream: Connection,
+    protocol: Protocol,
+    tls: Option<TlsAcceptor>,
+    max_frame_size: u32,
+) -> Result<(PacketReader, PacketWriter), PacketError> {
+    let stream: Connection = match tls {
+        Some(tls) => match tls.accept(stream).await {
+            Ok(stream) => Box::new(stream),
+            Err(err) => {
+                return Err(PacketError {
+                    kind: PacketErrorKind::Tls,
+                    message: err.to_string(),
+                })
+            }
+        },
+        None => stream,
+    };
+
+    match protocol {
+        Protocol::Framed => {
+            let (reader, writer) = io::split(stream);
+
+            Ok((
+                PacketReader::Framed(reader, max_frame_size),
+                PacketWriter::Framed(writer),
+            ))
+        }
+        Protocol::WebSocket => {
+            let config = WebSocketConfig {
+                max_message_size: Some(max_frame_size as usize),
+                max_frame_size: Some(max_frame_size as usize),
+                ..WebSocketConfig::default()
+            };
+
+            let socket =
+                match tokio_tungstenite::accept_async_with_config(stream, Some(config)).await {
+                    Ok(socket) => socket,
+                    Err(err) => return Err(websocket_error(err)),
+                };
+
+            let (writer, reader) = socket.split();
+
+            Ok((
+                PacketReader::WebSocket(reader),
+                PacketWriter::WebSocket(writer),
+            ))
+        }
+    }
+}
+
+impl PacketReader {
+    pub(crate) async fn recv(&mut self) -> Result<DataPacket, PacketError> {
+        let reader = match self {
+            PacketReader::Framed(reader, max_frame_size) => {
+                return packet_manager::recv_packet_async(reader, *max_frame_size).await
+            }
+            PacketReader::WebSocket(reader) => reader,
+        };
+
+        loop {
+            let message = match reader.next().await {
+                Some(Ok(message)) => message,
+                Some(Err(err)) => return Err(websocket_error(err)),
+                None => return Err(websocket_error(Error::ConnectionClosed)),
+            };
+
+            match message {
+                Message::Text(text) => return Codec::Json.decode(text.as_bytes()),
+                Message::Binary(buf) => return Codec::MessagePack.decode(&buf),
+                Message::Close(_) => return Err(websocket_error(Error::ConnectionClosed)),
+                Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => continue,
+            }
+        }
+    }
+}
+
+impl PacketWriter {
+    pub(crate) async fn send(&mut self, packet: DataPacket) -> Result<(), PacketError> {
+        match self {
+            PacketWriter::Framed(writer) => packet_manager::send_packet_async(writer, packet).await,
+            PacketWriter::WebSocket(writer) => {
+                let codec = packet.get_codec();
+                let buf = codec.encode(&packet)?;
+
+                let message = match codec {
+                    Codec::Json => match String::from_utf8(buf) {
+                        Ok(text) => Message::Text(text),
+                        Err(err) => {
+                            return Err(PacketError {
+                                kind: PacketErrorKind::Serialize,
+                                message: err.to_string(),
+                            })
+                        }
+                    },
+                    Codec::MessagePack => Message::Binary(buf),
+                };
+
+                match writer.send(message).await {
+                    Ok(()) => Ok(()),
+                    Err(err) => Err(websocket_error(err)),
+                }
+            }
+        }
+    }
+
+    pub(crate) async fn close(&mut self) {
+        match self {
+            PacketWriter::Framed(writer) => {
+                let _ = writer.shutdown().await;
+            }
+            PacketWriter::WebSocket(writer) => {
+                let _ = writer.close().await;
+            }
+        }
+    }
+}
+
+fn websocket_error(err: Error) -> PacketError {
+    let kind = match err {
+        Error::Capacity(CapacityError::MessageTooLong { .. }) => PacketErrorKind::FrameTooLarge,
+        Error::Protocol(_) | Error::Utf8 => PacketErrorKind::Malformed,
+        _ => PacketErrorKind::Io,
+    };
+
+    PacketError {
+        kind,
+        message: err.to_string(),
+    }
+}
@@ -0,0 +1,491 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use libs::{BaseModels, PacketModels};
+use uuid::Uuid;
+
+use super::{can_remove, validate_username, GroupError, Storage, StorageError};
+
+pub struct MemoryStorage {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    users: BTreeMap<String, BaseModels::User>,
+    srp: HashMap<String, (String, String)>,
+    sessions: HashMap<String, (String, Instant)>,
+    user_sessions: HashMap<String, HashSet<String>>,
+    server_key: Option<String>,
+    bundles: HashMap<String, PacketModels::PreKeyBundle>,
+    prekeys: HashMap<String, VecDeque<PacketModels::PreKey>>,
+    e2e: HashMap<String, Vec<PacketModels::E2E>>,
+    sender_keys: HashMap<String, Vec<PacketModels::SenderKey>>,
+    groups: HashMap<Uuid, GroupRecord>,
+    memberships: HashMap<String, HashSet<Uuid>>,
+    messages: HashMap<Uuid, Vec<BaseModels::Message>>,
+    read: HashMap<(String, Uuid), u64>,
+}
+
+struct GroupRecord {
+    name: String,
+    owner: String,
+    admins: BTreeSet<String>,
+    members: BTreeSet<String>,
+}
+
+impl GroupRecord {
+    fn to_group(&self, id: Uuid) -> BaseModels::Group {
+        BaseModels::Group::full(
+            id,
+            self.name.clone(),
+            self.owner.clone(),
+            self.admins.iter().cloned().collect(),
+            self.members.iter().cloned().collect(),
+        )
+    }
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    fn state(&self) -> Result<MutexGuard<'_, State>, StorageError> {
+        match self.state.lock() {
+            Ok(state) => Ok(state),
+            Err(_) => Err(StorageError::new("Storage Lock Poisoned")),
+        }
+    }
+}
+
+impl Default for MemoryStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl State {
+    fn revoke_sessions(&mut self, username: &str) {
+        if let Some(ids) = self.user_sessions.remove(username) {
+            for id in ids {
+                self.sessions.remove(&id);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn create_srp_user(
+        &self,
+        registration: PacketModels::SrpRegistration,
+    ) -> Result<(), StorageError> {
+        let username = registration.get_user().get_username();
+        validate_username(&username)?;
+
+        let mut state = self.state()?;
+
+        if state.users.contains_key(&username) {
+            return Err(StorageError::new("User Already Exists"));
+        }
+
+        state.srp.insert(
+            username.clone(),
+            (registration.get_salt(), registration.get_verifier()),
+        );
+        state.users.insert(
+            username,
+            registration.into_user().with_password(String::new()),
+        );
+
+        Ok(())
+    }
+
+    async fn set_srp(
+        &self,
+        username: String,
+        salt: String,
+        verifier: String,
+    ) -> Result<(), StorageError> {
+        let mut state = self.state()?;
+
+        state.srp.insert(username.clone(), (salt, verifier));
+
+        if let Some(user) = state.users.remove(&username) {
+            state
+                .users
+                .insert(username.clone(), user.with_password(String::new()));
+        }
+
+        state.revoke_sessions(&username);
+
+        Ok(())
+    }
+
+    async fn get_srp(&self, username: String) -> Result<Option<(String, String)>, StorageError> {
+        Ok(self.state()?.srp.get(&username).cloned())
+    }
+
+    async fn get_user(&self, key: String) -> Result<BaseModels::User, StorageError> {
+        match self.state()?.users.get(&key) {
+            Some(user) => Ok(user.clone()),
+            None => Err(StorageError::new("User Not Found")),
+        }
+    }
+
+    async fn index_user(&self, _username: String) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    async fn search_users(
+        &self,
+        prefix: String,
+        limit: isize,
+    ) -> Result<Vec<String>, StorageError> {
+        let state = self.state()?;
+
+        Ok(state
+            .users
+            .range(prefix.clone()..)
+            .map(|(username, _)| username)
+            .take_while(|username| username.starts_with(&prefix))
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn create_session(
+        &self,
+        id: String,
+        username: String,
+        ttl: i64,
+    ) -> Result<(), StorageError> {
+        let mut state = self.state()?;
+
+        let expires = Instant::now() + Duration::from_secs(ttl.max(1) as u64);
+
+        state
+            .sessions
+            .insert(id.clone(), (username.clone(), expires));
+        state.user_sessions.entry(username).or_default().insert(id);
+
+        Ok(())
+    }
+
+    async fn is_session_active(&self, id: String, username: String) -> Result<bool, StorageError> {
+        let state = self.state()?;
+
+        Ok(match state.sessions.get(&id) {
+            Some((owner, expires)) => *owner == username && *expires > Instant::now(),
+            None => false,
+        })
+    }
+
+    async fn revoke_session(&self, id: String, username: String) -> Result<(), StorageError> {
+        let mut state = self.state()?;
+
+        state.sessions.remove(&id);
+
+        if let Some(ids) = state.user_sessions.get_mut(&username) {
+            ids.remove(&id);
+        }
+
+        Ok(())
+    }
+
+    async fn revoke_sessions(&self, username: String) -> Result<(), StorageError> {
+        self.state()?.revoke_sessions(&username);
+
+        Ok(())
+    }
+
+    async fn get_server_key(&self) -> Result<Option<String>, StorageError> {
+        Ok(self.state()?.server_key.clone())
+    }
+
+    async fn set_server_key(&self, key: String) -> Result<(), StorageError> {
+        self.state()?.server_key = Some(key);
+
+        Ok(())
+    }
+
+    async fn set_key_bundle(
+        &self,
+        username: String,
+        bundle: PacketModels::KeyBundle,
+    ) -> Result<(), StorageError> {
+        let mut state = self.state()?;
+
+        let (bundle, prekeys) = bundle.split();
+
+        let rotated = match state.bundles.get(&username) {
+            Some(current) => current.get_identity_key() != bundle.get_identity_key(),
+            None => true,
+        };
+
+        if rotated {
+            state.prekeys.remove(&username);
+        }
+
+        state
+            .prekeys
+            .entry(username.clone())
+            .or_default()
+            .extend(prekeys);
+        state.bundles.insert(username, bundle);
+
+        Ok(())
+    }
+
+    async fn take_key_bundle(
+        &self,
+        username: String,
+    ) -> Result<PacketModels::PreKeyBundle, StorageError> {
+        let mut state = self.state()?;
+
+        let bundle = match state.bundles.get(&username) {
+            Some(bundle) => bundle.clone(),
+            None => return Err(StorageError::new("Key Bundle Not Found")),
+        };
+
+        let prekey = match state.prekeys.get_mut(&username) {
+            Some(prekeys) => prekeys.pop_front(),
+            None => None,
+        };
+
+        Ok(bundle.with_one_time_prekey(prekey))
+    }
+
+    async fn push_e2e(
+        &self,
+        username: String,
+        init: PacketModels::E2E,
+    ) -> Result<(), StorageError> {
+        self.state()?.e2e.entry(username).or_default().push(init);
+
+        Ok(())
+    }
+
+    async fn take_e2e(&self, username: String) -> Result<Vec<PacketModels::E2E>, StorageError> {
+        Ok(self.state()?.e2e.remove(&username).unwrap_or_default())
+    }
+
+    async fn push_sender_key(
+        &self,
+        username: String,
+        sender_key: PacketModels::SenderKey,
+    ) -> Result<(), StorageError> {
+        self.state()?
+            .sender_keys
+            .entry(username)
+            .or_default()
+            .push(sender_key);
+
+        Ok(())
+    }
+
+    async fn take_sender_keys(
+        &self,
+        username: String,
+    ) -> Result<Vec<PacketModels::SenderKey>, StorageError> {
+        Ok(self
+            .state()?
+            .sender_keys
+            .remove(&username)
+            .unwrap_or_default())
+    }
+
+    async fn create_group(
+        &self,
+        owner: String,
+        name: String,
+    ) -> Result<BaseModels::Group, StorageError> {
+        let mut state = self.state()?;
+
+        let id = Uuid::new_v4();
+
+        let record = GroupRecord {
+            name,
+            owner: owner.clone(),
+            admins: BTreeSet::from([owner.clone()]),
+            members: BTreeSet::from([owner.clone()]),
+        };
+
+        let group = record.to_group(id);
+
+        state.groups.insert(id, record);
+        state.memberships.entry(owner).or_default().insert(id);
+
+        Ok(group)
+    }
+
+    async fn get_group(&self, id: Uuid) -> Result<BaseModels::Group, GroupError> {
+        match self.state()?.groups.get(&id) {
+            Some(record) => Ok(record.to_group(id)),
+            None => Err(GroupError::NotFound),
+        }
+    }
+
+    async fn add_member(
+        &self,
+        id: Uuid,
+        requester: String,
+        username: String,
+    ) -> Result<BaseModels::Group, GroupError> {
+        let mut state = self.state()?;
+
+        let known = state.users.contains_key(&username);
+
+        let record = match state.groups.get_mut(&id) {
+            Some(record) => record,
+            None => return Err(GroupError::NotFound),
+        };
+
+        if !record.to_group(id).is_admin(&requester) {
+            return Err(GroupError::PermissionDenied);
+        }
+
+        if !known {
+            return Err(GroupError::UnknownUser(username));
+        }
+
+        if !record.members.insert(username.clone()) {
+            return Err(GroupError::AlreadyMember(username));
+        }
+
+        let group = record.to_group(id);
+
+        state.memberships.entry(username).or_default().insert(id);
+
+        Ok(group)
+    }
+
+    async fn remove_member(
+        &self,
+        id: Uuid,
+        requester: String,
+        username: String,
+    ) -> Result<BaseModels::Group, GroupError> {
+        let mut state = self.state()?;
+
+        let record = match state.groups.get_mut(&id) {
+            Some(record) => record,
+            None => return Err(GroupError::NotFound),
+        };
+
+        if !can_remove(&record.to_group(id), &requester, &username) {
+            return Err(GroupError::PermissionDenied);
+        }
+
+        if !record.members.remove(&username) {
+            return Err(GroupError::NotMember(username));
+        }
+        record.admins.remove(&username);
+
+        let group = record.to_group(id);
+
+        if let Some(ids) = state.memberships.get_mut(&username) {
+            ids.remove(&id);
+        }
+        state.read.remove(&(username, id));
+
+        Ok(group)
+    }
+
+    async fn is_group_member(&self, id: Uuid, username: String) -> Result<bool, StorageError> {
+        Ok(match self.state()?.groups.get(&id) {
+            Some(record) => record.members.contains(&username),
+            None => false,
+        })
+    }
+
+    async fn get_chats(&self, username: String) -> Result<PacketModels::Chats, GroupError> {
+        let state = self.state()?;
+
+        let ids = match state.memberships.get(&username) {
+            Some(ids) => ids.iter().copied().collect(),
+            None => Vec::new(),
+        };
+
+        let mut groups = Vec::with_capacity(ids.len());
+        let mut unread = HashMap::with_capacity(ids.len());
+
+        for id in ids {
+            let record = match state.groups.get(&id) {
+                Some(record) => record,
+                None => continue,
+            };
+
+            let total = state.messages.get(&id).map_or(0, |messages| messages.len()) as u64;
+            let read = state
+                .read
+                .get(&(username.clone(), id))
+                .copied()
+                .unwrap_or(0);
+
+            unread.insert(id, total.saturating_sub(read));
+            groups.push(record.to_group(id));
+        }
+
+        groups.sort_by_key(|group| group.get_name());
+
+        Ok(PacketModels::Chats::new(groups, unread))
+    }
+
+    async fn mark_read(&self, id: Uuid, username: String) -> Result<(), StorageError> {
+        let mut state = self.state()?;
+
+        let total = state.messages.get(&id).map_or(0, |messages| messages.len()) as u64;
+
+        state.read.insert((username, id), total);
+
+        Ok(())
+    }
+
+    async fn create_message(&self, message: &BaseModels::Message) -> Result<(), StorageError> {
+        let group = message.get_member().get_group().get_id();
+
+        self.state()?
+            .messages
+            .entry(group)
+            .or_default()
+            .push(message.clone());
+
+        Ok(())
+    }
+
+    async fn get_messages(
+        &self,
+        group: BaseModels::Group,
+        cursor: Option<u64>,
+        limit: u32,
+    ) -> Result<PacketModels::Messages, StorageError> {
+        let state = self.state()?;
+
+        let stored = match state.messages.get(&group.get_id()) {
+            Some(messages) => messages.as_slice(),
+            None => &[],
+        };
+
+        let len = stored.len() as u64;
+
+        let end = cursor.unwrap_or(len).min(len);
+        let start = end.saturating_sub(limit as u64);
+
+        let messages = stored[start as usize..end as usize].to_vec();
+
+        let next_cursor = match start > 0 {
+            true => Some(start),
+            false => None,
+        };
+
+        Ok(PacketModels::Messages::new(group, messages, next_cursor))
+    }
+}
@@ -0,0 +1,255 @@
+use std::{fmt, path::PathBuf, sync::Arc};
+
+use async_trait::async_trait;
+use libs::{BaseModels, PacketModels};
+use uuid::Uuid;
+
+mod memory;
+pub use memory::MemoryStorage;
+
+mod redis;
+pub use self::redis::RedisStorage;
+
+mod sqlite;
+pub use sqlite::SqliteStorage;
+
+pub const DEFAULT_REDIS_URL: &str = "redis://127.0.0.1/";
+
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn create_srp_user(
+        &self,
+        registration: PacketModels::SrpRegistration,
+    ) -> Result<(), StorageError>;
+
+    async fn set_srp(
+        &self,
+        username: String,
+        salt: String,
+        verifier: String,
+    ) -> Result<(), StorageError>;
+
+    async fn get_srp(&self, username: String) -> Result<Option<(String, String)>, StorageError>;
+
+    async fn get_user(&self, key: String) -> Result<BaseModels::User, StorageError>;
+
+    async fn index_user(&self, username: String) -> Result<(), StorageError>;
+
+    async fn search_users(&self, prefix: String, limit: isize)
+        -> Result<Vec<String>, StorageError>;
+
+    async fn create_session(
+        &self,
+        id: String,
+        username: String,
+        ttl: i64,
+    ) -> Result<(), StorageError>;
+
+    async fn is_session_active(&self, id: String, username: String) -> Result<bool, StorageError>;
+
+    async fn revoke_session(&self, id: String, username: String) -> Result<(), StorageError>;
+
+    async fn revoke_sessions(&self, username: String) -> Result<(), StorageError>;
+
+    async fn get_server_key(&self) -> Result<Option<String>, StorageError>;
+
+    async fn set_server_key(&self, key: String) -> Result<(), StorageError>;
+
+    async fn set_key_bundle(
+        &self,
+        username: String,
+        bundle: PacketModels::KeyBundle,
+    ) -> Result<(), StorageError>;
+
+    async fn take_key_bundle(
+        &self,
+        username: String,
+    ) -> Result<PacketModels::PreKeyBundle, StorageError>;
+
+    async fn push_e2e(&self, username: String, init: PacketModels::E2E)
+        -> Result<(), StorageError>;
+
+    async fn take_e2e(&self, username: String) -> Result<Vec<PacketModels::E2E>, StorageError>;
+
+    async fn push_sender_key(
+        &self,
+        username: String,
+        sender_key: PacketModels::SenderKey,
+    ) -> Result<(), StorageError>;
+
+    async fn take_sender_keys(
+        &self,
+        username: String,
+    ) -> Result<Vec<PacketModels::SenderKey>, StorageError>;
+
+    async fn create_group(
+        &self,
+        owner: String,
+        name: String,
+    ) -> Result<BaseModels::Group, StorageError>;
+
+    async fn get_group(&self, id: Uuid) -> Result<BaseModels::Group, GroupError>;
+
+    async fn add_member(
+        &self,
+        id: Uuid,
+        requester: String,
+        username: String,
+    ) -> Result<BaseModels::Group, GroupError>;
+
+    async fn remove_member(
+        &self,
+        id: Uuid,
+        requester: String,
+        username: String,
+    ) -> Result<BaseModels::Group, GroupError>;
+
+    async fn is_group_member(&self, id: Uuid, username: String) -> Result<bool, StorageError>;
+
+    async fn get_chats(&self, username: String) -> Result<PacketModels::Chats, GroupError>;
+
+    async fn mark_read(&self, id: Uuid, username: String) -> Result<(), StorageError>;
+
+    async fn create_message(&self, message: &BaseModels::Message) -> Result<(), StorageError>;
+
+    async fn get_messages(
+        &self,
+        group: BaseModels::Group,
+        cursor: Option<u64>,
+        limit: u32,
+    ) -> Result<PacketModels::Messages, StorageError>;
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Redis(String),
+    Sqlite(PathBuf),
+    Memory,
+}
+
+impl Backend {
+    pub fn parse(value: &str) -> Result<Self, &'static str> {
+        match value {
+            "memory" => Ok(Backend::Memory),
+            "redis" => Ok(Backend::Redis(String::from(DEFAULT_REDIS_URL))),
+            url if url.starts_with("redis://") || url.starts_with("rediss://") => {
+                Ok(Backend::Redis(String::from(url)))
+            }
+            path if path.starts_with("sqlite://") => match &path["sqlite://".len()..] {
+                "" => Err("SQLite path not provided"),
+                path => Ok(Backend::Sqlite(PathBuf::from(path))),
+            },
+            _ => Err("Unknown storage backend"),
+        }
+    }
+
+    pub fn open(&self) -> Result<Arc<dyn Storage>, StorageError> {
+        match self {
+            Backend::Redis(url) => Ok(Arc::new(RedisStorage::new(url)?)),
+            Backend::Sqlite(path) => Ok(Arc::new(SqliteStorage::new(path)?)),
+            Backend::Memory => Ok(Arc::new(MemoryStorage::new())),
+        }
+    }
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Backend::Redis(String::from(DEFAULT_REDIS_URL))
+    }
+}
+
+#[derive(Debug)]
+pub struct StorageError {
+    pub message: String,
+}
+
+impl StorageError {
+    pub fn new(message: &str) -> Self {
+        Self {
+            message: String::from(message),
+        }
+    }
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug)]
+pub enum GroupError {
+    NotFound,
+    PermissionDenied,
+    UnknownUser(String),
+    AlreadyMember(String),
+    NotMember(String),
+    Database(StorageError),
+}
+
+impl fmt::Display for GroupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GroupError::NotFound => write!(f, "Group Not Found"),
+            GroupError::PermissionDenied => write!(f, "Permission Denied"),
+            GroupError::UnknownUser(username) => write!(f, "User Not Found: {}", username),
+            GroupError::AlreadyMember(username) => {
+                write!(f, "User Already Member: {}", username)
+            }
+            GroupError::NotMember(username) => write!(f, "User Not Member: {}", username),
+            GroupError::Database(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<StorageError> for GroupError {
+    fn from(err: StorageError) -> Self {
+        GroupError::Database(err)
+    }
+}
+
+/// Admins may remove anyone but the owner and members may leave on their own.
+fn can_remove(group: &BaseModels::Group, requester: &str, username: &str) -> bool {
+    if username == group.get_owner() {
+        return false;
+    }
+
+    requester == username || group.is_admin(requester)
+}
+
+const MAX_USERNAME_LEN: usize = 32;
+
+/// Usernames end up in storage keys and paths, so they are limited to
+/// ASCII letters, digits, `_`, `-` and `.`, and may not start with a dot.
+fn validate_username(username: &str) -> Result<(), StorageError> {
+    let valid = !username.is_empty()
+        && username.len() <= MAX_USERNAME_LEN
+        && !username.starts_with('.')
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+
+    if !valid {
+        return Err(StorageError::new("Invalid Username"));
+    }
+
+    Ok(())
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<String, StorageError> {
+    match serde_json::to_string(value) {
+        Ok(value) => Ok(value),
+        Err(err) => Err(StorageError {
+            message: format!("Serialization Error: {}", err),
+        }),
+    }
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(value: &str) -> Result<T, StorageError> {
+    match serde_json::from_str(value) {
+        Ok(value) => Ok(value),
+        Err(err) => Err(StorageError {
+            message: format!("Deserialization Error: {}", err),
+        }),
+    }
+}
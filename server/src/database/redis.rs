@@ -0,0 +1,607 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use libs::{BaseModels, PacketModels};
+use redis::{aio::ConnectionManager, AsyncCommands};
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+use super::{can_remove, from_json, to_json, validate_username, GroupError, Storage, StorageError};
+
+pub struct RedisStorage {
+    db: redis::Client,
+    conn: OnceCell<ConnectionManager>,
+}
+
+impl RedisStorage {
+    pub fn new(url: &str) -> Result<Self, StorageError> {
+        let db = redis::Client::open(url)?;
+
+        Ok(Self {
+            db,
+            conn: OnceCell::new(),
+        })
+    }
+
+    /// Every call shares one multiplexed connection, opened on first use and
+    /// reopened by the manager if Redis goes away.
+    async fn connection(&self) -> Result<ConnectionManager, StorageError> {
+        let conn = self
+            .conn
+            .get_or_try_init(|| ConnectionManager::new(self.db.clone()))
+            .await?;
+
+        Ok(conn.clone())
+    }
+}
+
+/// User hashes live under their own prefix so a username can never collide
+/// with another key, like `group:{id}` or `server:identity`.
+fn user_key(username: &str) -> String {
+    format!("user:{}", username)
+}
+
+/// Registered users are exactly the members of the `users` index.
+async fn is_user(conn: &mut ConnectionManager, username: &str) -> Result<bool, StorageError> {
+    let score: Option<f64> = conn.zscore("users", username).await?;
+
+    Ok(score.is_some())
+}
+
+#[async_trait]
+impl Storage for RedisStorage {
+    async fn create_srp_user(
+        &self,
+        registration: PacketModels::SrpRegistration,
+    ) -> Result<(), StorageError> {
+        let username = registration.get_user().get_username();
+        validate_username(&username)?;
+
+        let mut conn = self.connection().await?;
+
+        if is_user(&mut conn, &username).await? {
+            return Err(StorageError::new("User Already Exists"));
+        }
+
+        let srp = to_json(&(registration.get_salt(), registration.get_verifier()))?;
+        let user = registration
+            .into_user()
+            .with_password(String::new())
+            .get_hash();
+
+        redis::pipe()
+            .atomic()
+            .hset_multiple(user_key(&user.0), &user.1)
+            .ignore()
+            .set(format!("srp:{}", username), srp)
+            .ignore()
+            .zadd("users", &username, 0)
+            .ignore()
+            .query_async::<_, ()>(&mut conn)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn set_srp(
+        &self,
+        username: String,
+        salt: String,
+        verifier: String,
+    ) -> Result<(), StorageError> {
+        let mut conn = self.connection().await?;
+
+        let srp = to_json(&(salt, verifier))?;
+
+        redis::pipe()
+            .atomic()
+            .set(format!("srp:{}", username), srp)
+            .ignore()
+            .hset(user_key(&username), 2, String::new())
+            .ignore()
+            .query_async::<_, ()>(&mut conn)
+            .await?;
+
+        self.revoke_sessions(username).await
+    }
+
+    async fn create_session(
+        &self,
+        id: String,
+        username: String,
+        ttl: i64,
+    ) -> Result<(), StorageError> {
+        let mut conn = self.connection().await?;
+
+        let ttl = ttl.max(1) as usize;
+
+        redis::pipe()
+            .atomic()
+            .set_ex(format!("session:{}", id), &username, ttl)
+            .ignore()
+            .sadd(format!("sessions:{}", username), id)
+            .ignore()
+            .query_async::<_, ()>(&mut conn)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn is_session_active(&self, id: String, username: String) -> Result<bool, StorageError> {
+        let mut conn = self.connection().await?;
+
+        let owner: Option<String> = conn.get(format!("session:{}", id)).await?;
+
+        Ok(owner.as_deref() == Some(username.as_str()))
+    }
+
+    async fn revoke_session(&self, id: String, username: String) -> Result<(), StorageError> {
+        let mut conn = self.connection().await?;
+
+        redis::pipe()
+            .atomic()
+            .del(format!("session:{}", id))
+            .ignore()
+            .srem(format!("sessions:{}", username), id)
+            .ignore()
+            .query_async::<_, ()>(&mut conn)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn revoke_sessions(&self, username: String) -> Result<(), StorageError> {
+        let mut conn = self.connection().await?;
+
+        let key = format!("sessions:{}", username);
+        let ids: Vec<String> = conn.smembers(&key).await?;
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+
+        for id in ids {
+            pipe.del(format!("session:{}", id)).ignore();
+        }
+
+        pipe.del(key)
+            .ignore()
+            .query_async::<_, ()>(&mut conn)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_srp(&self, username: String) -> Result<Option<(String, String)>, StorageError> {
+        let mut conn = self.connection().await?;
+
+        let srp: Option<String> = conn.get(format!("srp:{}", username)).await?;
+
+        match srp {
+            Some(srp) => Ok(Some(from_json(&srp)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_user(&self, key: String) -> Result<BaseModels::User, StorageError> {
+        let mut conn = self.connection().await?;
+        let hash: HashMap<u8, String> = conn.hgetall(user_key(&key)).await?;
+
+        if hash.is_empty() {
+            return Err(StorageError::new("User Not Found"));
+        }
+
+        let user = BaseModels::User::from_hash(hash);
+
+        Ok(user)
+    }
+
+    async fn index_user(&self, username: String) -> Result<(), StorageError> {
+        let mut conn = self.connection().await?;
+
+        conn.zadd::<_, _, _, ()>("users", username, 0).await?;
+
+        Ok(())
+    }
+
+    async fn search_users(
+        &self,
+        prefix: String,
+        limit: isize,
+    ) -> Result<Vec<String>, StorageError> {
+        let mut conn = self.connection().await?;
+
+        let mut min = vec![b'['];
+        min.extend_from_slice(prefix.as_bytes());
+
+        let mut max = min.clone();
+        max.push(0xff);
+
+        Ok(conn.zrangebylex_limit("users", min, max, 0, limit).await?)
+    }
+
+    async fn get_server_key(&self) -> Result<Option<String>, StorageError> {
+        let mut conn = self.connection().await?;
+
+        Ok(conn.get("server:identity").await?)
+    }
+
+    async fn set_server_key(&self, key: String) -> Result<(), StorageError> {
+        let mut conn = self.connection().await?;
+
+        conn.set::<_, _, ()>("server:identity", key).await?;
+
+        Ok(())
+    }
+
+    async fn set_key_bundle(
+        &self,
+        username: String,
+        bundle: PacketModels::KeyBundle,
+    ) -> Result<(), StorageError> {
+        let mut conn = self.connection().await?;
+
+        let (bundle, prekeys) = bundle.split();
+
+        let current: Option<String> = conn.get(format!("keys:{}:bundle", username)).await?;
+
+        let rotated = match current {
+            Some(current) => {
+                let current: PacketModels::PreKeyBundle = from_json(&current)?;
+                current.get_identity_key() != bundle.get_identity_key()
+            }
+            None => true,
+        };
+
+        let bundle = to_json(&bundle)?;
+
+        let mut encoded = Vec::with_capacity(prekeys.len());
+        for prekey in &prekeys {
+            encoded.push(to_json(prekey)?);
+        }
+
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .set(format!("keys:{}:bundle", username), bundle)
+            .ignore();
+
+        if rotated {
+            pipe.del(format!("keys:{}:prekeys", username)).ignore();
+        }
+
+        if !encoded.is_empty() {
+            pipe.rpush(format!("keys:{}:prekeys", username), encoded)
+                .ignore();
+        }
+
+        pipe.query_async::<_, ()>(&mut conn).await?;
+
+        Ok(())
+    }
+
+    async fn take_key_bundle(
+        &self,
+        username: String,
+    ) -> Result<PacketModels::PreKeyBundle, StorageError> {
+        let mut conn = self.connection().await?;
+
+        let bundle: Option<String> = conn.get(format!("keys:{}:bundle", username)).await?;
+
+        let bundle: PacketModels::PreKeyBundle = match bundle {
+            Some(bundle) => from_json(&bundle)?,
+            None => return Err(StorageError::new("Key Bundle Not Found")),
+        };
+
+        let prekey: Option<String> = conn
+            .lpop(format!("keys:{}:prekeys", username), None)
+            .await?;
+
+        let prekey = match prekey {
+            Some(prekey) => Some(from_json(&prekey)?),
+            None => None,
+        };
+
+        Ok(bundle.with_one_time_prekey(prekey))
+    }
+
+    async fn push_e2e(
+        &self,
+        username: String,
+        init: PacketModels::E2E,
+    ) -> Result<(), StorageError> {
+        let mut conn = self.connection().await?;
+
+        conn.rpush::<_, _, ()>(format!("e2e:{}", username), to_json(&init)?)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn take_e2e(&self, username: String) -> Result<Vec<PacketModels::E2E>, StorageError> {
+        let mut conn = self.connection().await?;
+
+        let key = format!("e2e:{}", username);
+
+        let (encoded,): (Vec<String>,) = redis::pipe()
+            .atomic()
+            .lrange(&key, 0, -1)
+            .del(&key)
+            .ignore()
+            .query_async(&mut conn)
+            .await?;
+
+        let mut sessions = Vec::with_capacity(encoded.len());
+        for init in encoded {
+            sessions.push(from_json(&init)?);
+        }
+
+        Ok(sessions)
+    }
+
+    async fn create_group(
+        &self,
+        owner: String,
+        name: String,
+    ) -> Result<BaseModels::Group, StorageError> {
+        let mut conn = self.connection().await?;
+
+        let id = Uuid::new_v4();
+
+        redis::pipe()
+            .atomic()
+            .hset_multiple(format!("group:{}", id), &[(0, &name), (1, &owner)])
+            .ignore()
+            .sadd(format!("group:{}:admins", id), &owner)
+            .ignore()
+            .sadd(format!("group:{}:members", id), &owner)
+            .ignore()
+            .sadd(format!("groups:{}", owner), id.to_string())
+            .ignore()
+            .query_async::<_, ()>(&mut conn)
+            .await?;
+
+        Ok(BaseModels::Group::full(
+            id,
+            name,
+            owner.clone(),
+            vec![owner.clone()],
+            vec![owner],
+        ))
+    }
+
+    async fn get_group(&self, id: Uuid) -> Result<BaseModels::Group, GroupError> {
+        let mut conn = self.connection().await?;
+
+        let (hash, mut admins, mut members): (HashMap<u8, String>, Vec<String>, Vec<String>) =
+            redis::pipe()
+                .hgetall(format!("group:{}", id))
+                .smembers(format!("group:{}:admins", id))
+                .smembers(format!("group:{}:members", id))
+                .query_async(&mut conn)
+                .await?;
+
+        let (name, owner) = match (hash.get(&0), hash.get(&1)) {
+            (Some(name), Some(owner)) => (name.to_string(), owner.to_string()),
+            _ => return Err(GroupError::NotFound),
+        };
+
+        admins.sort();
+        members.sort();
+
+        Ok(BaseModels::Group::full(id, name, owner, admins, members))
+    }
+
+    async fn add_member(
+        &self,
+        id: Uuid,
+        requester: String,
+        username: String,
+    ) -> Result<BaseModels::Group, GroupError> {
+        let group = self.get_group(id).await?;
+
+        if !group.is_admin(&requester) {
+            return Err(GroupError::PermissionDenied);
+        }
+
+        let mut conn = self.connection().await?;
+
+        if !is_user(&mut conn, &username).await? {
+            return Err(GroupError::UnknownUser(username));
+        }
+
+        let (added,): (bool,) = redis::pipe()
+            .atomic()
+            .sadd(format!("group:{}:members", id), &username)
+            .sadd(format!("groups:{}", username), id.to_string())
+            .ignore()
+            .query_async(&mut conn)
+            .await?;
+
+        if !added {
+            return Err(GroupError::AlreadyMember(username));
+        }
+
+        self.get_group(id).await
+    }
+
+    async fn remove_member(
+        &self,
+        id: Uuid,
+        requester: String,
+        username: String,
+    ) -> Result<BaseModels::Group, GroupError> {
+        let group = self.get_group(id).await?;
+
+        if !can_remove(&group, &requester, &username) {
+            return Err(GroupError::PermissionDenied);
+        }
+
+        let mut conn = self.connection().await?;
+
+        let (removed,): (bool,) = redis::pipe()
+            .atomic()
+            .srem(format!("group:{}:members", id), &username)
+            .srem(format!("group:{}:admins", id), &username)
+            .ignore()
+            .srem(format!("groups:{}", username), id.to_string())
+            .ignore()
+            .del(format!("read:{}:{}", username, id))
+            .ignore()
+            .query_async(&mut conn)
+            .await?;
+
+        if !removed {
+            return Err(GroupError::NotMember(username));
+        }
+
+        self.get_group(id).await
+    }
+
+    async fn get_chats(&self, username: String) -> Result<PacketModels::Chats, GroupError> {
+        let mut conn = self.connection().await?;
+
+        let ids: Vec<String> = conn.smembers(format!("groups:{}", username)).await?;
+
+        let mut groups = Vec::with_capacity(ids.len());
+        let mut unread = HashMap::with_capacity(ids.len());
+
+        for id in ids {
+            let id = match Uuid::parse_str(&id) {
+                Ok(id) => id,
+                Err(_) => continue,
+            };
+
+            let group = match self.get_group(id).await {
+                Ok(group) => group,
+                Err(GroupError::NotFound) => continue,
+                Err(err) => return Err(err),
+            };
+
+            let (total, read): (u64, Option<u64>) = redis::pipe()
+                .llen(format!("messages:{}", id))
+                .get(format!("read:{}:{}", username, id))
+                .query_async(&mut conn)
+                .await?;
+
+            unread.insert(id, total.saturating_sub(read.unwrap_or(0)));
+            groups.push(group);
+        }
+
+        groups.sort_by_key(|group| group.get_name());
+
+        Ok(PacketModels::Chats::new(groups, unread))
+    }
+
+    async fn mark_read(&self, id: Uuid, username: String) -> Result<(), StorageError> {
+        let mut conn = self.connection().await?;
+
+        let total: u64 = conn.llen(format!("messages:{}", id)).await?;
+
+        conn.set::<_, _, ()>(format!("read:{}:{}", username, id), total)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn is_group_member(&self, id: Uuid, username: String) -> Result<bool, StorageError> {
+        let mut conn = self.connection().await?;
+
+        Ok(conn
+            .sismember(format!("group:{}:members", id), username)
+            .await?)
+    }
+
+    async fn create_message(&self, message: &BaseModels::Message) -> Result<(), StorageError> {
+        let mut conn = self.connection().await?;
+
+        let group = message.get_member().get_group().get_id();
+
+        conn.rpush::<_, _, ()>(format!("messages:{}", group), to_json(message)?)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_messages(
+        &self,
+        group: BaseModels::Group,
+        cursor: Option<u64>,
+        limit: u32,
+    ) -> Result<PacketModels::Messages, StorageError> {
+        let mut conn = self.connection().await?;
+
+        let key = format!("messages:{}", group.get_id());
+
+        let len: u64 = conn.llen(&key).await?;
+
+        let end = cursor.unwrap_or(len).min(len);
+        let start = end.saturating_sub(limit as u64);
+
+        let encoded: Vec<String> = match end > start {
+            true => conn.lrange(&key, start as isize, end as isize - 1).await?,
+            false => Vec::new(),
+        };
+
+        let mut messages = Vec::with_capacity(encoded.len());
+        for message in encoded {
+            messages.push(from_json(&message)?);
+        }
+
+        let next_cursor = match start > 0 {
+            true => Some(start),
+            false => None,
+        };
+
+        Ok(PacketModels::Messages::new(group, messages, next_cursor))
+    }
+
+    async fn push_sender_key(
+        &self,
+        username: String,
+        sender_key: PacketModels::SenderKey,
+    ) -> Result<(), StorageError> {
+        let mut conn = self.connection().await?;
+
+        conn.rpush::<_, _, ()>(format!("sender_keys:{}", username), to_json(&sender_key)?)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn take_sender_keys(
+        &self,
+        username: String,
+    ) -> Result<Vec<PacketModels::SenderKey>, StorageError> {
+        let mut conn = self.connection().await?;
+
+        let key = format!("sender_keys:{}", username);
+
+        let (encoded,): (Vec<String>,) = redis::pipe()
+            .atomic()
+            .lrange(&key, 0, -1)
+            .del(&key)
+            .ignore()
+            .query_async(&mut conn)
+            .await?;
+
+        let mut sender_keys = Vec::with_capacity(encoded.len());
+        for sender_key in encoded {
+            sender_keys.push(from_json(&sender_key)?);
+        }
+
+        Ok(sender_keys)
+    }
+}
+
+impl From<redis::RedisError> for StorageError {
+    fn from(err: redis::RedisError) -> Self {
+        StorageError {
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<redis::RedisError> for GroupError {
+    fn from(err: redis::RedisError) -> Self {
+        GroupError::Database(StorageError::from(err))
+    }
+}
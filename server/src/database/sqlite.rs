@@ -0,0 +1,1120 @@
+use std::{
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use libs::{BaseModels, PacketModels};
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use tokio::task;
+use uuid::Uuid;
+
+use super::{can_remove, from_json, to_json, validate_username, GroupError, Storage, StorageError};
+
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Idle connections kept open for reuse; busier moments open extra ones.
+const MAX_IDLE_CONNECTIONS: usize = 8;
+
+/// Schema migrations, applied in order. The number of applied migrations is
+/// kept in `PRAGMA user_version`; append new entries, never edit old ones.
+const MIGRATIONS: &[&str] = &[r#"
+    CREATE TABLE users (
+        username TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        password TEXT NOT NULL,
+        srp_salt TEXT,
+        srp_verifier TEXT
+    );
+
+    CREATE TABLE sessions (
+        id TEXT PRIMARY KEY,
+        username TEXT NOT NULL,
+        expires_at INTEGER NOT NULL
+    );
+    CREATE INDEX sessions_by_user ON sessions (username);
+
+    CREATE TABLE server (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+
+    CREATE TABLE key_bundles (
+        username TEXT PRIMARY KEY,
+        bundle TEXT NOT NULL
+    );
+
+    CREATE TABLE prekeys (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        username TEXT NOT NULL,
+        prekey TEXT NOT NULL
+    );
+    CREATE INDEX prekeys_by_user ON prekeys (username, id);
+
+    CREATE TABLE e2e (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        username TEXT NOT NULL,
+        init TEXT NOT NULL
+    );
+    CREATE INDEX e2e_by_user ON e2e (username, id);
+
+    CREATE TABLE sender_keys (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        username TEXT NOT NULL,
+        sender_key TEXT NOT NULL
+    );
+    CREATE INDEX sender_keys_by_user ON sender_keys (username, id);
+
+    CREATE TABLE chat_groups (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        owner TEXT NOT NULL
+    );
+
+    CREATE TABLE group_members (
+        group_id TEXT NOT NULL REFERENCES chat_groups (id),
+        username TEXT NOT NULL,
+        is_admin INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (group_id, username)
+    );
+    CREATE INDEX group_members_by_user ON group_members (username);
+
+    CREATE TABLE messages (
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        id TEXT NOT NULL,
+        group_id TEXT NOT NULL REFERENCES chat_groups (id),
+        created_at INTEGER NOT NULL,
+        message TEXT NOT NULL
+    );
+    CREATE INDEX messages_by_group_time ON messages (group_id, created_at, seq);
+
+    CREATE TABLE read_markers (
+        username TEXT NOT NULL,
+        group_id TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        PRIMARY KEY (username, group_id)
+    );
+"#];
+
+/// rusqlite only blocks, so every call runs on the blocking pool and the
+/// async workers never wait on the disk.
+pub struct SqliteStorage {
+    db: Arc<Database>,
+}
+
+impl SqliteStorage {
+    pub fn new(path: &Path) -> Result<Self, StorageError> {
+        Ok(Self {
+            db: Arc::new(Database::new(path)?),
+        })
+    }
+
+    pub fn schema_version(&self) -> Result<usize, StorageError> {
+        self.db.schema_version()
+    }
+
+    async fn run<T, E, F>(&self, call: F) -> Result<T, E>
+    where
+        F: FnOnce(&Database) -> Result<T, E> + Send + 'static,
+        T: Send + 'static,
+        E: From<StorageError> + Send + 'static,
+    {
+        let db = Arc::clone(&self.db);
+
+        match task::spawn_blocking(move || call(&db)).await {
+            Ok(result) => result,
+            Err(err) => Err(E::from(StorageError {
+                message: err.to_string(),
+            })),
+        }
+    }
+}
+
+struct Database {
+    path: PathBuf,
+    idle: Mutex<Vec<Connection>>,
+}
+
+/// A connection borrowed from the pool, returned to it when dropped.
+struct Pooled<'a> {
+    conn: Option<Connection>,
+    idle: &'a Mutex<Vec<Connection>>,
+}
+
+impl Database {
+    fn new(path: &Path) -> Result<Self, StorageError> {
+        let storage = Self {
+            path: path.to_path_buf(),
+            idle: Mutex::new(Vec::new()),
+        };
+
+        let mut conn = storage.get_connection()?;
+
+        let mode: String = conn.query_row("PRAGMA journal_mode = WAL", [], |row| row.get(0))?;
+
+        if !mode.eq_ignore_ascii_case("wal") {
+            return Err(StorageError {
+                message: format!("WAL Mode Unavailable: {}", mode),
+            });
+        }
+
+        migrate(&mut conn)?;
+        drop(conn);
+
+        Ok(storage)
+    }
+
+    fn schema_version(&self) -> Result<usize, StorageError> {
+        user_version(&*self.get_connection()?)
+    }
+
+    fn get_connection(&self) -> Result<Pooled<'_>, StorageError> {
+        let idle = match self.idle.lock() {
+            Ok(mut idle) => idle.pop(),
+            Err(_) => None,
+        };
+
+        let conn = match idle {
+            Some(conn) => conn,
+            None => {
+                let conn = Connection::open(&self.path)?;
+
+                conn.busy_timeout(BUSY_TIMEOUT)?;
+                conn.pragma_update(None, "foreign_keys", true)?;
+                conn.pragma_update(None, "synchronous", "NORMAL")?;
+
+                conn
+            }
+        };
+
+        Ok(Pooled {
+            conn: Some(conn),
+            idle: &self.idle,
+        })
+    }
+}
+
+impl Deref for Pooled<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().expect("pooled connection")
+    }
+}
+
+impl DerefMut for Pooled<'_> {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.conn.as_mut().expect("pooled connection")
+    }
+}
+
+impl Drop for Pooled<'_> {
+    fn drop(&mut self) {
+        let conn = match self.conn.take() {
+            Some(conn) => conn,
+            None => return,
+        };
+
+        // a connection dropped mid-transaction rolls back; never reuse one
+        // that is still inside one
+        if !conn.is_autocommit() {
+            return;
+        }
+
+        if let Ok(mut idle) = self.idle.lock() {
+            if idle.len() < MAX_IDLE_CONNECTIONS {
+                idle.push(conn);
+            }
+        }
+    }
+}
+
+fn user_version(conn: &Connection) -> Result<usize, StorageError> {
+    Ok(conn.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), StorageError> {
+    let version = user_version(conn)?;
+
+    if version > MIGRATIONS.len() {
+        return Err(StorageError {
+            message: format!(
+                "Database Schema Too New: {} > {}",
+                version,
+                MIGRATIONS.len()
+            ),
+        });
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", index + 1)?;
+
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+fn now() -> i64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(now) => now.as_secs() as i64,
+        Err(_) => 0,
+    }
+}
+
+fn parse_id(id: String) -> Option<Uuid> {
+    Uuid::parse_str(&id).ok()
+}
+
+fn load_group(conn: &Connection, id: Uuid) -> Result<BaseModels::Group, GroupError> {
+    let group: Option<(String, String)> = conn
+        .query_row(
+            "SELECT name, owner FROM chat_groups WHERE id = ?1",
+            [id.to_string()],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+
+    let (name, owner) = match group {
+        Some(group) => group,
+        None => return Err(GroupError::NotFound),
+    };
+
+    let mut stmt = conn.prepare(
+        "SELECT username, is_admin FROM group_members WHERE group_id = ?1 ORDER BY username",
+    )?;
+
+    let mut admins = Vec::new();
+    let mut members = Vec::new();
+
+    let rows = stmt.query_map([id.to_string()], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, bool>(1)?))
+    })?;
+
+    for row in rows {
+        let (username, is_admin) = row?;
+
+        if is_admin {
+            admins.push(username.clone());
+        }
+        members.push(username);
+    }
+
+    Ok(BaseModels::Group::full(id, name, owner, admins, members))
+}
+
+fn count_messages(conn: &Connection, id: Uuid) -> Result<u64, StorageError> {
+    Ok(conn.query_row(
+        "SELECT COUNT(*) FROM messages WHERE group_id = ?1",
+        [id.to_string()],
+        |row| row.get(0),
+    )?)
+}
+
+impl Database {
+    fn create_srp_user(
+        &self,
+        registration: PacketModels::SrpRegistration,
+    ) -> Result<(), StorageError> {
+        let mut conn = self.get_connection()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        let user = registration.get_user();
+
+        let exists: bool = tx.query_row(
+            "SELECT EXISTS (SELECT 1 FROM users WHERE username = ?1)",
+            [user.get_username()],
+            |row| row.get(0),
+        )?;
+
+        if exists {
+            return Err(StorageError::new("User Already Exists"));
+        }
+
+        tx.execute(
+            "INSERT INTO users (username, name, password, srp_salt, srp_verifier)
+             VALUES (?1, ?2, '', ?3, ?4)",
+            params![
+                user.get_username(),
+                user.get_name(),
+                registration.get_salt(),
+                registration.get_verifier()
+            ],
+        )?;
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    fn set_srp(
+        &self,
+        username: String,
+        salt: String,
+        verifier: String,
+    ) -> Result<(), StorageError> {
+        let mut conn = self.get_connection()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        tx.execute(
+            "UPDATE users SET password = '', srp_salt = ?2, srp_verifier = ?3 WHERE username = ?1",
+            params![username, salt, verifier],
+        )?;
+        tx.execute("DELETE FROM sessions WHERE username = ?1", [username])?;
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    fn get_srp(&self, username: String) -> Result<Option<(String, String)>, StorageError> {
+        let conn = self.get_connection()?;
+
+        let srp: Option<(Option<String>, Option<String>)> = conn
+            .query_row(
+                "SELECT srp_salt, srp_verifier FROM users WHERE username = ?1",
+                [username],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+
+        match srp {
+            Some((Some(salt), Some(verifier))) => Ok(Some((salt, verifier))),
+            _ => Ok(None),
+        }
+    }
+
+    fn get_user(&self, key: String) -> Result<BaseModels::User, StorageError> {
+        let conn = self.get_connection()?;
+
+        let user = conn
+            .query_row(
+                "SELECT name, username, password FROM users WHERE username = ?1",
+                [key],
+                |row| {
+                    Ok(BaseModels::User::full(
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                    ))
+                },
+            )
+            .optional()?;
+
+        match user {
+            Some(user) => Ok(user),
+            None => Err(StorageError::new("User Not Found")),
+        }
+    }
+
+    fn index_user(&self, _username: String) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    fn search_users(&self, prefix: String, limit: isize) -> Result<Vec<String>, StorageError> {
+        let conn = self.get_connection()?;
+
+        let mut upper = prefix.clone();
+        upper.push(char::MAX);
+
+        let mut stmt = conn.prepare(
+            "SELECT username FROM users WHERE username >= ?1 AND username < ?2
+             ORDER BY username LIMIT ?3",
+        )?;
+
+        let rows = stmt.query_map(params![prefix, upper, limit.max(0) as i64], |row| {
+            row.get(0)
+        })?;
+
+        let mut usernames = Vec::new();
+        for row in rows {
+            usernames.push(row?);
+        }
+
+        Ok(usernames)
+    }
+
+    fn create_session(&self, id: String, username: String, ttl: i64) -> Result<(), StorageError> {
+        let conn = self.get_connection()?;
+
+        let now = now();
+
+        conn.execute("DELETE FROM sessions WHERE expires_at <= ?1", [now])?;
+        conn.execute(
+            "INSERT OR REPLACE INTO sessions (id, username, expires_at) VALUES (?1, ?2, ?3)",
+            params![id, username, now + ttl.max(1)],
+        )?;
+
+        Ok(())
+    }
+
+    fn is_session_active(&self, id: String, username: String) -> Result<bool, StorageError> {
+        let conn = self.get_connection()?;
+
+        Ok(conn.query_row(
+            "SELECT EXISTS (
+                SELECT 1 FROM sessions WHERE id = ?1 AND username = ?2 AND expires_at > ?3
+             )",
+            params![id, username, now()],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn revoke_session(&self, id: String, username: String) -> Result<(), StorageError> {
+        let conn = self.get_connection()?;
+
+        conn.execute(
+            "DELETE FROM sessions WHERE id = ?1 AND username = ?2",
+            params![id, username],
+        )?;
+
+        Ok(())
+    }
+
+    fn revoke_sessions(&self, username: String) -> Result<(), StorageError> {
+        let conn = self.get_connection()?;
+
+        conn.execute("DELETE FROM sessions WHERE username = ?1", [username])?;
+
+        Ok(())
+    }
+
+    fn get_server_key(&self) -> Result<Option<String>, StorageError> {
+        let conn = self.get_connection()?;
+
+        Ok(conn
+            .query_row(
+                "SELECT value FROM server WHERE key = 'identity'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    fn set_server_key(&self, key: String) -> Result<(), StorageError> {
+        let conn = self.get_connection()?;
+
+        conn.execute(
+            "INSERT OR REPLACE INTO server (key, value) VALUES ('identity', ?1)",
+            [key],
+        )?;
+
+        Ok(())
+    }
+
+    fn set_key_bundle(
+        &self,
+        username: String,
+        bundle: PacketModels::KeyBundle,
+    ) -> Result<(), StorageError> {
+        let (bundle, prekeys) = bundle.split();
+
+        let mut conn = self.get_connection()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        let current: Option<String> = tx
+            .query_row(
+                "SELECT bundle FROM key_bundles WHERE username = ?1",
+                [&username],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        let rotated = match current {
+            Some(current) => {
+                let current: PacketModels::PreKeyBundle = from_json(&current)?;
+                current.get_identity_key() != bundle.get_identity_key()
+            }
+            None => true,
+        };
+
+        tx.execute(
+            "INSERT OR REPLACE INTO key_bundles (username, bundle) VALUES (?1, ?2)",
+            params![username, to_json(&bundle)?],
+        )?;
+
+        if rotated {
+            tx.execute("DELETE FROM prekeys WHERE username = ?1", [&username])?;
+        }
+
+        for prekey in &prekeys {
+            tx.execute(
+                "INSERT INTO prekeys (username, prekey) VALUES (?1, ?2)",
+                params![username, to_json(prekey)?],
+            )?;
+        }
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    fn take_key_bundle(
+        &self,
+        username: String,
+    ) -> Result<PacketModels::PreKeyBundle, StorageError> {
+        let mut conn = self.get_connection()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        let bundle: Option<String> = tx
+            .query_row(
+                "SELECT bundle FROM key_bundles WHERE username = ?1",
+                [&username],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        let bundle: PacketModels::PreKeyBundle = match bundle {
+            Some(bundle) => from_json(&bundle)?,
+            None => return Err(StorageError::new("Key Bundle Not Found")),
+        };
+
+        let prekey: Option<(i64, String)> = tx
+            .query_row(
+                "SELECT id, prekey FROM prekeys WHERE username = ?1 ORDER BY id LIMIT 1",
+                [&username],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+
+        let prekey = match prekey {
+            Some((id, prekey)) => {
+                tx.execute("DELETE FROM prekeys WHERE id = ?1", [id])?;
+                Some(from_json(&prekey)?)
+            }
+            None => None,
+        };
+
+        tx.commit()?;
+
+        Ok(bundle.with_one_time_prekey(prekey))
+    }
+
+    fn push_e2e(&self, username: String, init: PacketModels::E2E) -> Result<(), StorageError> {
+        let conn = self.get_connection()?;
+
+        conn.execute(
+            "INSERT INTO e2e (username, init) VALUES (?1, ?2)",
+            params![username, to_json(&init)?],
+        )?;
+
+        Ok(())
+    }
+
+    fn take_e2e(&self, username: String) -> Result<Vec<PacketModels::E2E>, StorageError> {
+        let mut conn = self.get_connection()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        let encoded = {
+            let mut stmt = tx.prepare("SELECT init FROM e2e WHERE username = ?1 ORDER BY id")?;
+            let rows = stmt.query_map([&username], |row| row.get::<_, String>(0))?;
+
+            let mut encoded = Vec::new();
+            for row in rows {
+                encoded.push(row?);
+            }
+            encoded
+        };
+
+        tx.execute("DELETE FROM e2e WHERE username = ?1", [&username])?;
+        tx.commit()?;
+
+        let mut sessions = Vec::with_capacity(encoded.len());
+        for init in encoded {
+            sessions.push(from_json(&init)?);
+        }
+
+        Ok(sessions)
+    }
+
+    fn push_sender_key(
+        &self,
+        username: String,
+        sender_key: PacketModels::SenderKey,
+    ) -> Result<(), StorageError> {
+        let conn = self.get_connection()?;
+
+        conn.execute(
+            "INSERT INTO sender_keys (username, sender_key) VALUES (?1, ?2)",
+            params![username, to_json(&sender_key)?],
+        )?;
+
+        Ok(())
+    }
+
+    fn take_sender_keys(
+        &self,
+        username: String,
+    ) -> Result<Vec<PacketModels::SenderKey>, StorageError> {
+        let mut conn = self.get_connection()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        let encoded = {
+            let mut stmt =
+                tx.prepare("SELECT sender_key FROM sender_keys WHERE username = ?1 ORDER BY id")?;
+            let rows = stmt.query_map([&username], |row| row.get::<_, String>(0))?;
+
+            let mut encoded = Vec::new();
+            for row in rows {
+                encoded.push(row?);
+            }
+            encoded
+        };
+
+        tx.execute("DELETE FROM sender_keys WHERE username = ?1", [&username])?;
+        tx.commit()?;
+
+        let mut sender_keys = Vec::with_capacity(encoded.len());
+        for sender_key in encoded {
+            sender_keys.push(from_json(&sender_key)?);
+        }
+
+        Ok(sender_keys)
+    }
+
+    fn create_group(&self, owner: String, name: String) -> Result<BaseModels::Group, StorageError> {
+        let mut conn = self.get_connection()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        let id = Uuid::new_v4();
+
+        tx.execute(
+            "INSERT INTO chat_groups (id, name, owner) VALUES (?1, ?2, ?3)",
+            params![id.to_string(), name, owner],
+        )?;
+        tx.execute(
+            "INSERT INTO group_members (group_id, username, is_admin) VALUES (?1, ?2, 1)",
+            params![id.to_string(), owner],
+        )?;
+
+        tx.commit()?;
+
+        Ok(BaseModels::Group::full(
+            id,
+            name,
+            owner.clone(),
+            vec![owner.clone()],
+            vec![owner],
+        ))
+    }
+
+    fn get_group(&self, id: Uuid) -> Result<BaseModels::Group, GroupError> {
+        let conn = self.get_connection()?;
+
+        load_group(&conn, id)
+    }
+
+    fn add_member(
+        &self,
+        id: Uuid,
+        requester: String,
+        username: String,
+    ) -> Result<BaseModels::Group, GroupError> {
+        let mut conn = self.get_connection()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        if !load_group(&tx, id)?.is_admin(&requester) {
+            return Err(GroupError::PermissionDenied);
+        }
+
+        let known: bool = tx.query_row(
+            "SELECT EXISTS (SELECT 1 FROM users WHERE username = ?1)",
+            [&username],
+            |row| row.get(0),
+        )?;
+
+        if !known {
+            return Err(GroupError::UnknownUser(username));
+        }
+
+        let added = tx.execute(
+            "INSERT OR IGNORE INTO group_members (group_id, username) VALUES (?1, ?2)",
+            params![id.to_string(), username],
+        )?;
+
+        if added == 0 {
+            return Err(GroupError::AlreadyMember(username));
+        }
+
+        let group = load_group(&tx, id)?;
+
+        tx.commit()?;
+
+        Ok(group)
+    }
+
+    fn remove_member(
+        &self,
+        id: Uuid,
+        requester: String,
+        username: String,
+    ) -> Result<BaseModels::Group, GroupError> {
+        let mut conn = self.get_connection()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        if !can_remove(&load_group(&tx, id)?, &requester, &username) {
+            return Err(GroupError::PermissionDenied);
+        }
+
+        let removed = tx.execute(
+            "DELETE FROM group_members WHERE group_id = ?1 AND username = ?2",
+            params![id.to_string(), username],
+        )?;
+
+        if removed == 0 {
+            return Err(GroupError::NotMember(username));
+        }
+
+        tx.execute(
+            "DELETE FROM read_markers WHERE username = ?1 AND group_id = ?2",
+            params![username, id.to_string()],
+        )?;
+
+        let group = load_group(&tx, id)?;
+
+        tx.commit()?;
+
+        Ok(group)
+    }
+
+    fn is_group_member(&self, id: Uuid, username: String) -> Result<bool, StorageError> {
+        let conn = self.get_connection()?;
+
+        Ok(conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM group_members WHERE group_id = ?1 AND username = ?2)",
+            params![id.to_string(), username],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn get_chats(&self, username: String) -> Result<PacketModels::Chats, GroupError> {
+        let conn = self.get_connection()?;
+
+        let ids = {
+            let mut stmt =
+                conn.prepare("SELECT group_id FROM group_members WHERE username = ?1")?;
+            let rows = stmt.query_map([&username], |row| row.get::<_, String>(0))?;
+
+            let mut ids = Vec::new();
+            for row in rows {
+                if let Some(id) = parse_id(row?) {
+                    ids.push(id);
+                }
+            }
+            ids
+        };
+
+        let mut groups = Vec::with_capacity(ids.len());
+        let mut unread = HashMap::with_capacity(ids.len());
+
+        for id in ids {
+            let group = match load_group(&conn, id) {
+                Ok(group) => group,
+                Err(GroupError::NotFound) => continue,
+                Err(err) => return Err(err),
+            };
+
+            let total = count_messages(&conn, id)?;
+            let read: Option<u64> = conn
+                .query_row(
+                    "SELECT position FROM read_markers WHERE username = ?1 AND group_id = ?2",
+                    params![username, id.to_string()],
+                    |row| row.get(0),
+                )
+                .optional()?;
+
+            unread.insert(id, total.saturating_sub(read.unwrap_or(0)));
+            groups.push(group);
+        }
+
+        groups.sort_by_key(|group| group.get_name());
+
+        Ok(PacketModels::Chats::new(groups, unread))
+    }
+
+    fn mark_read(&self, id: Uuid, username: String) -> Result<(), StorageError> {
+        let conn = self.get_connection()?;
+
+        let total = count_messages(&conn, id)?;
+
+        conn.execute(
+            "INSERT OR REPLACE INTO read_markers (username, group_id, position) VALUES (?1, ?2, ?3)",
+            params![username, id.to_string(), total],
+        )?;
+
+        Ok(())
+    }
+
+    fn create_message(&self, message: &BaseModels::Message) -> Result<(), StorageError> {
+        let conn = self.get_connection()?;
+
+        let group = message.get_member().get_group().get_id();
+        let created_at = message.get_created_at().assume_utc().unix_timestamp_nanos() as i64;
+
+        conn.execute(
+            "INSERT INTO messages (id, group_id, created_at, message) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                message.get_id().to_string(),
+                group.to_string(),
+                created_at,
+                to_json(message)?
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    fn get_messages(
+        &self,
+        group: BaseModels::Group,
+        cursor: Option<u64>,
+        limit: u32,
+    ) -> Result<PacketModels::Messages, StorageError> {
+        let conn = self.get_connection()?;
+
+        let id = group.get_id().to_string();
+        let limit = limit as i64;
+
+        // pages walk backwards by (created_at, seq); the cursor is the seq of
+        // the oldest message already seen, so new messages never shift a page
+        let rows: Vec<(i64, String)> = match cursor {
+            Some(cursor) => {
+                let mut stmt = conn.prepare(
+                    "SELECT seq, message FROM messages
+                     WHERE group_id = ?1 AND (created_at, seq) < (
+                         SELECT created_at, seq FROM messages WHERE seq = ?2 AND group_id = ?1
+                     )
+                     ORDER BY created_at DESC, seq DESC LIMIT ?3",
+                )?;
+
+                let rows = stmt.query_map(params![id, cursor as i64, limit + 1], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?;
+                rows.collect::<Result<_, _>>()?
+            }
+            None => {
+                let mut stmt = conn.prepare(
+                    "SELECT seq, message FROM messages WHERE group_id = ?1
+                     ORDER BY created_at DESC, seq DESC LIMIT ?2",
+                )?;
+
+                let rows =
+                    stmt.query_map(params![id, limit + 1], |row| Ok((row.get(0)?, row.get(1)?)))?;
+                rows.collect::<Result<_, _>>()?
+            }
+        };
+
+        let more = rows.len() as i64 > limit;
+
+        let mut page = rows;
+        page.truncate(limit as usize);
+
+        let next_cursor = match (more, page.last()) {
+            (true, Some((seq, _))) => Some(*seq as u64),
+            _ => None,
+        };
+
+        let mut messages = Vec::with_capacity(page.len());
+        for (_, message) in page.into_iter().rev() {
+            messages.push(from_json(&message)?);
+        }
+
+        Ok(PacketModels::Messages::new(group, messages, next_cursor))
+    }
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn create_srp_user(
+        &self,
+        registration: PacketModels::SrpRegistration,
+    ) -> Result<(), StorageError> {
+        validate_username(&registration.get_user().get_username())?;
+
+        self.run(move |db| db.create_srp_user(registration)).await
+    }
+
+    async fn set_srp(
+        &self,
+        username: String,
+        salt: String,
+        verifier: String,
+    ) -> Result<(), StorageError> {
+        self.run(move |db| db.set_srp(username, salt, verifier))
+            .await
+    }
+
+    async fn get_srp(&self, username: String) -> Result<Option<(String, String)>, StorageError> {
+        self.run(move |db| db.get_srp(username)).await
+    }
+
+    async fn get_user(&self, key: String) -> Result<BaseModels::User, StorageError> {
+        self.run(move |db| db.get_user(key)).await
+    }
+
+    async fn index_user(&self, username: String) -> Result<(), StorageError> {
+        self.run(move |db| db.index_user(username)).await
+    }
+
+    async fn search_users(
+        &self,
+        prefix: String,
+        limit: isize,
+    ) -> Result<Vec<String>, StorageError> {
+        self.run(move |db| db.search_users(prefix, limit)).await
+    }
+
+    async fn create_session(
+        &self,
+        id: String,
+        username: String,
+        ttl: i64,
+    ) -> Result<(), StorageError> {
+        self.run(move |db| db.create_session(id, username, ttl))
+            .await
+    }
+
+    async fn is_session_active(&self, id: String, username: String) -> Result<bool, StorageError> {
+        self.run(move |db| db.is_session_active(id, username)).await
+    }
+
+    async fn revoke_session(&self, id: String, username: String) -> Result<(), StorageError> {
+        self.run(move |db| db.revoke_session(id, username)).await
+    }
+
+    async fn revoke_sessions(&self, username: String) -> Result<(), StorageError> {
+        self.run(move |db| db.revoke_sessions(username)).await
+    }
+
+    async fn get_server_key(&self) -> Result<Option<String>, StorageError> {
+        self.run(move |db| db.get_server_key()).await
+    }
+
+    async fn set_server_key(&self, key: String) -> Result<(), StorageError> {
+        self.run(move |db| db.set_server_key(key)).await
+    }
+
+    async fn set_key_bundle(
+        &self,
+        username: String,
+        bundle: PacketModels::KeyBundle,
+    ) -> Result<(), StorageError> {
+        self.run(move |db| db.set_key_bundle(username, bundle))
+            .await
+    }
+
+    async fn take_key_bundle(
+        &self,
+        username: String,
+    ) -> Result<PacketModels::PreKeyBundle, StorageError> {
+        self.run(move |db| db.take_key_bundle(username)).await
+    }
+
+    async fn push_e2e(
+        &self,
+        username: String,
+        init: PacketModels::E2E,
+    ) -> Result<(), StorageError> {
+        self.run(move |db| db.push_e2e(username, init)).await
+    }
+
+    async fn take_e2e(&self, username: String) -> Result<Vec<PacketModels::E2E>, StorageError> {
+        self.run(move |db| db.take_e2e(username)).await
+    }
+
+    async fn push_sender_key(
+        &self,
+        username: String,
+        sender_key: PacketModels::SenderKey,
+    ) -> Result<(), StorageError> {
+        self.run(move |db| db.push_sender_key(username, sender_key))
+            .await
+    }
+
+    async fn take_sender_keys(
+        &self,
+        username: String,
+    ) -> Result<Vec<PacketModels::SenderKey>, StorageError> {
+        self.run(move |db| db.take_sender_keys(username)).await
+    }
+
+    async fn create_group(
+        &self,
+        owner: String,
+        name: String,
+    ) -> Result<BaseModels::Group, StorageError> {
+        self.run(move |db| db.create_group(owner, name)).await
+    }
+
+    async fn get_group(&self, id: Uuid) -> Result<BaseModels::Group, GroupError> {
+        self.run(move |db| db.get_group(id)).await
+    }
+
+    async fn add_member(
+        &self,
+        id: Uuid,
+        requester: String,
+        username: String,
+    ) -> Result<BaseModels::Group, GroupError> {
+        self.run(move |db| db.add_member(id, requester, username))
+            .await
+    }
+
+    async fn remove_member(
+        &self,
+        id: Uuid,
+        requester: String,
+        username: String,
+    ) -> Result<BaseModels::Group, GroupError> {
+        self.run(move |db| db.remove_member(id, requester, username))
+            .await
+    }
+
+    async fn is_group_member(&self, id: Uuid, username: String) -> Result<bool, StorageError> {
+        self.run(move |db| db.is_group_member(id, username)).await
+    }
+
+    async fn get_chats(&self, username: String) -> Result<PacketModels::Chats, GroupError> {
+        self.run(move |db| db.get_chats(username)).await
+    }
+
+    async fn mark_read(&self, id: Uuid, username: String) -> Result<(), StorageError> {
+        self.run(move |db| db.mark_read(id, username)).await
+    }
+
+    async fn create_message(&self, message: &BaseModels::Message) -> Result<(), StorageError> {
+        let message = message.clone();
+
+        self.run(move |db| db.create_message(&message)).await
+    }
+
+    async fn get_messages(
+        &self,
+        group: BaseModels::Group,
+        cursor: Option<u64>,
+        limit: u32,
+    ) -> Result<PacketModels::Messages, StorageError> {
+        self.run(move |db| db.get_messages(group, cursor, limit))
+            .await
+    }
+}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(err: rusqlite::Error) -> Self {
+        StorageError {
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for GroupError {
+    fn from(err: rusqlite::Error) -> Self {
+        GroupError::Database(StorageError::from(err))
+    }
+}
@@ -1,73 +1,273 @@
-use std::{env, net::TcpListener, sync::Arc};
+use std::{io::ErrorKind, sync::Arc, time::Duration};
 
-use threadpool::ThreadPool;
+use libs::{
+    crypto::{tls, transport::ServerIdentity},
+    packet::{Packet, PacketType},
+    PacketModels,
+};
+use tokio::{runtime, sync::mpsc, task::JoinSet, time};
+use tokio_rustls::TlsAcceptor;
 
 mod client;
 use client::Client;
 
-mod database;
-use crate::database::Database;
+mod connection;
 
-pub struct Config {
-    pub addr: String,
-    pub max_workers: usize,
-    pub flags: String,
+pub mod config;
+pub use config::{Config, ConfigError, Limits, TlsConfig};
+
+pub mod database;
+use database::Storage;
+
+pub mod listener;
+use listener::{Connection, Incoming, Protocol};
+pub use listener::{Connector, Listener};
+
+pub mod log;
+
+pub mod password;
+
+pub mod registry;
+use registry::Registry;
+
+pub mod shutdown;
+pub use shutdown::Shutdown;
+
+pub mod token;
+use token::{TokenIssuer, DEFAULT_TOKEN_TTL};
+
+pub fn run(config: Config) -> Result<(), String> {
+    let mut listeners = Vec::new();
+
+    match Listener::bind(&config.addr) {
+        Ok(listener) => listeners.push(listener),
+        Err(err) => return Err(err.to_string()),
+    };
+
+    if let Some(addr) = &config.websocket {
+        match Listener::bind(addr) {
+            Ok(listener) => listeners.push(Listener::websocket(listener)),
+            Err(err) => return Err(format!("{}: {}", addr, err)),
+        }
+    }
+
+    let shutdown = Shutdown::new();
+    shutdown.on_signal()?;
+
+    serve_all(listeners, config, shutdown)
 }
 
-impl Config {
-    pub fn new(mut args: env::Args) -> Result<Self, &'static str> {
-        args.next();
+/// Bounds the pause after a failed accept, such as running out of file
+/// descriptors, so the loop doesn't spin while the cause persists.
+const MIN_ACCEPT_BACKOFF: Duration = Duration::from_millis(5);
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
 
-        let addr = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Address not provided"),
-        };
+/// How long blocking storage calls still running at exit are waited on.
+const BLOCKING_GRACE: Duration = Duration::from_millis(50);
+
+/// Covers the TLS and WebSocket handshakes of a new connection.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serves `listener` until `shutdown` is triggered, then stops accepting,
+/// tells every connected client and waits up to `limits.shutdown_timeout`
+/// for in-flight requests before closing what is left.
+pub fn serve(
+    listener: impl Into<Listener>,
+    config: Config,
+    shutdown: Shutdown,
+) -> Result<(), String> {
+    serve_all(vec![listener.into()], config, shutdown)
+}
+
+/// Like `serve`, but accepts from several listeners at once. Clients on any
+/// of them share the same storage and see each other's events.
+pub fn serve_all(
+    listeners: Vec<Listener>,
+    config: Config,
+    shutdown: Shutdown,
+) -> Result<(), String> {
+    log::set_level(config.log_level);
+
+    let tls = match &config.tls {
+        Some(tls) => match tls::server_config(&tls.cert, &tls.key) {
+            Ok(tls) => Some(TlsAcceptor::from(tls)),
+            Err(err) => return Err(err.message),
+        },
+        None => None,
+    };
 
-        let max_workers = match args.next() {
-            Some(arg) => arg.parse().unwrap(),
-            None => return Err("Max Workers not provided"),
+    let runtime = match runtime::Builder::new_multi_thread()
+        .worker_threads(config.max_workers)
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(err) => return Err(err.to_string()),
+    };
+
+    let registry = Arc::new(Registry::new());
+
+    let result = runtime.block_on(async {
+        let database = match config.storage.open() {
+            Ok(db) => db,
+            Err(err) => return Err(err.to_string()),
         };
 
-        let flags = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Flags not provided"),
+        let identity = load_identity(database.as_ref()).await?;
+
+        log::info(format!("Server key: {}", identity.public_key()));
+
+        let tokens = match identity.derive_secret(b"secure_chat/token/v1") {
+            Ok(key) => TokenIssuer::new(key, DEFAULT_TOKEN_TTL),
+            Err(err) => return Err(err.message),
         };
 
-        Ok(Self {
-            addr,
-            max_workers,
-            flags,
-        })
-    }
+        let identity = Arc::new(identity);
+        let tokens = Arc::new(tokens);
+
+        let (accepted, mut pending) = mpsc::channel(listeners.len());
+        let mut acceptors = JoinSet::new();
+
+        for listener in listeners {
+            match listener.incoming() {
+                Ok(incoming) => acceptors.spawn(accept(incoming, accepted.clone())),
+                Err(err) => return Err(err.to_string()),
+            };
+        }
+
+        log::info("Server is running");
+
+        let mut connections = JoinSet::new();
+
+        loop {
+            let (stream, protocol) = tokio::select! {
+                _ = shutdown.triggered() => break,
+                Some(accepted) = pending.recv() => accepted,
+                Some(_) = connections.join_next() => continue,
+            };
+
+            let tls = tls.clone();
+            let database = Arc::clone(&database);
+            let identity = Arc::clone(&identity);
+            let tokens = Arc::clone(&tokens);
+            let registry = Arc::clone(&registry);
+            let limits = config.limits;
+
+            connections.spawn(async move {
+                let handshake = connection::open(stream, protocol, tls, limits.max_frame_size);
+
+                let (reader, writer) = match time::timeout(HANDSHAKE_TIMEOUT, handshake).await {
+                    Ok(Ok(halves)) => halves,
+                    Ok(Err(err)) => return log::debug(err),
+                    Err(_) => return log::debug("Handshake timed out"),
+                };
+
+                Client::serve(reader, writer, database, identity, tokens, registry, limits).await;
+            });
+        }
+
+        // Dropping the acceptors closes their listeners.
+        acceptors.shutdown().await;
+
+        drain(connections, &registry, config.limits.shutdown_timeout).await
+    });
+
+    // Storage calls stuck on the blocking pool are abandoned rather than waited on.
+    runtime.shutdown_timeout(BLOCKING_GRACE);
+
+    result
 }
 
-pub fn run(config: Config) -> Result<(), String> {
-    let listener = match TcpListener::bind(config.addr) {
-        Ok(listener) => listener,
+/// Stops accepting, sends every client the shutdown notice and gives open
+/// connections until the deadline to finish before aborting them.
+async fn drain(
+    mut connections: JoinSet<()>,
+    registry: &Registry,
+    timeout: u32,
+) -> Result<(), String> {
+    let timeout = u64::from(timeout);
+    log::info(format!("Shutting down, waiting up to {}s", timeout));
+
+    let notice = Packet::new(
+        PacketType::Shutdown,
+        PacketModels::Shutdown::new(String::from("Server is shutting down"), timeout),
+    );
+
+    let notice = match notice.to() {
+        Ok(notice) => notice,
         Err(err) => return Err(err.to_string()),
     };
 
-    let pool = ThreadPool::new(config.max_workers);
+    registry.shutdown(&notice);
+
+    let finished = async { while connections.join_next().await.is_some() {} };
+    if time::timeout(Duration::from_secs(timeout), finished)
+        .await
+        .is_err()
+    {
+        log::warn(format!(
+            "Closed {} connections after the deadline",
+            connections.len()
+        ));
+        connections.shutdown().await;
+    }
+
+    Ok(())
+}
+
+/// Hands connections from one listener to the accept loop.
+async fn accept(mut incoming: Incoming, accepted: mpsc::Sender<(Connection, Protocol)>) {
+    let mut backoff = MIN_ACCEPT_BACKOFF;
 
-    println!("[!] Server is running");
+    loop {
+        match incoming.accept().await {
+            Ok(stream) => {
+                backoff = MIN_ACCEPT_BACKOFF;
 
-    let database = match Database::new() {
-        Ok(db) => db,
+                if accepted.send((stream, incoming.protocol())).await.is_err() {
+                    break;
+                }
+            }
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    ErrorKind::ConnectionAborted | ErrorKind::ConnectionReset
+                ) =>
+            {
+                log::debug(err)
+            }
+            Err(err) => {
+                log::warn(format!(
+                    "Accept failed, retrying in {}ms: {}",
+                    backoff.as_millis(),
+                    err
+                ));
+
+                time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+            }
+        }
+    }
+}
+
+async fn load_identity(database: &dyn Storage) -> Result<ServerIdentity, String> {
+    let key = match database.get_server_key().await {
+        Ok(key) => key,
         Err(err) => return Err(err.to_string()),
     };
 
-    let database = Arc::new(database);
-
-    for stream in listener.incoming() {
-        let stream = match stream {
-            Ok(stream) => stream,
-            _ => continue,
+    if let Some(key) = key {
+        return match ServerIdentity::from_encoded(&key) {
+            Ok(identity) => Ok(identity),
+            Err(err) => Err(err.message),
         };
+    }
 
-        let mut client = Client::new(stream, Arc::clone(&database));
+    let identity = ServerIdentity::generate();
 
-        pool.execute(move || client.run());
+    if let Err(err) = database.set_server_key(identity.encode()).await {
+        return Err(err.to_string());
     }
 
-    Ok(())
+    Ok(identity)
 }
@@ -0,0 +1,182 @@
+use std::{
+    future,
+    io::{self, ErrorKind},
+    net::TcpListener,
+};
+
+#[cfg(unix)]
+use std::{fs, os::unix::net::UnixListener};
+
+use libs::packet_manager::{self, Duplex, Stream};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net,
+    sync::mpsc,
+};
+
+const UNIX_PREFIX: &str = "unix:";
+
+/// Where the server accepts connections from.
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+    Pipe(mpsc::UnboundedReceiver<Duplex>),
+    /// Speaks WebSocket, one text frame per packet, on top of another listener.
+    WebSocket(Box<Listener>),
+}
+
+/// Opens in-process connections to a server serving a `Listener::pipe`.
+#[derive(Clone)]
+pub struct Connector {
+    tx: mpsc::UnboundedSender<Duplex>,
+}
+
+pub(crate) trait AsyncStream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> AsyncStream for T {}
+
+pub(crate) type Connection = Box<dyn AsyncStream>;
+
+/// How packets are laid out on an accepted connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Protocol {
+    Framed,
+    WebSocket,
+}
+
+/// A `Listener` registered with the tokio runtime.
+pub(crate) struct Incoming {
+    socket: Socket,
+    protocol: Protocol,
+}
+
+enum Socket {
+    Tcp(net::TcpListener),
+    #[cfg(unix)]
+    Unix(net::UnixListener),
+    Pipe(mpsc::UnboundedReceiver<Duplex>),
+}
+
+impl Listener {
+    /// Binds `addr`, which is either `host:port` or `unix:<path>`.
+    pub fn bind(addr: &str) -> io::Result<Self> {
+        let listener = match addr.strip_prefix(UNIX_PREFIX) {
+            #[cfg(unix)]
+            Some(path) => Listener::Unix(UnixListener::bind(path)?),
+            #[cfg(not(unix))]
+            Some(_) => {
+                return Err(io::Error::new(
+                    ErrorKind::Unsupported,
+                    "Unix sockets are not supported on this platform",
+                ))
+            }
+            None => Listener::Tcp(TcpListener::bind(addr)?),
+        };
+
+        Ok(listener)
+    }
+
+    pub fn pipe() -> (Connector, Self) {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        (Connector { tx }, Listener::Pipe(rx))
+    }
+
+    pub fn websocket(listener: impl Into<Listener>) -> Self {
+        Listener::WebSocket(Box::new(listener.into()))
+    }
+
+    /// Hands the listener to the runtime; must be called from inside it.
+    pub(crate) fn incoming(self) -> io::Result<Incoming> {
+        let socket = match self {
+            Listener::Tcp(listener) => {
+                listener.set_nonblocking(true)?;
+                Socket::Tcp(net::TcpListener::from_std(listener)?)
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener) => {
+                listener.set_nonblocking(true)?;
+                Socket::Unix(net::UnixListener::from_std(listener)?)
+            }
+            Listener::Pipe(rx) => Socket::Pipe(rx),
+            Listener::WebSocket(listener) => {
+                return Ok(Incoming {
+                    protocol: Protocol::WebSocket,
+                    ..listener.incoming()?
+                })
+            }
+        };
+
+        Ok(Incoming {
+            socket,
+            protocol: Protocol::Framed,
+        })
+    }
+}
+
+impl Incoming {
+    /// Waits for the next connection. A pipe with no connectors left never
+    /// yields one.
+    pub(crate) async fn accept(&mut self) -> io::Result<Connection> {
+        match &mut self.socket {
+            Socket::Tcp(listener) => {
+                let (stream, _) = listener.accept().await?;
+                Ok(Box::new(stream))
+            }
+            #[cfg(unix)]
+            Socket::Unix(listener) => {
+                let (stream, _) = listener.accept().await?;
+                Ok(Box::new(stream))
+            }
+            Socket::Pipe(rx) => match rx.recv().await {
+                Some(stream) => Ok(Box::new(stream)),
+                None => future::pending().await,
+            },
+        }
+    }
+
+    pub(crate) fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+}
+
+/// Removes the socket file so the next `bind` to the same path succeeds.
+#[cfg(unix)]
+impl Drop for Socket {
+    fn drop(&mut self) {
+        let addr = match self {
+            Socket::Unix(listener) => listener.local_addr(),
+            _ => return,
+        };
+
+        if let Some(path) = addr.ok().as_ref().and_then(|addr| addr.as_pathname()) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+impl From<TcpListener> for Listener {
+    fn from(listener: TcpListener) -> Self {
+        Listener::Tcp(listener)
+    }
+}
+
+#[cfg(unix)]
+impl From<UnixListener> for Listener {
+    fn from(listener: UnixListener) -> Self {
+        Listener::Unix(listener)
+    }
+}
+
+impl Connector {
+    /// Fails with `ConnectionRefused` once the server has stopped.
+    pub fn connect(&self) -> io::Result<Stream> {
+        let (client, server) = packet_manager::duplex();
+
+        match self.tx.send(server) {
+            Ok(()) => Ok(Stream::from(client)),
+            Err(_) => Err(io::Error::from(ErrorKind::ConnectionRefused)),
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 0,
+    Warn = 1,
+    Info = 2,
+    Debug = 3,
+}
+
+impl Level {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "error" => Ok(Level::Error),
+            "warn" | "warning" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            _ => Err(format!(
+                "unknown log level '{}' (expected error, warn, info or debug)",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Level::Error => write!(f, "error"),
+            Level::Warn => write!(f, "warn"),
+            Level::Info => write!(f, "info"),
+            Level::Debug => write!(f, "debug"),
+        }
+    }
+}
+
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+pub fn error(message: impl fmt::Display) {
+    if enabled(Level::Error) {
+        eprintln!("[error] {}", message);
+    }
+}
+
+pub fn warn(message: impl fmt::Display) {
+    if enabled(Level::Warn) {
+        eprintln!("[warn] {}", message);
+    }
+}
+
+pub fn info(message: impl fmt::Display) {
+    if enabled(Level::Info) {
+        println!("[!] {}", message);
+    }
+}
+
+pub fn debug(message: impl fmt::Display) {
+    if enabled(Level::Debug) {
+        println!("[debug] {}", message);
+    }
+}
@@ -1,9 +1,15 @@
-use server::Config;
+use server::{Config, ConfigError};
 use std::{env, process};
 fn main() {
-    let config = Config::new(env::args()).unwrap_or_else(|err| {
-        eprintln!("Problem parsing arguments: {}", err);
-        process::exit(1);
+    let config = Config::new(env::args()).unwrap_or_else(|err| match err {
+        ConfigError::Help => {
+            println!("{}", err);
+            process::exit(0);
+        }
+        ConfigError::Invalid(_) => {
+            eprintln!("Problem parsing arguments: {}", err);
+            process::exit(2);
+        }
     });
 
     if let Err(e) = server::run(config) {
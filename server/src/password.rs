@@ -0,0 +1,54 @@
+use argon2::{
+    password_hash::{self, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use rand::rngs::OsRng;
+use std::sync::OnceLock;
+use subtle::ConstantTimeEq;
+
+const DUMMY_PASSWORD: &str = "secure_chat/password/dummy";
+
+static DUMMY_HASH: OnceLock<Option<String>> = OnceLock::new();
+
+pub enum Verified {
+    Valid,
+    Invalid,
+    Legacy,
+}
+
+pub fn hash(password: &str) -> Result<String, password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string())
+}
+
+pub fn verify(password: &str, stored: &str) -> Verified {
+    if stored.is_empty() {
+        return Verified::Invalid;
+    }
+
+    let hash = match PasswordHash::new(stored) {
+        Ok(hash) => hash,
+        Err(_) => {
+            return match bool::from(password.as_bytes().ct_eq(stored.as_bytes())) {
+                true => Verified::Legacy,
+                false => Verified::Invalid,
+            }
+        }
+    };
+
+    match Argon2::default().verify_password(password.as_bytes(), &hash) {
+        Ok(()) => Verified::Valid,
+        Err(_) => Verified::Invalid,
+    }
+}
+
+pub fn verify_dummy(password: &str) {
+    let stored = DUMMY_HASH.get_or_init(|| hash(DUMMY_PASSWORD).ok());
+
+    if let Some(stored) = stored {
+        verify(password, stored);
+    }
+}
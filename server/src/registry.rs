@@ -0,0 +1,199 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+};
+
+use libs::packet::{DataPacket, PacketError};
+use tokio::sync::{
+    mpsc::{self, error::TrySendError, Receiver, Sender},
+    Notify,
+};
+
+/// Requests and events a connection may have queued before it counts as
+/// falling behind.
+pub const INBOX_CAPACITY: usize = 64;
+
+pub enum Inbound {
+    Request(DataPacket),
+    Event(DataPacket),
+    Closed(PacketError),
+    Shutdown(DataPacket),
+}
+
+/// Sending half of a connection's bounded queue. Frames read from the socket
+/// wait for room, so a connection that can't keep up stops reading; events
+/// from other connections never wait, and one that doesn't fit marks the
+/// connection as lagging so it is closed instead of silently missing events.
+#[derive(Clone)]
+pub struct Inbox {
+    queue: Sender<Inbound>,
+    lagging: Arc<Notify>,
+}
+
+impl Inbox {
+    pub fn new() -> (Self, Receiver<Inbound>) {
+        Self::with_capacity(INBOX_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> (Self, Receiver<Inbound>) {
+        let (queue, inbound) = mpsc::channel(capacity);
+
+        let inbox = Self {
+            queue,
+            lagging: Arc::new(Notify::new()),
+        };
+
+        (inbox, inbound)
+    }
+
+    /// Waits for room in the queue. Fails once the connection is gone.
+    pub async fn send(&self, inbound: Inbound) -> bool {
+        self.queue.send(inbound).await.is_ok()
+    }
+
+    /// Queues without waiting. Fails if the connection is gone or lagging.
+    pub fn push(&self, inbound: Inbound) -> bool {
+        match self.queue.try_send(inbound) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.lagging.notify_one();
+                false
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    }
+
+    /// Resolves once something could not be queued because it was full.
+    pub async fn lagging(&self) {
+        self.lagging.notified().await
+    }
+}
+
+type Listeners = HashMap<String, Vec<(u64, Inbox)>>;
+
+#[derive(Default)]
+struct Connections {
+    open: HashMap<u64, Inbox>,
+    notice: Option<DataPacket>,
+}
+
+pub struct Registry {
+    listeners: Mutex<Listeners>,
+    connections: Mutex<Connections>,
+    next_id: AtomicU64,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self {
+            listeners: Mutex::new(HashMap::new()),
+            connections: Mutex::new(Connections::default()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Tracks an open connection so it can be told about a shutdown. Once the
+    /// server is closing this hands back the shutdown notice instead.
+    pub fn connect(&self, inbox: Inbox) -> Result<u64, DataPacket> {
+        let mut connections = lock(&self.connections);
+
+        if let Some(notice) = &connections.notice {
+            return Err(notice.clone());
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        connections.open.insert(id, inbox);
+
+        Ok(id)
+    }
+
+    pub fn disconnect(&self, id: u64) {
+        lock(&self.connections).open.remove(&id);
+    }
+
+    pub fn connection_count(&self) -> usize {
+        lock(&self.connections).open.len()
+    }
+
+    pub fn is_closing(&self) -> bool {
+        lock(&self.connections).notice.is_some()
+    }
+
+    /// Refuses new connections and queues `notice` behind whatever each open
+    /// connection is already handling.
+    pub fn shutdown(&self, notice: &DataPacket) -> usize {
+        let mut connections = lock(&self.connections);
+
+        connections.notice = Some(notice.clone());
+        connections
+            .open
+            .retain(|_, inbox| inbox.push(Inbound::Shutdown(notice.clone())));
+
+        connections.open.len()
+    }
+
+    pub fn subscribe(&self, username: String, inbox: Inbox) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        self.lock().entry(username).or_default().push((id, inbox));
+
+        id
+    }
+
+    pub fn unsubscribe(&self, username: &str, id: u64) {
+        let mut listeners = self.lock();
+
+        if let Some(inboxes) = listeners.get_mut(username) {
+            inboxes.retain(|(listener, _)| *listener != id);
+
+            if inboxes.is_empty() {
+                listeners.remove(username);
+            }
+        }
+    }
+
+    pub fn publish(&self, usernames: &[String], packet: &DataPacket) -> usize {
+        let mut listeners = self.lock();
+
+        let mut delivered = 0;
+        for username in usernames {
+            let inboxes = match listeners.get_mut(username) {
+                Some(inboxes) => inboxes,
+                None => continue,
+            };
+
+            inboxes.retain(|(_, inbox)| inbox.push(Inbound::Event(packet.clone())));
+            delivered += inboxes.len();
+
+            if inboxes.is_empty() {
+                listeners.remove(username);
+            }
+        }
+
+        delivered
+    }
+
+    pub fn is_listening(&self, username: &str) -> bool {
+        self.lock().contains_key(username)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Listeners> {
+        lock(&self.listeners)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
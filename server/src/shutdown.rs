@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// Cloneable trigger shared between the accept loop and whoever decides the
+/// server should stop: the signal handler in `run`, or a test harness.
+#[derive(Clone)]
+pub struct Shutdown {
+    state: Arc<watch::Sender<bool>>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (state, _) = watch::channel(false);
+
+        Self {
+            state: Arc::new(state),
+        }
+    }
+
+    pub fn on_signal(&self) -> Result<(), String> {
+        let shutdown = self.clone();
+
+        match ctrlc::set_handler(move || shutdown.trigger()) {
+            Ok(()) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    pub fn trigger(&self) {
+        self.state.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.state.borrow()
+    }
+
+    /// Resolves once triggered, immediately if that already happened.
+    pub async fn triggered(&self) {
+        let mut triggered = self.state.subscribe();
+
+        // the sender lives as long as `self`, so this only ends when triggered
+        let _ = triggered.wait_for(|triggered| *triggered).await;
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
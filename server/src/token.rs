@@ -0,0 +1,119 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use libs::PacketModels;
+
+pub const DEFAULT_TOKEN_TTL: i64 = 7 * 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize)]
+pub struct Claims {
+    pub id: String,
+    pub username: String,
+    pub expires_at: i64,
+}
+
+pub struct TokenIssuer {
+    key: [u8; 32],
+    ttl: i64,
+}
+
+impl TokenIssuer {
+    pub fn new(key: [u8; 32], ttl: i64) -> Self {
+        Self { key, ttl }
+    }
+
+    pub fn ttl(&self) -> i64 {
+        self.ttl
+    }
+
+    pub fn issue(&self, username: String) -> Result<(Claims, PacketModels::SessionToken), String> {
+        self.issue_at(username, now())
+    }
+
+    pub fn issue_at(
+        &self,
+        username: String,
+        now: i64,
+    ) -> Result<(Claims, PacketModels::SessionToken), String> {
+        let mut id = [0; 16];
+        OsRng.fill_bytes(&mut id);
+
+        let claims = Claims {
+            id: STANDARD.encode(id),
+            username,
+            expires_at: now + self.ttl,
+        };
+
+        let payload = match serde_json::to_vec(&claims) {
+            Ok(payload) => STANDARD.encode(payload),
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let signature = STANDARD.encode(self.mac(&payload)?.finalize().into_bytes());
+
+        let token = PacketModels::SessionToken::new(
+            format!("{}.{}", payload, signature),
+            claims.expires_at,
+        );
+
+        Ok((claims, token))
+    }
+
+    pub fn verify(&self, token: &str) -> Result<Claims, String> {
+        self.verify_at(token, now())
+    }
+
+    pub fn verify_at(&self, token: &str, now: i64) -> Result<Claims, String> {
+        let (payload, signature) = match token.split_once('.') {
+            Some(parts) => parts,
+            None => return Err(String::from("Malformed Session Token")),
+        };
+
+        let signature = match STANDARD.decode(signature) {
+            Ok(signature) => signature,
+            Err(_) => return Err(String::from("Malformed Session Token")),
+        };
+
+        if self.mac(payload)?.verify_slice(&signature).is_err() {
+            return Err(String::from("Invalid Session Token"));
+        }
+
+        let claims: Claims = match STANDARD
+            .decode(payload)
+            .ok()
+            .and_then(|payload| serde_json::from_slice(&payload).ok())
+        {
+            Some(claims) => claims,
+            None => return Err(String::from("Malformed Session Token")),
+        };
+
+        if claims.expires_at <= now {
+            return Err(String::from("Session Token Expired"));
+        }
+
+        Ok(claims)
+    }
+
+    fn mac(&self, payload: &str) -> Result<Hmac<Sha256>, String> {
+        let mut mac = match Hmac::<Sha256>::new_from_slice(&self.key) {
+            Ok(mac) => mac,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        mac.update(payload.as_bytes());
+
+        Ok(mac)
+    }
+}
+
+fn now() -> i64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(_) => 0,
+    }
+}
@@ -0,0 +1,164 @@
+//! Fixtures shared by the integration tests that talk to a running server.
+#![allow(dead_code)]
+
+use std::{
+    net::TcpStream,
+    thread::{self, JoinHandle},
+};
+
+use libs::{
+    crypto::{
+        srp::{self, SrpClient},
+        transport::ClientHandshake,
+    },
+    packet::{Codec, DataPacket, Packet, PacketType},
+    packet_manager::{Channel, Stream},
+    BaseModels, PacketModels,
+};
+use serde::{Deserialize, Serialize};
+use server::{Config, Listener, Shutdown};
+
+/// Serves `listeners` on a background thread until the returned `Shutdown`
+/// is triggered.
+pub fn spawn(
+    listeners: Vec<Listener>,
+    config: Config,
+) -> (Shutdown, JoinHandle<Result<(), String>>) {
+    let shutdown = Shutdown::new();
+    let trigger = shutdown.clone();
+    let handle = thread::spawn(move || server::serve_all(listeners, config, trigger));
+
+    (shutdown, handle)
+}
+
+pub fn connect(addr: &str) -> Channel {
+    open(TcpStream::connect(addr).unwrap())
+}
+
+pub fn open(stream: impl Into<Stream>) -> Channel {
+    open_with(stream, Codec::Json)
+}
+
+/// Wraps `stream` in a channel and runs the key exchange on it.
+pub fn open_with(stream: impl Into<Stream>, codec: Codec) -> Channel {
+    let mut channel = Channel::new(stream).with_codec(codec);
+
+    let (handshake, hello) = ClientHandshake::start();
+    channel
+        .send(Packet::new(PacketType::PubKey, hello).to().unwrap())
+        .unwrap();
+
+    let reply = channel.recv().unwrap();
+    let reply: Packet<PacketModels::KeyExchange> =
+        Packet::parse(&reply, "PubKey").unwrap_or_else(|packet| panic!("{}", packet.get_data()));
+
+    channel.set_cipher(handshake.finish(reply.get().1, None).unwrap());
+    channel
+}
+
+pub fn request<T>(channel: &mut Channel, p_type: PacketType, body: T) -> DataPacket
+where
+    T: Serialize + for<'a> Deserialize<'a>,
+{
+    channel
+        .send(Packet::new(p_type, body).to().unwrap())
+        .unwrap();
+
+    channel.recv().unwrap()
+}
+
+pub fn parse<T>(packet: &DataPacket) -> T
+where
+    T: Serialize + for<'a> Deserialize<'a>,
+{
+    assert!(
+        !matches!(packet.get_type(), PacketType::Error),
+        "{}",
+        packet.get_data()
+    );
+
+    let packet: Packet<T> = Packet::parse(packet, "Unexpected Packet")
+        .unwrap_or_else(|packet| panic!("{}", packet.get_data()));
+    packet.get().1
+}
+
+/// Signs `username` up with SRP and logs in, rekeying like the client does.
+pub fn register(channel: &mut Channel, username: &str) -> BaseModels::User {
+    let (me, key) = signup(round_trip(channel), username);
+    channel.rekey(&key).unwrap();
+
+    assert_eq!(me.get_username(), username);
+    me
+}
+
+pub fn srp_login(
+    channel: &mut Channel,
+    username: &str,
+    password: &str,
+) -> Result<BaseModels::User, String> {
+    let (me, key) = login(round_trip(channel), username, password)?;
+    channel.rekey(&key).unwrap();
+
+    Ok(me)
+}
+
+/// Signs `username` up with the password "password" and logs in. `request`
+/// sends one packet and returns the reply; the caller rekeys with the
+/// returned session key.
+pub fn signup(
+    mut request: impl FnMut(DataPacket) -> DataPacket,
+    username: &str,
+) -> (BaseModels::User, [u8; 32]) {
+    let (salt, verifier) = srp::register(username, "password").unwrap();
+    let user = BaseModels::User::full(
+        String::from(username),
+        String::from(username),
+        String::new(),
+    );
+    let registration = PacketModels::SrpRegistration::new(user, salt, verifier);
+
+    let reply = request(
+        Packet::new(PacketType::SrpRegister, registration)
+            .to()
+            .unwrap(),
+    );
+    assert!(
+        matches!(reply.get_type(), PacketType::Ok),
+        "{}",
+        reply.get_data()
+    );
+
+    login(request, username, "password").unwrap()
+}
+
+/// Runs an SRP login through `request`, returning the server's error
+/// message if the proof is refused.
+pub fn login(
+    mut request: impl FnMut(DataPacket) -> DataPacket,
+    username: &str,
+    password: &str,
+) -> Result<(BaseModels::User, [u8; 32]), String> {
+    let (client, start) = SrpClient::start(username);
+
+    let challenge: PacketModels::SrpChallenge = parse(&request(
+        Packet::new(PacketType::SrpStart, start).to().unwrap(),
+    ));
+    let (proof, body) = client.finish(password, challenge).unwrap();
+
+    let reply = request(Packet::new(PacketType::SrpProof, body).to().unwrap());
+    if matches!(reply.get_type(), PacketType::Error) {
+        return Err(reply.get_data());
+    }
+
+    let verify: PacketModels::SrpVerify = parse(&reply);
+    let key = proof.verify(&verify.get_proof()).unwrap();
+
+    Ok((verify.into_user(), key))
+}
+
+fn round_trip(channel: &mut Channel) -> impl FnMut(DataPacket) -> DataPacket + '_ {
+    |packet| {
+        channel.send(packet).unwrap();
+        channel.recv().unwrap()
+    }
+}
@@ -0,0 +1,214 @@
+use std::{env, fs, path::PathBuf};
+
+use server::{database::Backend, log::Level, Config, ConfigError, Limits, TlsConfig};
+use uuid::Uuid;
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| String::from(*arg)).collect()
+}
+
+fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+    vars.iter()
+        .map(|(key, value)| (String::from(*key), String::from(*value)))
+        .collect()
+}
+
+fn invalid(result: Result<Config, ConfigError>) -> String {
+    match result {
+        Err(ConfigError::Invalid(message)) => message,
+        Err(ConfigError::Help) => panic!("unexpected help"),
+        Ok(_) => panic!("expected an invalid config"),
+    }
+}
+
+struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    fn new(contents: &str) -> Self {
+        let path = env::temp_dir().join(format!("secure_chat_{}.toml", Uuid::new_v4()));
+        fs::write(&path, contents).unwrap();
+
+        Self { path }
+    }
+
+    fn path(&self) -> &str {
+        self.path.to_str().unwrap()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[test]
+fn defaults_apply_without_arguments() {
+    let config = Config::parse(args(&[]), vars(&[])).unwrap();
+
+    assert_eq!(config, Config::default());
+    assert_eq!(config.storage, Backend::default());
+    assert_eq!(config.limits, Limits::default());
+}
+
+#[test]
+fn named_flags_are_parsed() {
+    let config = Config::parse(
+        args(&[
+            "--addr",
+            "0.0.0.0:9000",
+            "--websocket",
+            "0.0.0.0:9001",
+            "-w",
+            "16",
+            "--storage=memory",
+            "--max-page-size",
+            "50",
+            "--shutdown-timeout=30",
+            "--log-level",
+            "DEBUG",
+            "--tls-cert",
+            "cert.pem",
+            "--tls-key",
+            "key.pem",
+        ]),
+        vars(&[]),
+    )
+    .unwrap();
+
+    assert_eq!(config.addr, "0.0.0.0:9000");
+    assert_eq!(config.websocket.as_deref(), Some("0.0.0.0:9001"));
+    assert_eq!(config.max_workers, 16);
+    assert_eq!(config.storage, Backend::Memory);
+    assert_eq!(config.limits.max_page_size, 50);
+    assert_eq!(config.limits.shutdown_timeout, 30);
+    assert_eq!(config.log_level, Level::Debug);
+    assert_eq!(
+        config.tls,
+        Some(TlsConfig {
+            cert: PathBuf::from("cert.pem"),
+            key: PathBuf::from("key.pem"),
+        })
+    );
+}
+
+#[test]
+fn file_then_env_then_flags() {
+    let file = TempFile::new(
+        r#"
+        addr = "10.0.0.1:7000"
+        workers = 2
+        storage = "sqlite://chat.db"
+        log_level = "warn"
+
+        [limits]
+        max_frame_size = 4096
+        max_search_results = 5
+        "#,
+    );
+
+    let config = Config::parse(
+        args(&["--config", file.path(), "--workers", "8"]),
+        vars(&[
+            ("SECURE_CHAT_WORKERS", "3"),
+            ("SECURE_CHAT_MAX_SEARCH_RESULTS", "7"),
+            ("UNRELATED", "ignored"),
+        ]),
+    )
+    .unwrap();
+
+    assert_eq!(config.addr, "10.0.0.1:7000");
+    assert_eq!(config.max_workers, 8);
+    assert_eq!(config.storage, Backend::Sqlite(PathBuf::from("chat.db")));
+    assert_eq!(config.log_level, Level::Warn);
+    assert_eq!(config.limits.max_frame_size, 4096);
+    assert_eq!(config.limits.max_search_results, 7);
+}
+
+#[test]
+fn config_path_can_come_from_env() {
+    let file = TempFile::new("storage = \"memory\"\n");
+
+    let config = Config::parse(args(&[]), vars(&[("SECURE_CHAT_CONFIG", file.path())])).unwrap();
+
+    assert_eq!(config.storage, Backend::Memory);
+}
+
+#[test]
+fn invalid_values_name_their_source() {
+    let message = invalid(Config::parse(args(&["--workers", "many"]), vars(&[])));
+    assert!(message.contains("--workers"), "{}", message);
+    assert!(message.contains("many"), "{}", message);
+
+    let message = invalid(Config::parse(
+        args(&[]),
+        vars(&[("SECURE_CHAT_WORKERS", "0")]),
+    ));
+    assert!(message.contains("SECURE_CHAT_WORKERS"), "{}", message);
+
+    let message = invalid(Config::parse(
+        args(&["--storage", "postgres://db"]),
+        vars(&[]),
+    ));
+    assert!(message.contains("storage"), "{}", message);
+
+    let message = invalid(Config::parse(args(&["--log-level", "loud"]), vars(&[])));
+    assert!(message.contains("loud"), "{}", message);
+}
+
+#[test]
+fn client_settings_in_the_environment_are_ignored() {
+    let config = Config::parse(
+        args(&["-s", "memory", "-a", "127.0.0.1:0"]),
+        vars(&[
+            ("SECURE_CHAT_TLS", "1"),
+            ("SECURE_CHAT_TLS_CA", "ca.pem"),
+            ("SECURE_CHAT_HOME", "/tmp/secure_chat"),
+            ("SECURE_CHAT_CODEC", "msgpack"),
+        ]),
+    )
+    .unwrap();
+
+    assert_eq!(config.addr, "127.0.0.1:0");
+    assert_eq!(config.tls, None);
+}
+
+#[test]
+fn invalid_files_are_reported() {
+    let file = TempFile::new("workers = \"four\"\n");
+    let message = invalid(Config::parse(args(&["-c", file.path()]), vars(&[])));
+    assert!(message.contains(file.path()), "{}", message);
+
+    let file = TempFile::new("wrokers = 4\n");
+    let message = invalid(Config::parse(args(&["-c", file.path()]), vars(&[])));
+    assert!(message.contains("wrokers"), "{}", message);
+
+    let message = invalid(Config::parse(
+        args(&["--config", "/nonexistent/server.toml"]),
+        vars(&[]),
+    ));
+    assert!(message.contains("/nonexistent/server.toml"), "{}", message);
+}
+
+#[test]
+fn malformed_arguments_are_rejected() {
+    assert!(invalid(Config::parse(args(&["--workers"]), vars(&[]))).contains("Missing value"));
+    assert!(invalid(Config::parse(args(&["127.0.0.1:80"]), vars(&[]))).contains("Unexpected"));
+    assert!(invalid(Config::parse(args(&["--colour", "red"]), vars(&[]))).contains("Unknown"));
+    assert!(invalid(Config::parse(args(&["--tls-cert", "cert.pem"]), vars(&[]))).contains("key"));
+    assert_eq!(
+        Config::parse(args(&["--help"]), vars(&[])),
+        Err(ConfigError::Help)
+    );
+}
+
+#[test]
+fn example_file_matches_defaults() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/server.example.toml");
+
+    let config = Config::parse(args(&["--config", path]), vars(&[])).unwrap();
+
+    assert_eq!(config, Config::default());
+}
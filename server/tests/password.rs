@@ -0,0 +1,53 @@
+use server::password::{self, Verified};
+
+#[test]
+fn hash_is_argon2id_phc_string() {
+    let stored = password::hash("secret").unwrap();
+
+    assert!(stored.starts_with("$argon2id$"));
+    assert!(!stored.contains("secret"));
+}
+
+#[test]
+fn hashes_use_fresh_salts() {
+    assert_ne!(
+        password::hash("secret").unwrap(),
+        password::hash("secret").unwrap()
+    );
+}
+
+#[test]
+fn verify_hashed_password() {
+    let stored = password::hash("secret").unwrap();
+
+    assert!(matches!(
+        password::verify("secret", &stored),
+        Verified::Valid
+    ));
+    assert!(matches!(
+        password::verify("Secret", &stored),
+        Verified::Invalid
+    ));
+    assert!(matches!(password::verify("", &stored), Verified::Invalid));
+}
+
+#[test]
+fn plaintext_records_are_flagged_for_upgrade() {
+    assert!(matches!(
+        password::verify("secret", "secret"),
+        Verified::Legacy
+    ));
+    assert!(matches!(
+        password::verify("secret", "other"),
+        Verified::Invalid
+    ));
+    assert!(matches!(
+        password::verify("secre", "secret"),
+        Verified::Invalid
+    ));
+}
+
+#[test]
+fn accounts_without_password_never_match() {
+    assert!(matches!(password::verify("", ""), Verified::Invalid));
+}
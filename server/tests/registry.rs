@@ -0,0 +1,135 @@
+use libs::packet::DataPacket;
+use server::registry::{Inbound, Inbox, Registry};
+use tokio::sync::mpsc::Receiver;
+
+fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| String::from(*name)).collect()
+}
+
+fn connect(registry: &Registry, inbox: Inbox) -> u64 {
+    registry
+        .connect(inbox)
+        .unwrap_or_else(|packet| panic!("{}", packet.get_data()))
+}
+
+fn event(inbound: &mut Receiver<Inbound>) -> Option<String> {
+    match inbound.try_recv() {
+        Ok(Inbound::Event(packet)) => Some(packet.get_data()),
+        _ => None,
+    }
+}
+
+#[test]
+fn publish_reaches_every_listener() {
+    let registry = Registry::new();
+
+    let (first, mut first_rx) = Inbox::new();
+    let (second, mut second_rx) = Inbox::new();
+    let (bob, mut bob_rx) = Inbox::new();
+
+    registry.subscribe(String::from("alice"), first);
+    registry.subscribe(String::from("alice"), second);
+    registry.subscribe(String::from("bob"), bob);
+
+    let delivered = registry.publish(
+        &names(&["alice", "carol"]),
+        &DataPacket::ok_message(String::from("event")),
+    );
+
+    assert_eq!(delivered, 2);
+    assert_eq!(event(&mut first_rx).as_deref(), Some("event"));
+    assert_eq!(event(&mut second_rx).as_deref(), Some("event"));
+    assert!(event(&mut bob_rx).is_none());
+}
+
+#[test]
+fn closed_listeners_are_dropped() {
+    let registry = Registry::new();
+
+    let (alice, mut alice_rx) = Inbox::new();
+    registry.subscribe(String::from("alice"), alice);
+
+    let (closed, closed_rx) = Inbox::new();
+    registry.subscribe(String::from("alice"), closed);
+    drop(closed_rx);
+
+    let (bob, bob_rx) = Inbox::new();
+    registry.subscribe(String::from("bob"), bob);
+    drop(bob_rx);
+
+    let packet = DataPacket::ok_message(String::from("event"));
+
+    assert_eq!(registry.publish(&names(&["alice", "bob"]), &packet), 1);
+    assert!(event(&mut alice_rx).is_some());
+    assert!(registry.is_listening("alice"));
+    assert!(!registry.is_listening("bob"));
+}
+
+#[test]
+fn unsubscribed_listener_stops_receiving() {
+    let registry = Registry::new();
+
+    let (alice, mut alice_rx) = Inbox::new();
+    let id = registry.subscribe(String::from("alice"), alice.clone());
+    registry.subscribe(String::from("bob"), alice);
+
+    registry.unsubscribe("alice", id);
+
+    let packet = DataPacket::ok_message(String::from("event"));
+
+    assert_eq!(registry.publish(&names(&["alice"]), &packet), 0);
+    assert!(event(&mut alice_rx).is_none());
+    assert!(!registry.is_listening("alice"));
+    assert!(registry.is_listening("bob"));
+}
+
+#[test]
+fn shutdown_notifies_connections_and_refuses_new_ones() {
+    let registry = Registry::new();
+
+    let (inbox, mut inbound) = Inbox::new();
+    let id = connect(&registry, inbox);
+
+    let (closed, closed_rx) = Inbox::new();
+    connect(&registry, closed);
+    drop(closed_rx);
+
+    assert_eq!(registry.connection_count(), 2);
+    assert!(!registry.is_closing());
+
+    let notified = registry.shutdown(&DataPacket::ok_message(String::from("bye")));
+
+    assert_eq!(notified, 1);
+    assert!(registry.is_closing());
+    assert!(matches!(
+        inbound.try_recv(),
+        Ok(Inbound::Shutdown(packet)) if packet.get_data() == "bye"
+    ));
+
+    let (late, _) = Inbox::new();
+    match registry.connect(late) {
+        Err(notice) => assert_eq!(notice.get_data(), "bye"),
+        Ok(_) => panic!("connection accepted after shutdown"),
+    }
+
+    registry.disconnect(id);
+    assert_eq!(registry.connection_count(), 0);
+}
+
+#[tokio::test]
+async fn full_inboxes_drop_events_and_flag_the_connection() {
+    let registry = Registry::new();
+
+    let (alice, mut alice_rx) = Inbox::with_capacity(1);
+    registry.subscribe(String::from("alice"), alice.clone());
+
+    let packet = DataPacket::ok_message(String::from("event"));
+
+    assert_eq!(registry.publish(&names(&["alice"]), &packet), 1);
+    assert_eq!(registry.publish(&names(&["alice"]), &packet), 0);
+    assert!(!registry.is_listening("alice"));
+
+    alice.lagging().await;
+    assert!(event(&mut alice_rx).is_some());
+    assert!(event(&mut alice_rx).is_none());
+}
@@ -0,0 +1,516 @@
+use std::{
+    env, fs,
+    net::{TcpListener, TcpStream},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use libs::{
+    crypto::srp::{self, SrpClient},
+    packet::{Codec, PacketType},
+    packet_manager::Channel,
+    BaseModels, PacketModels,
+};
+use server::{
+    database::{Backend, SqliteStorage},
+    password, Config, Limits, Listener, Shutdown,
+};
+
+mod common;
+
+use common::{connect, open, open_with, parse, register, request, srp_login};
+
+struct Server {
+    addr: String,
+    shutdown: Shutdown,
+    handle: JoinHandle<Result<(), String>>,
+}
+
+fn start() -> Server {
+    start_with(Limits::default())
+}
+
+fn start_with(limits: Limits) -> Server {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+
+    let (shutdown, handle) = spawn(listener, limits);
+
+    Server {
+        addr,
+        shutdown,
+        handle,
+    }
+}
+
+fn spawn(
+    listener: impl Into<Listener>,
+    limits: Limits,
+) -> (Shutdown, JoinHandle<Result<(), String>>) {
+    let config = Config {
+        storage: Backend::Memory,
+        limits,
+        ..Config::default()
+    };
+
+    common::spawn(vec![listener.into()], config)
+}
+
+#[test]
+fn requests_require_key_exchange() {
+    let addr = start().addr;
+
+    let mut channel = Channel::new(TcpStream::connect(&addr).unwrap());
+    let reply = request(&mut channel, PacketType::GetChats, PacketModels::Empty {});
+
+    assert!(matches!(reply.get_type(), PacketType::Error));
+    assert_eq!(reply.get_data(), "Key Exchange Required");
+}
+
+#[test]
+fn reply_types_are_refused_as_requests() {
+    let mut channel = connect(&start().addr);
+
+    for p_type in [PacketType::Ok, PacketType::Empty] {
+        let reply = request(&mut channel, p_type, PacketModels::Empty {});
+        assert_eq!(reply.get_data(), "Packet Type Error");
+    }
+
+    // the connection survives
+    let reply = request(&mut channel, PacketType::GetChats, PacketModels::Empty {});
+    assert_eq!(reply.get_data(), "Login Required");
+}
+
+#[test]
+fn plaintext_registration_is_refused() {
+    let mut channel = connect(&start().addr);
+
+    let reply = request(
+        &mut channel,
+        PacketType::Register,
+        BaseModels::User::full(
+            String::from("alice"),
+            String::from("alice"),
+            String::from("password"),
+        ),
+    );
+    assert!(matches!(reply.get_type(), PacketType::Error));
+}
+
+#[test]
+fn legacy_passwords_migrate_to_srp_once() {
+    let path = env::temp_dir().join(format!("secure_chat_{}.db", uuid::Uuid::new_v4()));
+
+    // accounts from before SRP only have a password hash, and nothing
+    // creates those any more
+    drop(SqliteStorage::new(&path).unwrap());
+    rusqlite::Connection::open(&path)
+        .unwrap()
+        .execute(
+            "INSERT INTO users (username, name, password) VALUES ('alice', 'alice', ?1)",
+            [password::hash("password").unwrap()],
+        )
+        .unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let config = Config {
+        storage: Backend::Sqlite(path.clone()),
+        ..Config::default()
+    };
+    let shutdown = Shutdown::new();
+    let trigger = shutdown.clone();
+    let handle = thread::spawn(move || server::serve(Listener::from(listener), config, trigger));
+
+    let mut channel = connect(&addr);
+
+    let legacy = srp_login(&mut channel, "alice", "password").err().unwrap();
+    assert_eq!(legacy, "Password Migration Required");
+    let unknown = srp_login(&mut channel, "nobody", "password").err().unwrap();
+    assert_eq!(unknown, "Invalid Username or Password");
+
+    let migrate = |channel: &mut Channel, password: &str| {
+        request(
+            channel,
+            PacketType::Login,
+            BaseModels::User::simple(String::from("alice"), String::from(password)),
+        )
+    };
+
+    let reply = migrate(&mut channel, "wrong");
+    assert!(matches!(reply.get_type(), PacketType::Error));
+
+    let reply = migrate(&mut channel, "password");
+    assert!(matches!(reply.get_type(), PacketType::Ok));
+
+    // migrating doesn't log the connection in
+    let reply = request(&mut channel, PacketType::GetChats, PacketModels::Empty {});
+    assert_eq!(reply.get_data(), "Login Required");
+
+    let me = srp_login(&mut channel, "alice", "password").unwrap();
+    assert_eq!(me.get_username(), "alice");
+
+    let reply = migrate(&mut channel, "password");
+    assert_eq!(reply.get_data(), "Invalid Username or Password");
+
+    shutdown.trigger();
+    assert_eq!(handle.join().unwrap(), Ok(()));
+
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = fs::remove_file(format!("{}{}", path.display(), suffix));
+    }
+}
+
+#[test]
+fn changing_the_verifier_needs_a_fresh_password_proof() {
+    let addr = start().addr;
+
+    let mut alice = connect(&addr);
+    register(&mut alice, "alice");
+
+    let change = |channel: &mut Channel, password: &str| {
+        let (salt, verifier) = srp::register("alice", password).unwrap();
+
+        request(
+            channel,
+            PacketType::SrpRegister,
+            PacketModels::SrpRegistration::new(
+                BaseModels::User::simple(String::from("alice"), String::new()),
+                salt,
+                verifier,
+            ),
+        )
+    };
+
+    let reply = change(&mut alice, "changed");
+    assert!(matches!(reply.get_type(), PacketType::Ok));
+
+    // each proof covers one change
+    let reply = change(&mut alice, "again");
+    assert_eq!(reply.get_data(), "Password Proof Required");
+
+    let mut bob = connect(&addr);
+    let (client, start) = SrpClient::start("alice");
+    let challenge: PacketModels::SrpChallenge =
+        parse(&request(&mut bob, PacketType::SrpStart, start));
+    let (proof, body) = client.finish("changed", challenge).unwrap();
+    let mut verify: PacketModels::SrpVerify = parse(&request(&mut bob, PacketType::SrpProof, body));
+    bob.rekey(&proof.verify(&verify.get_proof()).unwrap())
+        .unwrap();
+    let token = verify.take_token().unwrap();
+
+    // a session restored from a token never proved the password
+    let mut thief = connect(&addr);
+    let me: BaseModels::User = parse(&request(&mut thief, PacketType::Resume, token));
+    assert_eq!(me.get_username(), "alice");
+
+    let reply = change(&mut thief, "stolen");
+    assert_eq!(reply.get_data(), "Password Proof Required");
+}
+
+#[test]
+fn tokens_resume_until_the_resumed_session_logs_out() {
+    let addr = start().addr;
+
+    let mut alice = connect(&addr);
+    register(&mut alice, "alice");
+
+    let (client, start) = SrpClient::start("alice");
+    let challenge: PacketModels::SrpChallenge =
+        parse(&request(&mut alice, PacketType::SrpStart, start));
+    let (proof, body) = client.finish("password", challenge).unwrap();
+    let mut verify: PacketModels::SrpVerify =
+        parse(&request(&mut alice, PacketType::SrpProof, body));
+    alice
+        .rekey(&proof.verify(&verify.get_proof()).unwrap())
+        .unwrap();
+    let token = verify.take_token().unwrap();
+
+    let mut resumed = connect(&addr);
+    let me: BaseModels::User = parse(&request(&mut resumed, PacketType::Resume, token.clone()));
+    assert_eq!(me.get_username(), "alice");
+
+    let reply = request(&mut resumed, PacketType::Logout, PacketModels::Empty {});
+    assert!(matches!(reply.get_type(), PacketType::Ok));
+
+    let reply = request(&mut connect(&addr), PacketType::Resume, token);
+    assert_eq!(reply.get_data(), "Session Token Revoked");
+}
+
+#[test]
+fn group_conversation_runs_on_memory_storage() {
+    let addr = start().addr;
+
+    let mut alice = connect(&addr);
+    let mut bob = connect(&addr);
+
+    register(&mut alice, "alice");
+    register(&mut bob, "bob");
+
+    let group: BaseModels::Group = parse(&request(
+        &mut alice,
+        PacketType::CreateGroup,
+        BaseModels::Group::new(String::from("team")),
+    ));
+
+    let group: BaseModels::Group = parse(&request(
+        &mut alice,
+        PacketType::AddUser,
+        BaseModels::Member::new(
+            group.clone(),
+            BaseModels::User::simple(String::from("bob"), String::new()),
+        ),
+    ));
+    assert!(group.is_member("bob"));
+
+    let member = BaseModels::Member::new(
+        group.clone(),
+        BaseModels::User::simple(String::from("bob"), String::new()),
+    );
+    let sent: BaseModels::Message = parse(&request(
+        &mut bob,
+        PacketType::CreateMessage,
+        BaseModels::Message::new(member, String::from("hello")),
+    ));
+    assert_eq!(sent.get_member().get_user().get_username(), "bob");
+
+    let chats: PacketModels::Chats = parse(&request(
+        &mut alice,
+        PacketType::GetChats,
+        PacketModels::Empty {},
+    ));
+    assert_eq!(chats.get_unread(group.get_id()), 1);
+
+    let page: PacketModels::Messages = parse(&request(
+        &mut alice,
+        PacketType::GetMessages,
+        PacketModels::GetMessages::new(group.get_id(), None, 0),
+    ));
+    assert_eq!(page.get_next_cursor(), None);
+
+    let messages = page.get();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].get_body(), "hello");
+    assert_eq!(messages[0].get_id(), sent.get_id());
+}
+
+#[test]
+fn message_pack_and_json_clients_share_a_group() {
+    let addr = start().addr;
+
+    let mut alice = open_with(TcpStream::connect(&addr).unwrap(), Codec::MessagePack);
+    let mut bob = connect(&addr);
+
+    register(&mut alice, "alice");
+    register(&mut bob, "bob");
+
+    let group: BaseModels::Group = parse(&request(
+        &mut alice,
+        PacketType::CreateGroup,
+        BaseModels::Group::new(String::from("codecs")),
+    ));
+
+    let group: BaseModels::Group = parse(&request(
+        &mut alice,
+        PacketType::AddUser,
+        BaseModels::Member::new(
+            group,
+            BaseModels::User::simple(String::from("bob"), String::new()),
+        ),
+    ));
+
+    let reply = request(&mut alice, PacketType::Listen, PacketModels::Empty {});
+    assert!(matches!(reply.get_type(), PacketType::Ok));
+    assert_eq!(reply.get_codec(), Codec::MessagePack);
+
+    let member = BaseModels::Member::new(
+        group,
+        BaseModels::User::simple(String::from("bob"), String::new()),
+    );
+    let reply = request(
+        &mut bob,
+        PacketType::CreateMessage,
+        BaseModels::Message::new(member, String::from("\"quoted\" hello")),
+    );
+    assert_eq!(reply.get_codec(), Codec::Json);
+    let sent: BaseModels::Message = parse(&reply);
+
+    let event = alice.recv().unwrap();
+    assert!(event.is_event());
+    assert_eq!(event.get_codec(), Codec::MessagePack);
+
+    let received: BaseModels::Message = parse(&event);
+    assert_eq!(received.get_id(), sent.get_id());
+    assert_eq!(received.get_body(), "\"quoted\" hello");
+}
+
+#[test]
+fn removed_members_are_told_and_lose_access() {
+    let addr = start().addr;
+
+    let mut alice = connect(&addr);
+    let mut bob = connect(&addr);
+
+    register(&mut alice, "alice");
+    register(&mut bob, "bob");
+
+    let group: BaseModels::Group = parse(&request(
+        &mut alice,
+        PacketType::CreateGroup,
+        BaseModels::Group::new(String::from("team")),
+    ));
+
+    let bob_member = BaseModels::Member::new(
+        group.clone(),
+        BaseModels::User::simple(String::from("bob"), String::new()),
+    );
+
+    let reply = request(&mut alice, PacketType::AddUser, bob_member.clone());
+    assert!(matches!(reply.get_type(), PacketType::AddUser));
+
+    let reply = request(&mut bob, PacketType::Listen, PacketModels::Empty {});
+    assert!(matches!(reply.get_type(), PacketType::Ok));
+
+    let group: BaseModels::Group = parse(&request(
+        &mut alice,
+        PacketType::RemoveUser,
+        bob_member.clone(),
+    ));
+    assert_eq!(group.get_members(), vec![String::from("alice")]);
+
+    let event = bob.recv().unwrap();
+    assert!(matches!(event.get_type(), PacketType::Refresh));
+    let refresh: PacketModels::Refresh = parse(&event);
+    assert_eq!(refresh.get_id(), group.get_id());
+
+    let reply = request(
+        &mut bob,
+        PacketType::GetMessages,
+        PacketModels::GetMessages::new(group.get_id(), None, 0),
+    );
+    assert!(matches!(reply.get_type(), PacketType::Error));
+
+    let reply = request(&mut bob, PacketType::RemoveUser, bob_member);
+    assert!(matches!(reply.get_type(), PacketType::Error));
+}
+
+#[test]
+fn shutdown_notifies_clients_and_stops_accepting() {
+    let server = start_with(Limits {
+        shutdown_timeout: 2,
+        ..Limits::default()
+    });
+
+    let mut alice = connect(&server.addr);
+    register(&mut alice, "alice");
+
+    server.shutdown.trigger();
+
+    let notice = alice.recv().unwrap();
+    assert!(notice.is_event());
+
+    let notice: PacketModels::Shutdown = parse(&notice);
+    assert_eq!(notice.get_grace_period(), 2);
+    assert!(!notice.get_reason().is_empty());
+
+    assert!(alice.recv().is_err());
+    assert_eq!(server.handle.join().unwrap(), Ok(()));
+
+    let refused = TcpStream::connect(&server.addr);
+    assert!(refused.is_err());
+}
+
+#[test]
+fn shutdown_closes_idle_connections_before_the_handshake() {
+    let server = start_with(Limits {
+        shutdown_timeout: 1,
+        ..Limits::default()
+    });
+
+    let mut idle = Channel::new(TcpStream::connect(&server.addr).unwrap());
+    thread::sleep(Duration::from_millis(100));
+
+    server.shutdown.trigger();
+
+    let notice = idle.recv().unwrap();
+    assert!(matches!(notice.get_type(), PacketType::Shutdown));
+    assert_eq!(server.handle.join().unwrap(), Ok(()));
+}
+
+#[test]
+fn idle_listeners_do_not_hold_workers() {
+    let (connector, listener) = Listener::pipe();
+
+    let config = Config {
+        storage: Backend::Memory,
+        max_workers: 1,
+        ..Config::default()
+    };
+
+    let shutdown = Shutdown::new();
+    let trigger = shutdown.clone();
+    let handle = thread::spawn(move || server::serve(listener, config, trigger));
+
+    let idle: Vec<Channel> = (0..200)
+        .map(|_| open(connector.connect().unwrap()))
+        .collect();
+
+    let mut alice = open(connector.connect().unwrap());
+    register(&mut alice, "alice");
+
+    let reply = request(&mut alice, PacketType::Listen, PacketModels::Empty {});
+    assert!(!matches!(reply.get_type(), PacketType::Error));
+
+    shutdown.trigger();
+    assert_eq!(handle.join().unwrap(), Ok(()));
+    drop(idle);
+}
+
+#[test]
+fn in_process_pipes_serve_requests() {
+    let (connector, listener) = Listener::pipe();
+    let (shutdown, handle) = spawn(listener, Limits::default());
+
+    let mut alice = open(connector.connect().unwrap());
+    let mut bob = open(connector.connect().unwrap());
+
+    register(&mut alice, "alice");
+    register(&mut bob, "bob");
+
+    let group: BaseModels::Group = parse(&request(
+        &mut alice,
+        PacketType::CreateGroup,
+        BaseModels::Group::new(String::from("pipes")),
+    ));
+
+    let group: BaseModels::Group = parse(&request(
+        &mut alice,
+        PacketType::AddUser,
+        BaseModels::Member::new(
+            group,
+            BaseModels::User::simple(String::from("bob"), String::new()),
+        ),
+    ));
+    assert!(group.is_member("bob"));
+
+    shutdown.trigger();
+    assert_eq!(handle.join().unwrap(), Ok(()));
+    assert!(connector.connect().is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn unix_socket_listener_serves_requests() {
+    use std::{env, os::unix::net::UnixStream};
+
+    let path = env::temp_dir().join(format!("secure_chat_{}.sock", uuid::Uuid::new_v4()));
+    let listener = Listener::bind(&format!("unix:{}", path.display())).unwrap();
+    let (shutdown, handle) = spawn(listener, Limits::default());
+
+    let mut alice = open(UnixStream::connect(&path).unwrap());
+    register(&mut alice, "alice");
+
+    shutdown.trigger();
+    assert_eq!(handle.join().unwrap(), Ok(()));
+    assert!(!path.exists());
+}
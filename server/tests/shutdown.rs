@@ -0,0 +1,28 @@
+use std::time::Duration;
+
+use server::Shutdown;
+use tokio::time;
+
+#[tokio::test]
+async fn triggered_wakes_every_clone() {
+    let shutdown = Shutdown::new();
+
+    let waiting = tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move { shutdown.triggered().await }
+    });
+
+    assert!(!shutdown.is_triggered());
+    shutdown.trigger();
+
+    time::timeout(Duration::from_secs(1), waiting)
+        .await
+        .unwrap()
+        .unwrap();
+
+    // waiting after the fact resolves straight away
+    time::timeout(Duration::from_secs(1), shutdown.triggered())
+        .await
+        .unwrap();
+    assert!(shutdown.is_triggered());
+}
@@ -0,0 +1,355 @@
+use std::{env, fs, path::PathBuf, sync::Arc};
+
+use libs::{BaseModels, PacketModels};
+use rusqlite::Connection;
+use server::database::{Backend, GroupError, SqliteStorage, Storage};
+use uuid::Uuid;
+
+struct TempDb {
+    path: PathBuf,
+}
+
+impl TempDb {
+    fn new() -> Self {
+        Self {
+            path: env::temp_dir().join(format!("secure_chat_{}.db", Uuid::new_v4())),
+        }
+    }
+
+    fn open(&self) -> SqliteStorage {
+        SqliteStorage::new(&self.path).unwrap()
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", self.path.display(), suffix));
+        }
+    }
+}
+
+fn registration(username: &str) -> PacketModels::SrpRegistration {
+    PacketModels::SrpRegistration::new(
+        BaseModels::User::full(
+            String::from(username),
+            String::from(username),
+            String::new(),
+        ),
+        String::from("salt"),
+        String::from("verifier"),
+    )
+}
+
+async fn user(storage: &SqliteStorage, username: &str) {
+    storage
+        .create_srp_user(registration(username))
+        .await
+        .unwrap();
+}
+
+fn message(group: &BaseModels::Group, body: &str) -> BaseModels::Message {
+    let member = BaseModels::Member::new(
+        group.clone(),
+        BaseModels::User::simple(group.get_owner(), String::new()),
+    );
+
+    BaseModels::Message::new(member, String::from(body)).with_id(Uuid::new_v4())
+}
+
+fn bodies(page: PacketModels::Messages) -> Vec<String> {
+    page.get().iter().map(|m| m.get_body()).collect()
+}
+
+#[test]
+fn schema_migrates_once_in_wal_mode() {
+    let db = TempDb::new();
+
+    assert_eq!(db.open().schema_version().unwrap(), 1);
+    assert_eq!(db.open().schema_version().unwrap(), 1);
+
+    let conn = Connection::open(&db.path).unwrap();
+    let mode: String = conn
+        .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(mode, "wal");
+
+    let index: String = conn
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE name = 'messages_by_group_time'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert!(index.contains("group_id, created_at"));
+}
+
+#[test]
+fn newer_schema_is_rejected() {
+    let db = TempDb::new();
+    db.open();
+
+    let conn = Connection::open(&db.path).unwrap();
+    conn.pragma_update(None, "user_version", 99).unwrap();
+
+    assert!(SqliteStorage::new(&db.path).is_err());
+}
+
+#[tokio::test]
+async fn data_survives_reopen() {
+    let db = TempDb::new();
+
+    let id = {
+        let storage = db.open();
+        user(&storage, "alice").await;
+        storage
+            .set_server_key(String::from("identity"))
+            .await
+            .unwrap();
+
+        let group = storage
+            .create_group(String::from("alice"), String::from("team"))
+            .await
+            .unwrap();
+        storage
+            .create_message(&message(&group, "hello"))
+            .await
+            .unwrap();
+
+        group.get_id()
+    };
+
+    let storage = db.open();
+
+    assert_eq!(
+        storage.get_server_key().await.unwrap().as_deref(),
+        Some("identity")
+    );
+    assert_eq!(
+        storage
+            .get_user(String::from("alice"))
+            .await
+            .unwrap()
+            .get_name(),
+        "alice"
+    );
+
+    let group = storage.get_group(id).await.unwrap();
+    assert_eq!(group.get_admins(), vec![String::from("alice")]);
+    assert_eq!(
+        bodies(storage.get_messages(group, None, 10).await.unwrap()),
+        vec!["hello"]
+    );
+}
+
+#[tokio::test]
+async fn users_sessions_and_srp() {
+    let db = TempDb::new();
+    let storage = db.open();
+
+    user(&storage, "alice").await;
+    user(&storage, "alicia").await;
+    user(&storage, "bob").await;
+
+    assert!(storage.create_srp_user(registration("bob")).await.is_err());
+    assert_eq!(
+        storage.get_srp(String::from("bob")).await.unwrap(),
+        Some((String::from("salt"), String::from("verifier")))
+    );
+    assert_eq!(
+        storage.search_users(String::from("ali"), 20).await.unwrap(),
+        vec![String::from("alice"), String::from("alicia")]
+    );
+
+    storage
+        .create_session(String::from("a"), String::from("alice"), 60)
+        .await
+        .unwrap();
+    assert!(storage
+        .is_session_active(String::from("a"), String::from("alice"))
+        .await
+        .unwrap());
+
+    storage
+        .set_srp(
+            String::from("alice"),
+            String::from("new salt"),
+            String::from("new verifier"),
+        )
+        .await
+        .unwrap();
+
+    assert!(!storage
+        .is_session_active(String::from("a"), String::from("alice"))
+        .await
+        .unwrap());
+    assert_eq!(
+        storage.get_srp(String::from("alice")).await.unwrap(),
+        Some((String::from("new salt"), String::from("new verifier")))
+    );
+    assert_eq!(storage.get_srp(String::from("carol")).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn groups_enforce_membership_rules() {
+    let db = TempDb::new();
+    let storage = db.open();
+
+    user(&storage, "alice").await;
+    user(&storage, "bob").await;
+
+    let id = storage
+        .create_group(String::from("alice"), String::from("team"))
+        .await
+        .unwrap()
+        .get_id();
+
+    assert!(matches!(
+        storage
+            .add_member(id, String::from("bob"), String::from("bob"))
+            .await,
+        Err(GroupError::PermissionDenied)
+    ));
+    assert!(matches!(
+        storage
+            .add_member(id, String::from("alice"), String::from("carol"))
+            .await,
+        Err(GroupError::UnknownUser(_))
+    ));
+
+    storage
+        .add_member(id, String::from("alice"), String::from("bob"))
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        storage
+            .add_member(id, String::from("alice"), String::from("bob"))
+            .await,
+        Err(GroupError::AlreadyMember(_))
+    ));
+    assert!(storage
+        .is_group_member(id, String::from("bob"))
+        .await
+        .unwrap());
+    assert!(matches!(
+        storage.get_group(Uuid::new_v4()).await,
+        Err(GroupError::NotFound)
+    ));
+
+    assert!(matches!(
+        storage
+            .remove_member(id, String::from("bob"), String::from("alice"))
+            .await,
+        Err(GroupError::PermissionDenied)
+    ));
+
+    storage
+        .remove_member(id, String::from("bob"), String::from("bob"))
+        .await
+        .unwrap();
+
+    assert!(!storage
+        .is_group_member(id, String::from("bob"))
+        .await
+        .unwrap());
+    assert!(matches!(
+        storage
+            .remove_member(id, String::from("alice"), String::from("bob"))
+            .await,
+        Err(GroupError::NotMember(_))
+    ));
+}
+
+#[tokio::test]
+async fn messages_page_by_time_and_track_unread() {
+    let db = TempDb::new();
+    let storage = db.open();
+
+    user(&storage, "alice").await;
+
+    let group = storage
+        .create_group(String::from("alice"), String::from("team"))
+        .await
+        .unwrap();
+    let id = group.get_id();
+
+    for i in 0..5 {
+        storage
+            .create_message(&message(&group, &i.to_string()))
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(
+        storage
+            .get_chats(String::from("alice"))
+            .await
+            .unwrap()
+            .get_unread(id),
+        5
+    );
+
+    let page = storage.get_messages(group.clone(), None, 2).await.unwrap();
+    let cursor = page.get_next_cursor();
+    assert!(cursor.is_some());
+    assert_eq!(bodies(page), vec!["3", "4"]);
+
+    // messages arriving between pages don't shift older ones
+    storage.create_message(&message(&group, "5")).await.unwrap();
+
+    let page = storage
+        .get_messages(group.clone(), cursor, 2)
+        .await
+        .unwrap();
+    let cursor = page.get_next_cursor();
+    assert!(cursor.is_some());
+    assert_eq!(bodies(page), vec!["1", "2"]);
+
+    let page = storage
+        .get_messages(group.clone(), cursor, 10)
+        .await
+        .unwrap();
+    assert_eq!(page.get_next_cursor(), None);
+    assert_eq!(bodies(page), vec!["0"]);
+
+    storage.mark_read(id, String::from("alice")).await.unwrap();
+
+    let chats = storage.get_chats(String::from("alice")).await.unwrap();
+    assert!(!chats.is_new());
+    assert_eq!(chats.get_unread(id), 0);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_workers_share_the_database() {
+    let db = TempDb::new();
+    let storage: Arc<dyn Storage> = Backend::Sqlite(db.path.clone()).open().unwrap();
+
+    let group = storage
+        .create_group(String::from("alice"), String::from("team"))
+        .await
+        .unwrap();
+
+    let workers: Vec<_> = (0..4)
+        .map(|worker| {
+            let storage = Arc::clone(&storage);
+            let group = group.clone();
+
+            tokio::spawn(async move {
+                for i in 0..10 {
+                    storage
+                        .create_message(&message(&group, &format!("{}-{}", worker, i)))
+                        .await
+                        .unwrap();
+                }
+            })
+        })
+        .collect();
+
+    for worker in workers {
+        worker.await.unwrap();
+    }
+
+    let page = storage.get_messages(group, None, 100).await.unwrap();
+    assert_eq!(page.get().len(), 40);
+}
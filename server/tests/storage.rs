@@ -0,0 +1,353 @@
+use std::path::PathBuf;
+
+use libs::{BaseModels, PacketModels};
+use server::database::{Backend, GroupError, MemoryStorage, Storage};
+use uuid::Uuid;
+
+fn registration(username: &str) -> PacketModels::SrpRegistration {
+    PacketModels::SrpRegistration::new(
+        BaseModels::User::full(
+            String::from(username),
+            String::from(username),
+            String::new(),
+        ),
+        String::from("salt"),
+        String::from("verifier"),
+    )
+}
+
+async fn user(storage: &MemoryStorage, username: &str) {
+    storage
+        .create_srp_user(registration(username))
+        .await
+        .unwrap();
+}
+
+fn message(group: &BaseModels::Group, body: &str) -> BaseModels::Message {
+    let member = BaseModels::Member::new(
+        group.clone(),
+        BaseModels::User::simple(group.get_owner(), String::new()),
+    );
+
+    BaseModels::Message::new(member, String::from(body)).with_id(Uuid::new_v4())
+}
+
+fn prekey(id: u32) -> PacketModels::PreKey {
+    PacketModels::PreKey::new(id, format!("prekey-{}", id))
+}
+
+fn bundle(identity: &str, prekeys: Vec<PacketModels::PreKey>) -> PacketModels::KeyBundle {
+    PacketModels::KeyBundle::new(
+        String::from(identity),
+        String::from("signing"),
+        prekey(0),
+        String::from("signature"),
+        prekeys,
+    )
+}
+
+#[tokio::test]
+async fn users_are_unique() {
+    let storage = MemoryStorage::new();
+
+    user(&storage, "alice").await;
+
+    assert!(storage
+        .create_srp_user(registration("alice"))
+        .await
+        .is_err());
+    assert!(storage.get_user(String::from("alice")).await.is_ok());
+    assert!(storage.get_user(String::from("bob")).await.is_err());
+}
+
+#[tokio::test]
+async fn usernames_are_validated_on_registration() {
+    let storage = MemoryStorage::new();
+
+    let register = |username: &str| storage.create_srp_user(registration(username));
+
+    for username in [
+        "",
+        "..",
+        "../alice",
+        "a/b",
+        "group:1",
+        ".hidden",
+        &"a".repeat(33),
+    ] {
+        assert!(register(username).await.is_err(), "{:?}", username);
+    }
+
+    for username in ["alice", "bob.smith", "carol_2", "dave-x"] {
+        register(username).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn search_matches_username_prefix() {
+    let storage = MemoryStorage::new();
+
+    for username in ["alice", "alicia", "bob", "al"] {
+        user(&storage, username).await;
+    }
+
+    let found = storage.search_users(String::from("ali"), 20).await.unwrap();
+    assert_eq!(found, vec![String::from("alice"), String::from("alicia")]);
+
+    let limited = storage.search_users(String::from("al"), 2).await.unwrap();
+    assert_eq!(limited.len(), 2);
+}
+
+#[tokio::test]
+async fn sessions_are_revoked_by_srp_reset() {
+    let storage = MemoryStorage::new();
+
+    user(&storage, "alice").await;
+
+    storage
+        .create_session(String::from("a"), String::from("alice"), 60)
+        .await
+        .unwrap();
+    storage
+        .create_session(String::from("b"), String::from("alice"), 60)
+        .await
+        .unwrap();
+
+    assert!(storage
+        .is_session_active(String::from("a"), String::from("alice"))
+        .await
+        .unwrap());
+    assert!(!storage
+        .is_session_active(String::from("a"), String::from("bob"))
+        .await
+        .unwrap());
+
+    storage
+        .revoke_session(String::from("a"), String::from("alice"))
+        .await
+        .unwrap();
+    assert!(!storage
+        .is_session_active(String::from("a"), String::from("alice"))
+        .await
+        .unwrap());
+
+    storage
+        .set_srp(
+            String::from("alice"),
+            String::from("salt"),
+            String::from("verifier"),
+        )
+        .await
+        .unwrap();
+
+    assert!(!storage
+        .is_session_active(String::from("b"), String::from("alice"))
+        .await
+        .unwrap());
+    assert_eq!(
+        storage.get_srp(String::from("alice")).await.unwrap(),
+        Some((String::from("salt"), String::from("verifier")))
+    );
+}
+
+#[tokio::test]
+async fn prekeys_are_consumed_and_reset_on_rotation() {
+    let storage = MemoryStorage::new();
+
+    storage
+        .set_key_bundle(String::from("alice"), bundle("first", vec![prekey(1)]))
+        .await
+        .unwrap();
+
+    let taken = storage
+        .take_key_bundle(String::from("alice"))
+        .await
+        .unwrap();
+    assert_eq!(taken.get_identity_key(), "first");
+    assert!(taken.get_one_time_prekey().is_some());
+
+    let taken = storage
+        .take_key_bundle(String::from("alice"))
+        .await
+        .unwrap();
+    assert!(taken.get_one_time_prekey().is_none());
+
+    storage
+        .set_key_bundle(String::from("alice"), bundle("first", vec![prekey(2)]))
+        .await
+        .unwrap();
+    storage
+        .set_key_bundle(String::from("alice"), bundle("second", vec![prekey(3)]))
+        .await
+        .unwrap();
+
+    let taken = storage
+        .take_key_bundle(String::from("alice"))
+        .await
+        .unwrap();
+    assert_eq!(taken.get_one_time_prekey().unwrap().get_id(), 3);
+
+    assert!(storage.take_key_bundle(String::from("bob")).await.is_err());
+}
+
+#[tokio::test]
+async fn only_admins_add_known_members() {
+    let storage = MemoryStorage::new();
+
+    user(&storage, "alice").await;
+    user(&storage, "bob").await;
+
+    let group = storage
+        .create_group(String::from("alice"), String::from("team"))
+        .await
+        .unwrap();
+    let id = group.get_id();
+
+    assert!(matches!(
+        storage
+            .add_member(id, String::from("bob"), String::from("bob"))
+            .await,
+        Err(GroupError::PermissionDenied)
+    ));
+    assert!(matches!(
+        storage
+            .add_member(id, String::from("alice"), String::from("carol"))
+            .await,
+        Err(GroupError::UnknownUser(_))
+    ));
+
+    let group = storage
+        .add_member(id, String::from("alice"), String::from("bob"))
+        .await
+        .unwrap();
+    assert!(group.is_member("bob"));
+    assert!(!group.is_admin("bob"));
+
+    assert!(matches!(
+        storage
+            .add_member(id, String::from("alice"), String::from("bob"))
+            .await,
+        Err(GroupError::AlreadyMember(_))
+    ));
+    assert!(matches!(
+        storage.get_group(Uuid::new_v4()).await,
+        Err(GroupError::NotFound)
+    ));
+}
+
+#[tokio::test]
+async fn members_leave_and_admins_remove_anyone_but_the_owner() {
+    let storage = MemoryStorage::new();
+
+    user(&storage, "alice").await;
+    user(&storage, "bob").await;
+    user(&storage, "carol").await;
+
+    let id = storage
+        .create_group(String::from("alice"), String::from("team"))
+        .await
+        .unwrap()
+        .get_id();
+    for member in ["bob", "carol"] {
+        storage
+            .add_member(id, String::from("alice"), String::from(member))
+            .await
+            .unwrap();
+    }
+
+    assert!(matches!(
+        storage
+            .remove_member(id, String::from("bob"), String::from("carol"))
+            .await,
+        Err(GroupError::PermissionDenied)
+    ));
+    assert!(matches!(
+        storage
+            .remove_member(id, String::from("alice"), String::from("alice"))
+            .await,
+        Err(GroupError::PermissionDenied)
+    ));
+
+    let group = storage
+        .remove_member(id, String::from("bob"), String::from("bob"))
+        .await
+        .unwrap();
+    assert!(!group.is_member("bob"));
+    assert!(storage
+        .get_chats(String::from("bob"))
+        .await
+        .unwrap()
+        .get_groups()
+        .is_empty());
+
+    let group = storage
+        .remove_member(id, String::from("alice"), String::from("carol"))
+        .await
+        .unwrap();
+    assert_eq!(group.get_members(), vec![String::from("alice")]);
+
+    assert!(matches!(
+        storage
+            .remove_member(id, String::from("alice"), String::from("carol"))
+            .await,
+        Err(GroupError::NotMember(_))
+    ));
+}
+
+#[tokio::test]
+async fn messages_page_backwards_and_track_unread() {
+    let storage = MemoryStorage::new();
+
+    user(&storage, "alice").await;
+
+    let group = storage
+        .create_group(String::from("alice"), String::from("team"))
+        .await
+        .unwrap();
+    let id = group.get_id();
+
+    for i in 0..5 {
+        storage
+            .create_message(&message(&group, &i.to_string()))
+            .await
+            .unwrap();
+    }
+
+    let chats = storage.get_chats(String::from("alice")).await.unwrap();
+    assert!(chats.is_new());
+    assert_eq!(chats.get_unread(id), 5);
+
+    let page = storage.get_messages(group.clone(), None, 2).await.unwrap();
+    assert_eq!(page.get_next_cursor(), Some(3));
+    let bodies: Vec<String> = page.get().iter().map(|m| m.get_body()).collect();
+    assert_eq!(bodies, vec!["3", "4"]);
+
+    let page = storage
+        .get_messages(group.clone(), Some(3), 10)
+        .await
+        .unwrap();
+    assert_eq!(page.get_next_cursor(), None);
+    assert_eq!(page.get().len(), 3);
+
+    storage.mark_read(id, String::from("alice")).await.unwrap();
+
+    let chats = storage.get_chats(String::from("alice")).await.unwrap();
+    assert!(!chats.is_new());
+    assert_eq!(chats.get_unread(id), 0);
+}
+
+#[test]
+fn backend_names_parse() {
+    assert_eq!(Backend::parse("memory"), Ok(Backend::Memory));
+    assert_eq!(
+        Backend::parse("redis://cache:6379/"),
+        Ok(Backend::Redis(String::from("redis://cache:6379/")))
+    );
+    assert_eq!(Backend::parse("redis"), Ok(Backend::default()));
+    assert_eq!(
+        Backend::parse("sqlite://data/chat.db"),
+        Ok(Backend::Sqlite(PathBuf::from("data/chat.db")))
+    );
+    assert!(Backend::parse("sqlite://").is_err());
+    assert!(Backend::parse("postgres").is_err());
+}
@@ -0,0 +1,177 @@
+use std::{
+    env, fs,
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+};
+
+use libs::{
+    crypto::{
+        tls::{self, ServerTrust},
+        transport::ClientHandshake,
+    },
+    packet::{Packet, PacketErrorKind, PacketType},
+    packet_manager::Channel,
+};
+use rcgen::{BasicConstraints, Certificate, CertificateParams, DnType, IsCa, SanType};
+use server::{database::Backend, Config, Listener, TlsConfig};
+use uuid::Uuid;
+
+mod common;
+
+use common::{open, register};
+
+/// A throwaway certificate authority and a server certificate it signed for
+/// `localhost` and 127.0.0.1, written to a temporary directory.
+struct TestCa {
+    dir: PathBuf,
+}
+
+impl TestCa {
+    fn new() -> Self {
+        let dir = env::temp_dir().join(format!("secure_chat_tls_{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut params = CertificateParams::new(Vec::new());
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "secure_chat test CA");
+        let ca = Certificate::from_params(params).unwrap();
+
+        let mut params = CertificateParams::new(vec![String::from("localhost")]);
+        params
+            .subject_alt_names
+            .push(SanType::IpAddress("127.0.0.1".parse().unwrap()));
+        let cert = Certificate::from_params(params).unwrap();
+
+        fs::write(dir.join("ca.pem"), ca.serialize_pem().unwrap()).unwrap();
+        fs::write(
+            dir.join("server.pem"),
+            cert.serialize_pem_with_signer(&ca).unwrap(),
+        )
+        .unwrap();
+        fs::write(dir.join("server.key"), cert.serialize_private_key_pem()).unwrap();
+
+        Self { dir }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    fn tls(&self) -> TlsConfig {
+        TlsConfig {
+            cert: self.path("server.pem"),
+            key: self.path("server.key"),
+        }
+    }
+}
+
+impl Drop for TestCa {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn start(ca: &TestCa) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+
+    let config = Config {
+        addr: addr.clone(),
+        storage: Backend::Memory,
+        tls: Some(ca.tls()),
+        ..Config::default()
+    };
+
+    common::spawn(vec![Listener::from(listener)], config);
+
+    addr
+}
+
+#[test]
+fn clients_trusting_the_ca_can_register() {
+    let ca = TestCa::new();
+    let addr = start(&ca);
+
+    let trust = ServerTrust::authority(&ca.path("ca.pem")).unwrap();
+    let stream = tls::connect(trust, "localhost", TcpStream::connect(&addr).unwrap()).unwrap();
+
+    register(&mut open(stream), "alice");
+}
+
+#[test]
+fn first_use_fingerprint_pins_the_server() {
+    let ca = TestCa::new();
+    let addr = start(&ca);
+
+    let stream = tls::connect(
+        ServerTrust::FirstUse,
+        "127.0.0.1",
+        TcpStream::connect(&addr).unwrap(),
+    )
+    .unwrap();
+
+    let pin = stream.peer_fingerprint().unwrap();
+    let expected = tls::load_certs(&ca.path("server.pem")).unwrap();
+    assert_eq!(pin, tls::fingerprint(&expected[0].0));
+
+    let stream = tls::connect(
+        ServerTrust::Pinned(pin),
+        "127.0.0.1",
+        TcpStream::connect(&addr).unwrap(),
+    )
+    .unwrap();
+
+    register(&mut open(stream), "bob");
+}
+
+#[test]
+fn mismatched_pin_and_unknown_ca_are_rejected() {
+    let ca = TestCa::new();
+    let addr = start(&ca);
+
+    let err = match tls::connect(
+        ServerTrust::Pinned(tls::fingerprint(b"another server")),
+        "127.0.0.1",
+        TcpStream::connect(&addr).unwrap(),
+    ) {
+        Err(err) => err,
+        Ok(_) => panic!("pinned fingerprint was ignored"),
+    };
+    assert_eq!(err.kind, PacketErrorKind::Tls);
+    assert!(err.message.contains("pinned"), "{}", err.message);
+
+    let other = TestCa::new();
+    let trust = ServerTrust::authority(&other.path("ca.pem")).unwrap();
+    assert!(tls::connect(trust, "localhost", TcpStream::connect(&addr).unwrap()).is_err());
+}
+
+#[test]
+fn plaintext_clients_cannot_talk_to_a_tls_listener() {
+    let ca = TestCa::new();
+    let addr = start(&ca);
+
+    let mut channel = Channel::new(TcpStream::connect(&addr).unwrap());
+    let (_, hello) = ClientHandshake::start();
+    let _ = channel.send(Packet::new(PacketType::PubKey, hello).to().unwrap());
+
+    assert!(channel.recv().is_err());
+}
+
+#[test]
+fn missing_certificate_fails_startup() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+    let config = Config {
+        storage: Backend::Memory,
+        tls: Some(TlsConfig {
+            cert: PathBuf::from("/nonexistent/server.pem"),
+            key: PathBuf::from("/nonexistent/server.key"),
+        }),
+        ..Config::default()
+    };
+
+    let err = server::serve(listener, config, server::Shutdown::new()).unwrap_err();
+    assert!(err.contains("/nonexistent/server.pem"), "{}", err);
+}
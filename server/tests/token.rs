@@ -0,0 +1,58 @@
+use server::token::TokenIssuer;
+
+#[test]
+fn issued_token_verifies() {
+    let issuer = TokenIssuer::new([1; 32], 60);
+
+    let (claims, token) = issuer.issue_at(String::from("alice"), 1000).unwrap();
+    assert_eq!(token.get_expires_at(), 1060);
+
+    let verified = issuer.verify_at(&token.get_token(), 1030).unwrap();
+    assert_eq!(verified.id, claims.id);
+    assert_eq!(verified.username, "alice");
+}
+
+#[test]
+fn tokens_are_unique() {
+    let issuer = TokenIssuer::new([1; 32], 60);
+
+    let (first, _) = issuer.issue_at(String::from("alice"), 1000).unwrap();
+    let (second, _) = issuer.issue_at(String::from("alice"), 1000).unwrap();
+
+    assert_ne!(first.id, second.id);
+}
+
+#[test]
+fn expired_token_is_rejected() {
+    let issuer = TokenIssuer::new([1; 32], 60);
+
+    let (_, token) = issuer.issue_at(String::from("alice"), 1000).unwrap();
+
+    assert!(issuer.verify_at(&token.get_token(), 1060).is_err());
+}
+
+#[test]
+fn token_from_another_key_is_rejected() {
+    let issuer = TokenIssuer::new([1; 32], 60);
+    let other = TokenIssuer::new([2; 32], 60);
+
+    let (_, token) = other.issue_at(String::from("alice"), 1000).unwrap();
+
+    assert!(issuer.verify_at(&token.get_token(), 1000).is_err());
+}
+
+#[test]
+fn tampered_claims_are_rejected() {
+    let issuer = TokenIssuer::new([1; 32], 60);
+
+    let (_, token) = issuer.issue_at(String::from("alice"), 1000).unwrap();
+    let (_, forged) = issuer.issue_at(String::from("mallory"), 1000).unwrap();
+
+    let signature = token.get_token().split_once('.').unwrap().1.to_string();
+    let payload = forged.get_token().split_once('.').unwrap().0.to_string();
+
+    assert!(issuer
+        .verify_at(&format!("{}.{}", payload, signature), 1000)
+        .is_err());
+    assert!(issuer.verify_at("garbage", 1000).is_err());
+}
@@ -0,0 +1,232 @@
+use std::{
+    net::{TcpListener, TcpStream},
+    thread::JoinHandle,
+};
+
+use libs::{
+    crypto::transport::ClientHandshake,
+    packet::{Codec, DataPacket, Packet, PacketType},
+    packet_manager::Encryption,
+    BaseModels, PacketModels,
+};
+use serde::{Deserialize, Serialize};
+use server::{database::Backend, Config, Listener, Shutdown};
+use tungstenite::{Message, WebSocket};
+
+mod common;
+
+use common::{connect, parse, register, request, signup};
+
+struct Server {
+    tcp: String,
+    websocket: String,
+    shutdown: Shutdown,
+    handle: JoinHandle<Result<(), String>>,
+}
+
+/// Serves plain TCP and WebSocket clients from the same storage.
+fn start() -> Server {
+    let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+    let websocket = TcpListener::bind("127.0.0.1:0").unwrap();
+
+    let tcp_addr = tcp.local_addr().unwrap().to_string();
+    let websocket_addr = websocket.local_addr().unwrap().to_string();
+
+    let config = Config {
+        storage: Backend::Memory,
+        ..Config::default()
+    };
+
+    let (shutdown, handle) = common::spawn(
+        vec![Listener::from(tcp), Listener::websocket(websocket)],
+        config,
+    );
+
+    Server {
+        tcp: tcp_addr,
+        websocket: websocket_addr,
+        shutdown,
+        handle,
+    }
+}
+
+/// A client speaking one `DataPacket` per frame: JSON as text, MessagePack
+/// as binary.
+struct WsClient {
+    socket: WebSocket<TcpStream>,
+    encryption: Encryption,
+    codec: Codec,
+}
+
+impl WsClient {
+    fn connect(addr: &str) -> Self {
+        Self::connect_with(addr, Codec::Json)
+    }
+
+    fn connect_with(addr: &str, codec: Codec) -> Self {
+        let stream = TcpStream::connect(addr).unwrap();
+        let (socket, _) = tungstenite::client(format!("ws://{}/", addr), stream).unwrap();
+
+        Self {
+            socket,
+            encryption: Encryption::new(),
+            codec,
+        }
+    }
+
+    fn send(&mut self, packet: DataPacket) {
+        let packet = packet.transcode(self.codec).unwrap();
+        let packet = self.encryption.seal(packet).unwrap();
+
+        let message = match self.codec {
+            Codec::Json => Message::Text(packet.buf().unwrap()),
+            Codec::MessagePack => Message::Binary(self.codec.encode(&packet).unwrap()),
+        };
+        self.socket.send(message).unwrap();
+    }
+
+    fn recv(&mut self) -> DataPacket {
+        loop {
+            let packet = match self.socket.read().unwrap() {
+                Message::Text(text) => Codec::Json.decode(text.as_bytes()).unwrap(),
+                Message::Binary(buf) => Codec::MessagePack.decode(&buf).unwrap(),
+                Message::Ping(_) | Message::Pong(_) => continue,
+                other => panic!("unexpected message {:?}", other),
+            };
+
+            assert_eq!(packet.get_codec(), self.codec);
+            return self.encryption.open(packet).unwrap();
+        }
+    }
+
+    fn exchange_keys(&mut self) {
+        let (handshake, hello) = ClientHandshake::start();
+        self.send(Packet::new(PacketType::PubKey, hello).to().unwrap());
+
+        let reply = self.recv();
+        let reply: Packet<PacketModels::KeyExchange> = Packet::parse(&reply, "PubKey")
+            .unwrap_or_else(|packet| panic!("{}", packet.get_data()));
+
+        self.encryption
+            .set_cipher(handshake.finish(reply.get().1, None).unwrap());
+    }
+
+    fn request<T>(&mut self, p_type: PacketType, body: T) -> DataPacket
+    where
+        T: Serialize + for<'a> Deserialize<'a>,
+    {
+        self.send(Packet::new(p_type, body).to().unwrap());
+        self.recv()
+    }
+}
+
+fn ws_register(client: &mut WsClient, username: &str) -> BaseModels::User {
+    let (me, key) = signup(
+        |packet| {
+            client.send(packet);
+            client.recv()
+        },
+        username,
+    );
+
+    client.encryption.rekey(&key).unwrap();
+    me
+}
+
+#[test]
+fn websocket_requests_require_key_exchange() {
+    let server = start();
+
+    let mut client = WsClient::connect(&server.websocket);
+    let reply = client.request(PacketType::GetChats, PacketModels::Empty {});
+
+    assert!(matches!(reply.get_type(), PacketType::Error));
+    assert_eq!(reply.get_data(), "Key Exchange Required");
+}
+
+#[test]
+fn websocket_binary_frames_speak_message_pack() {
+    let server = start();
+
+    let mut client = WsClient::connect_with(&server.websocket, Codec::MessagePack);
+    client.exchange_keys();
+
+    let me = ws_register(&mut client, "carol");
+    assert_eq!(me.get_username(), "carol");
+
+    let reply = client.request(PacketType::GetChats, PacketModels::Empty {});
+    assert_eq!(reply.get_codec(), Codec::MessagePack);
+
+    let chats: PacketModels::Chats = parse(&reply);
+    assert!(chats.get_groups().is_empty());
+}
+
+#[test]
+fn tcp_and_websocket_clients_share_groups() {
+    let server = start();
+
+    let mut alice = connect(&server.tcp);
+    let me = register(&mut alice, "alice");
+    assert_eq!(me.get_username(), "alice");
+
+    let mut bob = WsClient::connect(&server.websocket);
+    bob.exchange_keys();
+    let me = ws_register(&mut bob, "bob");
+    assert_eq!(me.get_username(), "bob");
+
+    let group: BaseModels::Group = parse(&request(
+        &mut alice,
+        PacketType::CreateGroup,
+        BaseModels::Group::new(String::from("bridge")),
+    ));
+    let group: BaseModels::Group = parse(&request(
+        &mut alice,
+        PacketType::AddUser,
+        BaseModels::Member::new(
+            group,
+            BaseModels::User::simple(String::from("bob"), String::new()),
+        ),
+    ));
+
+    let reply = request(&mut alice, PacketType::Listen, PacketModels::Empty {});
+    assert!(matches!(reply.get_type(), PacketType::Ok));
+
+    let reply = bob.request(PacketType::Listen, PacketModels::Empty {});
+    assert!(matches!(reply.get_type(), PacketType::Ok));
+
+    let member = |username: &str| {
+        BaseModels::Member::new(
+            group.clone(),
+            BaseModels::User::simple(String::from(username), String::new()),
+        )
+    };
+
+    let sent: BaseModels::Message = parse(&bob.request(
+        PacketType::CreateMessage,
+        BaseModels::Message::new(member("bob"), String::from("from the browser")),
+    ));
+
+    let event = alice.recv().unwrap();
+    assert!(event.is_event());
+    let received: BaseModels::Message = parse(&event);
+    assert_eq!(received.get_id(), sent.get_id());
+    assert_eq!(received.get_body(), "from the browser");
+
+    // bob is a member too, so his own message comes back as an event
+    assert!(bob.recv().is_event());
+
+    let sent: BaseModels::Message = parse(&request(
+        &mut alice,
+        PacketType::CreateMessage,
+        BaseModels::Message::new(member("alice"), String::from("from the terminal")),
+    ));
+
+    let event = bob.recv();
+    assert!(event.is_event());
+    let received: BaseModels::Message = parse(&event);
+    assert_eq!(received.get_id(), sent.get_id());
+    assert_eq!(received.get_member().get_user().get_username(), "alice");
+
+    server.shutdown.trigger();
+    assert_eq!(server.handle.join().unwrap(), Ok(()));
+}